@@ -1,12 +1,19 @@
-use crate::config::{
-    self, Global, Jira, PDfDimension, Printer, Trello,
+use crate::{
+    config::{
+        self, Duplex, Github, Gitlab, Global, Jira, JiraAuth,
+        LabelLanguage, LabelPrinter, Local, PDfDimension,
+        Printer, ReceiptPrinter, Sheet, Trello, TrelloSubtitle,
+    },
+    layout::Color,
 };
 use clap::{
     app_from_crate, crate_authors, crate_description, crate_name,
     crate_version, Arg,
 };
 use failure::ResultExt;
-use std::process::exit;
+use std::{
+    collections::BTreeMap, convert::TryFrom, process::exit,
+};
 
 const GENERATE_CONFIG: &str = "generate-example-config";
 const PRINT_CONFIG: &str = "print-current-config";
@@ -15,6 +22,12 @@ const POLL_SECS: &str = "poll";
 const POLL_SECS_ENV: &str = "POLL_SECS";
 const OUT_DIR: &str = "out-dir";
 const OUT_DIR_ENV: &str = "OUT_DIR";
+const JOURNAL: &str = "journal";
+const JOURNAL_ENV: &str = "JOURNAL";
+const HTTP_TIMEOUT: &str = "http-timeout";
+const HTTP_TIMEOUT_ENV: &str = "HTTP_TIMEOUT";
+const HTTP_RETRIES: &str = "http-retries";
+const HTTP_RETRIES_ENV: &str = "HTTP_RETRIES";
 
 const PDF_HEIGHT: &str = "pdf-height";
 const PDF_HEIGHT_ENV: &str = "PDF_HEIGHT";
@@ -34,6 +47,22 @@ const PDF_QRCODE_SEPERATOR_MARGIN_ENV: &str =
     "PDF_QRCODE_SEPERATOR_MARGIN";
 const PDF_SUBTITLE_SIZE: &str = "pdf-subtitle-size";
 const PDF_SUBTITLE_SIZE_ENV: &str = "PDF_SUBTITLE_SIZE";
+const PDF_FIELD_LINES: &str = "pdf-field-lines";
+const PDF_FIELD_LINES_ENV: &str = "PDF_FIELD_LINES";
+const PDF_LAYOUT: &str = "pdf-layout";
+const PDF_LAYOUT_ENV: &str = "PDF_LAYOUT";
+const PDF_FONT: &str = "pdf-font";
+const PDF_FONT_ENV: &str = "PDF_FONT";
+const PDF_TITLE_MIN_SIZE: &str = "pdf-title-min-size";
+const PDF_TITLE_MIN_SIZE_ENV: &str = "PDF_TITLE_MIN_SIZE";
+const PDF_TITLE_MAX_SIZE: &str = "pdf-title-max-size";
+const PDF_TITLE_MAX_SIZE_ENV: &str = "PDF_TITLE_MAX_SIZE";
+const PDF_BACK_LINES: &str = "pdf-back-lines";
+const PDF_BACK_LINES_ENV: &str = "PDF_BACK_LINES";
+const PDF_BACK_LAYOUT: &str = "pdf-back-layout";
+const PDF_BACK_LAYOUT_ENV: &str = "PDF_BACK_LAYOUT";
+const PDF_COLORS: &str = "pdf-colors";
+const PDF_COLORS_ENV: &str = "PDF_COLORS";
 const PDF_ARGUMENTS: &[&str] = &[
     PDF_HEIGHT,
     PDF_WIDTH,
@@ -53,6 +82,27 @@ const PRINTER_NUMBER_OF_COPIES_ENV: &str =
     "PRINTER_NUMBER_OF_COPIES";
 const PRINTER_NAME: &str = "printer-name";
 const PRINTER_NAME_ENV: &str = "PRINTER_NAME";
+const PRINTER_URI: &str = "printer-uri";
+const PRINTER_URI_ENV: &str = "PRINTER_URI";
+const PRINTER_JOB_TIMEOUT: &str = "printer-job-timeout";
+const PRINTER_JOB_TIMEOUT_ENV: &str = "PRINTER_JOB_TIMEOUT";
+const PRINTER_DUPLEX: &str = "printer-duplex";
+const PRINTER_DUPLEX_ENV: &str = "PRINTER_DUPLEX";
+const PRINTER_DUPLEX_POSSIBLE: &[&str; 2] =
+    &["long-edge", "short-edge"];
+const PRINTER_LABEL_LANGUAGE: &str = "printer-label-language";
+const PRINTER_LABEL_LANGUAGE_ENV: &str = "PRINTER_LABEL_LANGUAGE";
+const PRINTER_LABEL_LANGUAGE_POSSIBLE: &[&str; 2] =
+    &["zpl", "brother-ql"];
+const PRINTER_LABEL_OUTPUT: &str = "printer-label-output";
+const PRINTER_LABEL_OUTPUT_ENV: &str = "PRINTER_LABEL_OUTPUT";
+const PRINTER_LABEL_DPI: &str = "printer-label-dpi";
+const PRINTER_LABEL_DPI_ENV: &str = "PRINTER_LABEL_DPI";
+const PRINTER_RECEIPT_OUTPUT: &str = "printer-receipt-output";
+const PRINTER_RECEIPT_OUTPUT_ENV: &str = "PRINTER_RECEIPT_OUTPUT";
+const PRINTER_RECEIPT_COLUMNS: &str = "printer-receipt-columns";
+const PRINTER_RECEIPT_COLUMNS_ENV: &str =
+    "PRINTER_RECEIPT_COLUMNS";
 const PRINTER_ARGUMENTS: &[&str] = &[
     PRINTER_MEDIA,
     PRINTER_ORIENTATION,
@@ -60,6 +110,21 @@ const PRINTER_ARGUMENTS: &[&str] = &[
     PRINTER_NAME,
 ];
 
+const SHEET_SIZE: &str = "sheet-size";
+const SHEET_SIZE_ENV: &str = "SHEET_SIZE";
+const SHEET_COLUMNS: &str = "sheet-columns";
+const SHEET_COLUMNS_ENV: &str = "SHEET_COLUMNS";
+const SHEET_ROWS: &str = "sheet-rows";
+const SHEET_ROWS_ENV: &str = "SHEET_ROWS";
+const SHEET_MARGIN: &str = "sheet-margin";
+const SHEET_MARGIN_ENV: &str = "SHEET_MARGIN";
+const SHEET_GUTTER: &str = "sheet-gutter";
+const SHEET_GUTTER_ENV: &str = "SHEET_GUTTER";
+const SHEET_CUT_MARKS: &str = "sheet-cut-marks";
+const SHEET_CUT_MARKS_ENV: &str = "SHEET_CUT_MARKS";
+const SHEET_FOLD_LINE: &str = "sheet-fold-line";
+const SHEET_FOLD_LINE_ENV: &str = "SHEET_FOLD_LINE";
+
 const TRELLO_APP_KEY: &str = "trello-app-key";
 const TRELLO_APP_KEY_ENV: &str = "TRELLO_APP_KEY";
 const TRELLO_TOKEN: &str = "trello-token";
@@ -68,30 +133,83 @@ const TRELLO_PRINT_LABEL: &str = "trello-print-label";
 const TRELLO_PRINT_LABEL_ENV: &str = "TRELLO_PRINT_LABEL";
 const TRELLO_LIMIT_TO_BOARDS: &str = "trello-limit-to-boards";
 const TRELLO_LIMIT_TO_BOARDS_ENV: &str = "TRELLO_LIMIT_TO_BOARDS";
+const TRELLO_SUBTITLE: &str = "trello-subtitle";
+const TRELLO_SUBTITLE_ENV: &str = "TRELLO_SUBTITLE";
+const TRELLO_SUBTITLE_POSSIBLE: &[&str; 2] = &["id", "list"];
 const TRELLO_ARGUMENTS: &[&str] =
     &[TRELLO_APP_KEY, TRELLO_TOKEN, TRELLO_PRINT_LABEL];
 
 const JIRA_HOST: &str = "jira-host";
 const JIRA_HOST_ENV: &str = "JIRA_HOST";
+const JIRA_BASE_URL: &str = "jira-base-url";
+const JIRA_BASE_URL_ENV: &str = "JIRA_BASE_URL";
 const JIRA_USER: &str = "jira-user";
 const JIRA_USER_ENV: &str = "JIRA_USER";
 const JIRA_TOKEN: &str = "jira-token";
 const JIRA_TOKEN_ENV: &str = "JIRA_TOKEN";
+const JIRA_AUTH: &str = "jira-auth";
+const JIRA_AUTH_ENV: &str = "JIRA_AUTH";
+const JIRA_AUTH_POSSIBLE: &[&str; 2] = &["basic", "bearer"];
+const JIRA_API_VERSION: &str = "jira-api-version";
+const JIRA_API_VERSION_ENV: &str = "JIRA_API_VERSION";
 const JIRA_PRINT_LABEL: &str = "jira-print-label";
 const JIRA_PRINT_LABEL_ENV: &str = "JIRA_PRINT_LABEL";
 const JIRA_LIMIT_TO_TYPES: &str = "jira-limit-to-types";
 const JIRA_LIMIT_TO_TYPES_ENV: &str = "JIRA_LIMIT_TO_TYPES";
 const JIRA_LIMIT_TO_PROJECTS: &str = "jira-limit-to-projects";
 const JIRA_LIMIT_TO_PROJECTS_ENV: &str = "JIRA_LIMIT_TO_PROJECTS";
-const JIRA_ARGUMENTS: &[&str] =
-    &[JIRA_HOST, JIRA_USER, JIRA_TOKEN, JIRA_PRINT_LABEL];
+const JIRA_JQL: &str = "jira-jql";
+const JIRA_JQL_ENV: &str = "JIRA_JQL";
+const JIRA_FIELDS: &str = "jira-fields";
+const JIRA_FIELDS_ENV: &str = "JIRA_FIELDS";
+const JIRA_ARGUMENTS: &[&str] = &[JIRA_TOKEN, JIRA_PRINT_LABEL];
+
+const GITHUB_TOKEN: &str = "github-token";
+const GITHUB_TOKEN_ENV: &str = "GITHUB_TOKEN";
+const GITHUB_PRINT_LABEL: &str = "github-print-label";
+const GITHUB_PRINT_LABEL_ENV: &str = "GITHUB_PRINT_LABEL";
+const GITHUB_LIMIT_TO_REPOSITORIES: &str =
+    "github-limit-to-repositories";
+const GITHUB_LIMIT_TO_REPOSITORIES_ENV: &str =
+    "GITHUB_LIMIT_TO_REPOSITORIES";
+const GITHUB_API_URL: &str = "github-api-url";
+const GITHUB_API_URL_ENV: &str = "GITHUB_API_URL";
+const GITHUB_ARGUMENTS: &[&str] =
+    &[GITHUB_TOKEN, GITHUB_PRINT_LABEL];
+
+const GITLAB_TOKEN: &str = "gitlab-token";
+const GITLAB_TOKEN_ENV: &str = "GITLAB_TOKEN";
+const GITLAB_PRINT_LABEL: &str = "gitlab-print-label";
+const GITLAB_PRINT_LABEL_ENV: &str = "GITLAB_PRINT_LABEL";
+const GITLAB_LIMIT_TO_PROJECTS: &str = "gitlab-limit-to-projects";
+const GITLAB_LIMIT_TO_PROJECTS_ENV: &str =
+    "GITLAB_LIMIT_TO_PROJECTS";
+const GITLAB_LIMIT_TO_GROUPS: &str = "gitlab-limit-to-groups";
+const GITLAB_LIMIT_TO_GROUPS_ENV: &str = "GITLAB_LIMIT_TO_GROUPS";
+const GITLAB_MERGE_REQUESTS: &str = "gitlab-merge-requests";
+const GITLAB_MERGE_REQUESTS_ENV: &str = "GITLAB_MERGE_REQUESTS";
+const GITLAB_URL: &str = "gitlab-url";
+const GITLAB_URL_ENV: &str = "GITLAB_URL";
+const GITLAB_ARGUMENTS: &[&str] =
+    &[GITLAB_TOKEN, GITLAB_PRINT_LABEL];
+
+const LOCAL_PATH: &str = "local-path";
+const LOCAL_PATH_ENV: &str = "LOCAL_PATH";
+const LOCAL_FORMAT: &str = "local-format";
+const LOCAL_FORMAT_ENV: &str = "LOCAL_FORMAT";
+const LOCAL_FORMAT_POSSIBLE: &[&str; 5] =
+    &["csv", "json", "yaml", "yml", "toml"];
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Arguments {
     pdf: Option<PDfDimension>,
     printer: Option<Printer>,
+    sheet: Option<Sheet>,
     trello: Option<Trello>,
     jira: Option<Jira>,
+    github: Option<Github>,
+    gitlab: Option<Gitlab>,
+    local: Option<Local>,
     global: Option<Global>,
     print: Option<String>,
 }
@@ -107,12 +225,24 @@ impl Arguments {
         if let Some(printer) = self.printer {
             config.printer = Some(printer);
         }
+        if let Some(sheet) = self.sheet {
+            config.sheet = Some(sheet);
+        }
         if let Some(trello) = self.trello {
             config.trello = Some(trello);
         }
         if let Some(jira) = self.jira {
             config.jira = Some(jira);
         }
+        if let Some(github) = self.github {
+            config.github = Some(github);
+        }
+        if let Some(gitlab) = self.gitlab {
+            config.gitlab = Some(gitlab);
+        }
+        if let Some(local) = self.local {
+            config.local = Some(local);
+        }
         match (config.global.as_mut(), self.global) {
             (Some(c_global), Some(a_global)) => {
                 if let Some(poll) = a_global.poll {
@@ -121,12 +251,21 @@ impl Arguments {
                 if let Some(out_dir) = a_global.out_dir {
                     c_global.out_dir = Some(out_dir);
                 }
+                if let Some(journal) = a_global.journal {
+                    c_global.journal = Some(journal);
+                }
+                if let Some(timeout) = a_global.http_timeout {
+                    c_global.http_timeout = Some(timeout);
+                }
+                if let Some(retries) = a_global.http_retries {
+                    c_global.http_retries = Some(retries);
+                }
             }
             (None, global) => {
                 config.global = global;
             }
             _ => {}
-        };
+        }
         if let Some(format) = self.print.as_ref() {
             let config_text = stringify_config(format, config)?;
             println!("{}", config_text);
@@ -136,19 +275,6 @@ impl Arguments {
     }
 }
 
-impl Default for Arguments {
-    fn default() -> Self {
-        Self {
-            pdf: None,
-            printer: None,
-            trello: None,
-            jira: None,
-            global: None,
-            print: None,
-        }
-    }
-}
-
 #[allow(clippy::too_many_lines)]
 pub fn handle() -> crate::Result<Arguments> {
     let mut arguments = Arguments::default();
@@ -157,6 +283,8 @@ pub fn handle() -> crate::Result<Arguments> {
         let config = create_default_config();
         let config_text = stringify_config(config_type, &config)?;
         println!("{}", config_text);
+        // nothing but the generated configuration is asked for
+        #[allow(clippy::exit)]
         exit(0);
     }
     arguments.print =
@@ -164,139 +292,325 @@ pub fn handle() -> crate::Result<Arguments> {
     let mut global = Global {
         poll: None,
         out_dir: None,
+        journal: None,
+        http_timeout: None,
+        http_retries: None,
     };
     global.poll =
         matches.value_of(POLL_SECS).and_then(|s| s.parse().ok());
     global.out_dir =
         matches.value_of(OUT_DIR).and_then(|s| s.parse().ok());
+    global.journal = matches.value_of(JOURNAL).map(|s| s.into());
+    global.http_timeout = matches
+        .value_of(HTTP_TIMEOUT)
+        .map(str::parse::<u64>)
+        .transpose()
+        .with_context(|_| {
+            format!("{} must be numeric", HTTP_TIMEOUT)
+        })?;
+    global.http_retries = matches
+        .value_of(HTTP_RETRIES)
+        .map(str::parse::<u32>)
+        .transpose()
+        .with_context(|_| {
+            format!("{} must be numeric", HTTP_RETRIES)
+        })?;
     arguments.global = Some(global);
 
     if matches.is_present(PDF_HEIGHT) {
         arguments.pdf = Some(PDfDimension {
-            height: matches
-                .value_of(PDF_HEIGHT)
-                .expect("CLAP REQUIRES")
+            height: required(&matches, PDF_HEIGHT)?
                 .parse::<f32>()
                 .with_context(|_| {
                     format!("{} must be numeric", PDF_HEIGHT)
                 })?,
-            width: matches
-                .value_of(PDF_WIDTH)
-                .expect("CLAP REQUIRES")
+            width: required(&matches, PDF_WIDTH)?
                 .parse::<f32>()
                 .with_context(|_| {
                     format!("{} must be numeric", PDF_WIDTH)
                 })?,
-            margin: matches
-                .value_of(PDF_MARGIN)
-                .expect("CLAP REQUIRES")
+            margin: required(&matches, PDF_MARGIN)?
                 .parse::<f32>()
                 .with_context(|_| {
                     format!("{} must be numeric", PDF_MARGIN)
                 })?,
-            title_lines: matches
-                .value_of(PDF_TITLE_LINES)
-                .expect("CLAP REQUIRES")
+            title_lines: required(&matches, PDF_TITLE_LINES)?
                 .parse::<u32>()
                 .with_context(|_| {
                     format!("{} must be numeric", PDF_TITLE_LINES)
                 })?,
-            title_seperator_margin: matches
-                .value_of(PDF_TITLE_SEPERATOR_MARGIN)
-                .expect("CLAP REQUIRES")
+            title_seperator_margin: required(
+                &matches,
+                PDF_TITLE_SEPERATOR_MARGIN,
+            )?
+            .parse::<f32>()
+            .with_context(|_| {
+                format!(
+                    "{} must be numeric",
+                    PDF_TITLE_SEPERATOR_MARGIN
+                )
+            })?,
+            qrcode_seperator_margin: required(
+                &matches,
+                PDF_QRCODE_SEPERATOR_MARGIN,
+            )?
+            .parse::<f32>()
+            .with_context(|_| {
+                format!(
+                    "{} must be numeric",
+                    PDF_QRCODE_SEPERATOR_MARGIN
+                )
+            })?,
+            subtitle_size: required(&matches, PDF_SUBTITLE_SIZE)?
                 .parse::<f32>()
                 .with_context(|_| {
                     format!(
                         "{} must be numeric",
-                        PDF_TITLE_SEPERATOR_MARGIN
+                        PDF_SUBTITLE_SIZE
                     )
                 })?,
-            qrcode_seperator_margin: matches
-                .value_of(PDF_QRCODE_SEPERATOR_MARGIN)
-                .expect("CLAP REQUIRES")
-                .parse::<f32>()
+            field_lines: matches
+                .values_of(PDF_FIELD_LINES)
+                .map_or_else(Vec::new, |i| {
+                    i.map(|s| s.into()).collect()
+                }),
+            layout: matches
+                .value_of(PDF_LAYOUT)
+                .map(|s| s.into()),
+            font: matches.value_of(PDF_FONT).map(|s| s.into()),
+            title_min_size: matches
+                .value_of(PDF_TITLE_MIN_SIZE)
+                .map(str::parse::<f32>)
+                .transpose()
                 .with_context(|_| {
                     format!(
                         "{} must be numeric",
-                        PDF_QRCODE_SEPERATOR_MARGIN
+                        PDF_TITLE_MIN_SIZE
                     )
                 })?,
-            subtitle_size: matches
-                .value_of(PDF_SUBTITLE_SIZE)
-                .expect("CLAP REQUIRES")
-                .parse::<f32>()
+            title_max_size: matches
+                .value_of(PDF_TITLE_MAX_SIZE)
+                .map(str::parse::<f32>)
+                .transpose()
                 .with_context(|_| {
                     format!(
                         "{} must be numeric",
-                        PDF_SUBTITLE_SIZE
+                        PDF_TITLE_MAX_SIZE
                     )
                 })?,
+            back_lines: matches
+                .values_of(PDF_BACK_LINES)
+                .map_or_else(Vec::new, |i| {
+                    i.map(|s| s.into()).collect()
+                }),
+            back_layout: matches
+                .value_of(PDF_BACK_LAYOUT)
+                .map(|s| s.into()),
+            colors: matches
+                .values_of(PDF_COLORS)
+                .into_iter()
+                .flatten()
+                .map(|s| {
+                    let mut parts = s.splitn(2, '=');
+                    let value = parts.next().unwrap_or_default();
+                    let color = parts.next().unwrap_or_default();
+                    Color::try_from(color.to_string())
+                        .map(|color| (value.to_string(), color))
+                        .map_err(failure::err_msg)
+                        .with_context(|_| {
+                            format!(
+                                "{} must look like value=#rrggbb",
+                                PDF_COLORS
+                            )
+                        })
+                })
+                .collect::<Result<_, _>>()?,
         })
     }
     if matches.is_present(PRINTER_MEDIA) {
+        let language = matches.value_of(PRINTER_LABEL_LANGUAGE);
+        let label = match language {
+            Some(language) => Some(LabelPrinter {
+                language: if language == "zpl" {
+                    LabelLanguage::Zpl
+                } else {
+                    LabelLanguage::BrotherQl
+                },
+                output: required(&matches, PRINTER_LABEL_OUTPUT)?
+                    .into(),
+                dpi: matches
+                    .value_of(PRINTER_LABEL_DPI)
+                    .map(str::parse::<u32>)
+                    .transpose()
+                    .with_context(|_| {
+                        format!(
+                            "{} must be numeric",
+                            PRINTER_LABEL_DPI
+                        )
+                    })?,
+            }),
+            None => None,
+        };
+        let receipt =
+            match matches.value_of(PRINTER_RECEIPT_OUTPUT) {
+                Some(output) => Some(ReceiptPrinter {
+                    output: output.into(),
+                    columns: matches
+                        .value_of(PRINTER_RECEIPT_COLUMNS)
+                        .map(str::parse::<usize>)
+                        .transpose()
+                        .with_context(|_| {
+                            format!(
+                                "{} must be numeric",
+                                PRINTER_RECEIPT_COLUMNS
+                            )
+                        })?,
+                }),
+                None => None,
+            };
         arguments.printer = Some(Printer {
-            media: matches
-                .value_of(PRINTER_MEDIA)
-                .expect("CLAP REQUIRES")
+            media: required(&matches, PRINTER_MEDIA)?.into(),
+            orientation: required(&matches, PRINTER_ORIENTATION)?
                 .into(),
-            orientation: matches
-                .value_of(PRINTER_ORIENTATION)
-                .expect("CLAP REQUIRES")
-                .into(),
-            number_of_copies: matches
-                .value_of(PRINTER_NUMBER_OF_COPIES)
-                .expect("CLAP REQUIRES")
-                .parse::<u16>()
+            number_of_copies: required(
+                &matches,
+                PRINTER_NUMBER_OF_COPIES,
+            )?
+            .parse::<u16>()
+            .with_context(|_| {
+                format!(
+                    "{} must be numeric",
+                    PRINTER_NUMBER_OF_COPIES
+                )
+            })?,
+            name: required(&matches, PRINTER_NAME)?.into(),
+            uri: matches.value_of(PRINTER_URI).map(|s| s.into()),
+            job_timeout: matches
+                .value_of(PRINTER_JOB_TIMEOUT)
+                .map(str::parse::<u64>)
+                .transpose()
                 .with_context(|_| {
                     format!(
                         "{} must be numeric",
-                        PRINTER_NUMBER_OF_COPIES
+                        PRINTER_JOB_TIMEOUT
+                    )
+                })?,
+            duplex: match matches.value_of(PRINTER_DUPLEX) {
+                Some("long-edge") => Some(Duplex::LongEdge),
+                Some("short-edge") => Some(Duplex::ShortEdge),
+                _ => None,
+            },
+            label,
+            receipt,
+        })
+    }
+    if matches.is_present(SHEET_SIZE) {
+        let defaults = Sheet::default();
+        arguments.sheet = Some(Sheet {
+            size: required(&matches, SHEET_SIZE)?.into(),
+            columns: matches
+                .value_of(SHEET_COLUMNS)
+                .map(str::parse::<u32>)
+                .transpose()
+                .with_context(|_| {
+                    format!("{} must be numeric", SHEET_COLUMNS)
+                })?,
+            rows: matches
+                .value_of(SHEET_ROWS)
+                .map(str::parse::<u32>)
+                .transpose()
+                .with_context(|_| {
+                    format!("{} must be numeric", SHEET_ROWS)
+                })?,
+            margin: matches
+                .value_of(SHEET_MARGIN)
+                .map_or(Ok(defaults.margin), str::parse::<f32>)
+                .with_context(|_| {
+                    format!("{} must be numeric", SHEET_MARGIN)
+                })?,
+            gutter: matches
+                .value_of(SHEET_GUTTER)
+                .map_or(Ok(defaults.gutter), str::parse::<f32>)
+                .with_context(|_| {
+                    format!("{} must be numeric", SHEET_GUTTER)
+                })?,
+            cut_marks: matches
+                .value_of(SHEET_CUT_MARKS)
+                .map_or(
+                    Ok(defaults.cut_marks),
+                    str::parse::<bool>,
+                )
+                .with_context(|_| {
+                    format!(
+                        "{} must be true or false",
+                        SHEET_CUT_MARKS
+                    )
+                })?,
+            fold_line: matches
+                .value_of(SHEET_FOLD_LINE)
+                .map_or(
+                    Ok(defaults.fold_line),
+                    str::parse::<bool>,
+                )
+                .with_context(|_| {
+                    format!(
+                        "{} must be true or false",
+                        SHEET_FOLD_LINE
                     )
                 })?,
-            name: matches
-                .value_of(PRINTER_NAME)
-                .expect("CLAP REQUIRES")
-                .into(),
         })
     }
     if matches.is_present(TRELLO_APP_KEY) {
         arguments.trello = Some(Trello {
-            app_key: matches
-                .value_of(TRELLO_APP_KEY)
-                .expect("CLAP REQUIRES")
-                .into(),
-            token: matches
-                .value_of(TRELLO_TOKEN)
-                .expect("CLAP REQUIRES")
-                .into(),
-            print_label: matches
-                .value_of(TRELLO_PRINT_LABEL)
-                .expect("CLAP REQUIRES")
+            app_key: required(&matches, TRELLO_APP_KEY)?.into(),
+            token: required(&matches, TRELLO_TOKEN)?.into(),
+            print_label: required(&matches, TRELLO_PRINT_LABEL)?
                 .into(),
             limit_to_boards: matches
                 .values_of(TRELLO_LIMIT_TO_BOARDS)
                 .map_or_else(Vec::new, |i| {
                     i.map(|s| s.into()).collect()
                 }),
+            subtitle: match matches.value_of(TRELLO_SUBTITLE) {
+                Some("list") => TrelloSubtitle::List,
+                _ => TrelloSubtitle::Id,
+            },
+            after_print: None,
+            api_url: None,
         })
     }
-    if matches.is_present(JIRA_HOST) {
+    if matches.is_present(JIRA_TOKEN) {
+        if !matches.is_present(JIRA_HOST)
+            && !matches.is_present(JIRA_BASE_URL)
+        {
+            return Err(failure::err_msg(format!(
+                "{} or {} is required",
+                JIRA_HOST, JIRA_BASE_URL
+            ))
+            .into());
+        }
         arguments.jira = Some(Jira {
             host: matches
                 .value_of(JIRA_HOST)
-                .expect("CLAP REQUIRES")
+                .unwrap_or_default()
                 .into(),
+            base_url: matches
+                .value_of(JIRA_BASE_URL)
+                .map(|s| s.into()),
             user: matches
                 .value_of(JIRA_USER)
-                .expect("CLAP REQUIRES")
+                .unwrap_or_default()
                 .into(),
-            token: matches
-                .value_of(JIRA_TOKEN)
-                .expect("CLAP REQUIRES")
+            token: required(&matches, JIRA_TOKEN)?.into(),
+            auth: match matches.value_of(JIRA_AUTH) {
+                Some("bearer") => JiraAuth::Bearer,
+                _ => JiraAuth::Basic,
+            },
+            api_version: matches
+                .value_of(JIRA_API_VERSION)
+                .unwrap_or("2")
                 .into(),
-            print_label: matches
-                .value_of(JIRA_PRINT_LABEL)
-                .expect("CLAP REQUIRES")
+            print_label: required(&matches, JIRA_PRINT_LABEL)?
                 .into(),
             limit_to_types: matches
                 .values_of(JIRA_LIMIT_TO_TYPES)
@@ -308,11 +622,85 @@ pub fn handle() -> crate::Result<Arguments> {
                 .map_or_else(Vec::new, |i| {
                     i.map(|s| s.into()).collect()
                 }),
+            jql: matches.value_of(JIRA_JQL).map(|s| s.into()),
+            fields: matches.values_of(JIRA_FIELDS).map_or_else(
+                BTreeMap::new,
+                |i| {
+                    i.map(|s| {
+                        let mut parts = s.splitn(2, '=');
+                        let name =
+                            parts.next().unwrap_or_default();
+                        let id = parts.next().unwrap_or(name);
+                        (name.into(), id.into())
+                    })
+                    .collect()
+                },
+            ),
+            after_print: None,
+        })
+    }
+    if matches.is_present(GITHUB_TOKEN) {
+        arguments.github = Some(Github {
+            token: required(&matches, GITHUB_TOKEN)?.into(),
+            print_label: required(&matches, GITHUB_PRINT_LABEL)?
+                .into(),
+            limit_to_repositories: matches
+                .values_of(GITHUB_LIMIT_TO_REPOSITORIES)
+                .map_or_else(Vec::new, |i| {
+                    i.map(|s| s.into()).collect()
+                }),
+            api_url: matches
+                .value_of(GITHUB_API_URL)
+                .map(|s| s.into()),
+        })
+    }
+    if matches.is_present(GITLAB_TOKEN) {
+        arguments.gitlab = Some(Gitlab {
+            token: required(&matches, GITLAB_TOKEN)?.into(),
+            print_label: required(&matches, GITLAB_PRINT_LABEL)?
+                .into(),
+            limit_to_projects: matches
+                .values_of(GITLAB_LIMIT_TO_PROJECTS)
+                .map_or_else(Vec::new, |i| {
+                    i.map(|s| s.into()).collect()
+                }),
+            limit_to_groups: matches
+                .values_of(GITLAB_LIMIT_TO_GROUPS)
+                .map_or_else(Vec::new, |i| {
+                    i.map(|s| s.into()).collect()
+                }),
+            merge_requests: matches
+                .value_of(GITLAB_MERGE_REQUESTS)
+                .map_or(Ok(false), str::parse::<bool>)
+                .with_context(|_| {
+                    format!(
+                        "{} must be true or false",
+                        GITLAB_MERGE_REQUESTS
+                    )
+                })?,
+            url: matches.value_of(GITLAB_URL).map(|s| s.into()),
+        })
+    }
+    if matches.is_present(LOCAL_PATH) {
+        arguments.local = Some(Local {
+            path: required(&matches, LOCAL_PATH)?.into(),
+            format: matches
+                .value_of(LOCAL_FORMAT)
+                .map(|s| s.into()),
         })
     }
     Ok(arguments)
 }
 
+/// Value of an argument clap requires together with another one
+fn required<'a>(
+    matches: &'a clap::ArgMatches<'_>,
+    name: &str,
+) -> Result<&'a str, failure::Error> {
+    matches.value_of(name).ok_or_else(|| {
+        failure::format_err!("{} is required", name)
+    })
+}
 
 #[allow(clippy::too_many_lines)]
 fn setup() -> clap::ArgMatches<'static> {
@@ -358,6 +746,30 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .env(OUT_DIR_ENV)
                 .help("Optional Directory to save pdfs to. Otherwise pdfs are created in a tmp directory and removed after printing")
         )
+        .arg(
+            Arg::with_name(JOURNAL)
+                .long(JOURNAL)
+                .takes_value(true)
+                .value_name("path")
+                .env(JOURNAL_ENV)
+                .help("File keeping consumed tickets until they are printed. Tickets left by an interrupted run are marked for printing again on start. Defaults to journal.json in the local data directory\n[conf: global.journal]")
+        )
+        .arg(
+            Arg::with_name(HTTP_TIMEOUT)
+                .long(HTTP_TIMEOUT)
+                .takes_value(true)
+                .value_name("secs")
+                .env(HTTP_TIMEOUT_ENV)
+                .help("Secs before a request to a tracker is aborted. Defaults to 30\n[conf: global.http_timeout]")
+        )
+        .arg(
+            Arg::with_name(HTTP_RETRIES)
+                .long(HTTP_RETRIES)
+                .takes_value(true)
+                .value_name("count")
+                .env(HTTP_RETRIES_ENV)
+                .help("Retries of a tracker request failing with a timeout, rate limit or server error. Defaults to 3\n[conf: global.http_retries]")
+        )
         .arg(
             Arg::with_name(PDF_HEIGHT)
                 .long(PDF_HEIGHT)
@@ -421,6 +833,84 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .help("Font size of the subtitle\n[conf: pdf.subtitle_size]")
                 .requires_all(PDF_ARGUMENTS)
         )
+        .arg(
+            Arg::with_name(PDF_FIELD_LINES)
+                .long(PDF_FIELD_LINES)
+                .takes_value(true)
+                .value_name("line")
+                .env(PDF_FIELD_LINES_ENV)
+                .help("Line above the subtitle. Supports placeholders like {fields.priority}\n[conf: pdf.field_lines]")
+                .requires_all(PDF_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(PDF_LAYOUT)
+                .long(PDF_LAYOUT)
+                .takes_value(true)
+                .value_name("file")
+                .env(PDF_LAYOUT_ENV)
+                .help("Toml, yaml or json file describing the card layout\n[conf: pdf.layout]")
+                .requires_all(PDF_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PDF_FONT)
+                .long(PDF_FONT)
+                .takes_value(true)
+                .value_name("file")
+                .env(PDF_FONT_ENV)
+                .help("Ttf font used for all card text\n[conf: pdf.font]")
+                .requires_all(PDF_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PDF_TITLE_MIN_SIZE)
+                .long(PDF_TITLE_MIN_SIZE)
+                .takes_value(true)
+                .value_name("size")
+                .env(PDF_TITLE_MIN_SIZE_ENV)
+                .help("Smallest font size used to fit long titles\n[conf: pdf.title_min_size]")
+                .requires_all(PDF_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PDF_TITLE_MAX_SIZE)
+                .long(PDF_TITLE_MAX_SIZE)
+                .takes_value(true)
+                .value_name("size")
+                .env(PDF_TITLE_MAX_SIZE_ENV)
+                .help("Font size of the title before shrinking\n[conf: pdf.title_max_size]")
+                .requires_all(PDF_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PDF_BACK_LINES)
+                .long(PDF_BACK_LINES)
+                .takes_value(true)
+                .value_name("line")
+                .env(PDF_BACK_LINES_ENV)
+                .help("Line on the back of the card. Supports placeholders like {fields.description}\n[conf: pdf.back_lines]")
+                .requires_all(PDF_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(PDF_BACK_LAYOUT)
+                .long(PDF_BACK_LAYOUT)
+                .takes_value(true)
+                .value_name("file")
+                .env(PDF_BACK_LAYOUT_ENV)
+                .help("Toml, yaml or json file describing the back of the card\n[conf: pdf.back_layout]")
+                .requires_all(PDF_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PDF_COLORS)
+                .long(PDF_COLORS)
+                .takes_value(true)
+                .value_name("value=#rrggbb")
+                .env(PDF_COLORS_ENV)
+                .help("Colour of a ticket value for layout bands, e.g. highest=#d32f2f\n[conf: pdf.colors]")
+                .requires_all(PDF_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
         .arg(
             Arg::with_name(PRINTER_MEDIA)
                 .long(PRINTER_MEDIA)
@@ -457,6 +947,146 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .help("Name of the printer\n[conf: printer.name]")
                 .requires_all(PRINTER_ARGUMENTS)
         )
+        .arg(
+            Arg::with_name(PRINTER_URI)
+                .long(PRINTER_URI)
+                .takes_value(true)
+                .value_name("uri")
+                .env(PRINTER_URI_ENV)
+                .help("IPP uri of the printer. Defaults to the cups queue printer-name on this machine\n[conf: printer.uri]")
+                .requires_all(PRINTER_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PRINTER_JOB_TIMEOUT)
+                .long(PRINTER_JOB_TIMEOUT)
+                .takes_value(true)
+                .value_name("secs")
+                .env(PRINTER_JOB_TIMEOUT_ENV)
+                .help("Secs to wait for the printer to complete a job. Defaults to 300, 0 does not wait\n[conf: printer.job_timeout]")
+                .requires_all(PRINTER_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PRINTER_DUPLEX)
+                .long(PRINTER_DUPLEX)
+                .takes_value(true)
+                .possible_values(PRINTER_DUPLEX_POSSIBLE)
+                .value_name("edge")
+                .env(PRINTER_DUPLEX_ENV)
+                .help("Prints both sides, flipping the paper around its long or short edge\n[conf: printer.duplex]")
+                .requires_all(PRINTER_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(PRINTER_LABEL_LANGUAGE)
+                .long(PRINTER_LABEL_LANGUAGE)
+                .takes_value(true)
+                .possible_values(PRINTER_LABEL_LANGUAGE_POSSIBLE)
+                .value_name("language")
+                .env(PRINTER_LABEL_LANGUAGE_ENV)
+                .help("Sends raster labels straight to a label printer instead of using cups\n[conf: printer.label.language]")
+                .requires_all(PRINTER_ARGUMENTS)
+                .requires(PRINTER_LABEL_OUTPUT)
+        )
+        .arg(
+            Arg::with_name(PRINTER_LABEL_OUTPUT)
+                .long(PRINTER_LABEL_OUTPUT)
+                .takes_value(true)
+                .value_name("tcp://host[:port] or path")
+                .env(PRINTER_LABEL_OUTPUT_ENV)
+                .help("Network address or device of the label printer\n[conf: printer.label.output]")
+                .requires(PRINTER_LABEL_LANGUAGE)
+        )
+        .arg(
+            Arg::with_name(PRINTER_LABEL_DPI)
+                .long(PRINTER_LABEL_DPI)
+                .takes_value(true)
+                .value_name("dpi")
+                .env(PRINTER_LABEL_DPI_ENV)
+                .help("Resolution of the label printer. Defaults to 203 for zpl and 300 for brother-ql\n[conf: printer.label.dpi]")
+                .requires(PRINTER_LABEL_LANGUAGE)
+        )
+        .arg(
+            Arg::with_name(PRINTER_RECEIPT_OUTPUT)
+                .long(PRINTER_RECEIPT_OUTPUT)
+                .takes_value(true)
+                .value_name("tcp://host[:port] or path")
+                .env(PRINTER_RECEIPT_OUTPUT_ENV)
+                .help("Prints tickets as text on an ESC/POS receipt printer instead of using cups\n[conf: printer.receipt.output]")
+                .requires_all(PRINTER_ARGUMENTS)
+                .conflicts_with(PRINTER_LABEL_LANGUAGE)
+        )
+        .arg(
+            Arg::with_name(PRINTER_RECEIPT_COLUMNS)
+                .long(PRINTER_RECEIPT_COLUMNS)
+                .takes_value(true)
+                .value_name("count")
+                .env(PRINTER_RECEIPT_COLUMNS_ENV)
+                .help("Characters per line, 32 for 58 mm and 48 for 80 mm paper. Defaults to 32\n[conf: printer.receipt.columns]")
+                .requires(PRINTER_RECEIPT_OUTPUT)
+        )
+        .arg(
+            Arg::with_name(SHEET_SIZE)
+                .long(SHEET_SIZE)
+                .takes_value(true)
+                .value_name("size")
+                .env(SHEET_SIZE_ENV)
+                .help("Places many cards on each page of a sheet, e.g. a4, letter or 210x297\n[conf: sheet.size]")
+        )
+        .arg(
+            Arg::with_name(SHEET_COLUMNS)
+                .long(SHEET_COLUMNS)
+                .takes_value(true)
+                .value_name("number")
+                .env(SHEET_COLUMNS_ENV)
+                .help("Cards per row, defaults to as many as fit\n[conf: sheet.columns]")
+                .requires(SHEET_SIZE)
+        )
+        .arg(
+            Arg::with_name(SHEET_ROWS)
+                .long(SHEET_ROWS)
+                .takes_value(true)
+                .value_name("number")
+                .env(SHEET_ROWS_ENV)
+                .help("Cards per column, defaults to as many as fit\n[conf: sheet.rows]")
+                .requires(SHEET_SIZE)
+        )
+        .arg(
+            Arg::with_name(SHEET_MARGIN)
+                .long(SHEET_MARGIN)
+                .takes_value(true)
+                .value_name("margin")
+                .env(SHEET_MARGIN_ENV)
+                .help("Unprinted border of the sheet\n[conf: sheet.margin]")
+                .requires(SHEET_SIZE)
+        )
+        .arg(
+            Arg::with_name(SHEET_GUTTER)
+                .long(SHEET_GUTTER)
+                .takes_value(true)
+                .value_name("gutter")
+                .env(SHEET_GUTTER_ENV)
+                .help("Space between two cards\n[conf: sheet.gutter]")
+                .requires(SHEET_SIZE)
+        )
+        .arg(
+            Arg::with_name(SHEET_CUT_MARKS)
+                .long(SHEET_CUT_MARKS)
+                .takes_value(true)
+                .possible_values(&["true", "false"])
+                .value_name("bool")
+                .env(SHEET_CUT_MARKS_ENV)
+                .help("Prints cut marks in the margin\n[conf: sheet.cut_marks]")
+                .requires(SHEET_SIZE)
+        )
+        .arg(
+            Arg::with_name(SHEET_FOLD_LINE)
+                .long(SHEET_FOLD_LINE)
+                .takes_value(true)
+                .possible_values(&["true", "false"])
+                .value_name("bool")
+                .env(SHEET_FOLD_LINE_ENV)
+                .help("Prints a dashed line across the middle of the sheet\n[conf: sheet.fold_line]")
+                .requires(SHEET_SIZE)
+        )
         .arg(
             Arg::with_name(TRELLO_APP_KEY)
                 .long(TRELLO_APP_KEY)
@@ -495,6 +1125,16 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .number_of_values(1)
                 .multiple(true)
         )
+        .arg(
+            Arg::with_name(TRELLO_SUBTITLE)
+                .long(TRELLO_SUBTITLE)
+                .takes_value(true)
+                .possible_values(TRELLO_SUBTITLE_POSSIBLE)
+                .value_name("source")
+                .env(TRELLO_SUBTITLE_ENV)
+                .help("print the card id or the list name as subtitle\n[conf: trello.subtitle]")
+                .requires_all(TRELLO_ARGUMENTS)
+        )
         .arg(
             Arg::with_name(JIRA_HOST)
                 .long(JIRA_HOST)
@@ -504,6 +1144,15 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .help("jira server hostname\n[conf: jira.host]")
                 .requires_all(JIRA_ARGUMENTS)
         )
+        .arg(
+            Arg::with_name(JIRA_BASE_URL)
+                .long(JIRA_BASE_URL)
+                .takes_value(true)
+                .value_name("url")
+                .env(JIRA_BASE_URL_ENV)
+                .help("jira url including scheme and context path. Replaces the hostname\n[conf: jira.base_url]")
+                .requires_all(JIRA_ARGUMENTS)
+        )
         .arg(
             Arg::with_name(JIRA_USER)
                 .long(JIRA_USER)
@@ -522,6 +1171,25 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .help("jira access token\n[conf: jira.token]")
                 .requires_all(JIRA_ARGUMENTS)
         )
+        .arg(
+            Arg::with_name(JIRA_AUTH)
+                .long(JIRA_AUTH)
+                .takes_value(true)
+                .possible_values(JIRA_AUTH_POSSIBLE)
+                .value_name("method")
+                .env(JIRA_AUTH_ENV)
+                .help("basic for user and api token, bearer for personal access tokens\n[conf: jira.auth]")
+                .requires_all(JIRA_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(JIRA_API_VERSION)
+                .long(JIRA_API_VERSION)
+                .takes_value(true)
+                .value_name("version")
+                .env(JIRA_API_VERSION_ENV)
+                .help("version of the jira rest api\n[conf: jira.api_version]")
+                .requires_all(JIRA_ARGUMENTS)
+        )
         .arg(
             Arg::with_name(JIRA_PRINT_LABEL)
                 .long(JIRA_PRINT_LABEL)
@@ -553,32 +1221,197 @@ Configuration is merged from 1 to 5 with higher numbers overriding lower numbers
                 .number_of_values(1)
                 .multiple(true)
         )
+        .arg(
+            Arg::with_name(JIRA_JQL)
+                .long(JIRA_JQL)
+                .takes_value(true)
+                .value_name("jql")
+                .env(JIRA_JQL_ENV)
+                .help("custom search query replacing the limits. {print_label} is replaced with the print label\n[conf: jira.jql]")
+                .requires_all(JIRA_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(JIRA_FIELDS)
+                .long(JIRA_FIELDS)
+                .takes_value(true)
+                .value_name("name=field id")
+                .env(JIRA_FIELDS_ENV)
+                .help("additional field to print, e.g. points=customfield_10016\n[conf: jira.fields]")
+                .requires_all(JIRA_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(GITHUB_TOKEN)
+                .long(GITHUB_TOKEN)
+                .takes_value(true)
+                .value_name("token")
+                .env(GITHUB_TOKEN_ENV)
+                .help("github access token\n[conf: github.token]")
+                .requires_all(GITHUB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITHUB_PRINT_LABEL)
+                .long(GITHUB_PRINT_LABEL)
+                .takes_value(true)
+                .value_name("label")
+                .env(GITHUB_PRINT_LABEL_ENV)
+                .help("label to find issues\n[conf: github.print_label]")
+                .requires_all(GITHUB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITHUB_LIMIT_TO_REPOSITORIES)
+                .long(GITHUB_LIMIT_TO_REPOSITORIES)
+                .takes_value(true)
+                .value_name("owner/repo")
+                .env(GITHUB_LIMIT_TO_REPOSITORIES_ENV)
+                .help("limit search to repositories\n[conf: github.limit_to_repositories]")
+                .requires_all(GITHUB_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(GITHUB_API_URL)
+                .long(GITHUB_API_URL)
+                .takes_value(true)
+                .value_name("url")
+                .env(GITHUB_API_URL_ENV)
+                .help("api url for github enterprise\n[conf: github.api_url]")
+                .requires_all(GITHUB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITLAB_TOKEN)
+                .long(GITLAB_TOKEN)
+                .takes_value(true)
+                .value_name("token")
+                .env(GITLAB_TOKEN_ENV)
+                .help("gitlab access token\n[conf: gitlab.token]")
+                .requires_all(GITLAB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITLAB_PRINT_LABEL)
+                .long(GITLAB_PRINT_LABEL)
+                .takes_value(true)
+                .value_name("label")
+                .env(GITLAB_PRINT_LABEL_ENV)
+                .help("label to find issues\n[conf: gitlab.print_label]")
+                .requires_all(GITLAB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITLAB_LIMIT_TO_PROJECTS)
+                .long(GITLAB_LIMIT_TO_PROJECTS)
+                .takes_value(true)
+                .value_name("project")
+                .env(GITLAB_LIMIT_TO_PROJECTS_ENV)
+                .help("limit search to projects\n[conf: gitlab.limit_to_projects]")
+                .requires_all(GITLAB_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(GITLAB_LIMIT_TO_GROUPS)
+                .long(GITLAB_LIMIT_TO_GROUPS)
+                .takes_value(true)
+                .value_name("group")
+                .env(GITLAB_LIMIT_TO_GROUPS_ENV)
+                .help("limit search to groups\n[conf: gitlab.limit_to_groups]")
+                .requires_all(GITLAB_ARGUMENTS)
+                .number_of_values(1)
+                .multiple(true)
+        )
+        .arg(
+            Arg::with_name(GITLAB_MERGE_REQUESTS)
+                .long(GITLAB_MERGE_REQUESTS)
+                .takes_value(true)
+                .possible_values(&["true", "false"])
+                .value_name("bool")
+                .env(GITLAB_MERGE_REQUESTS_ENV)
+                .help("also print merge requests\n[conf: gitlab.merge_requests]")
+                .requires_all(GITLAB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(GITLAB_URL)
+                .long(GITLAB_URL)
+                .takes_value(true)
+                .value_name("url")
+                .env(GITLAB_URL_ENV)
+                .help("url of a self-hosted gitlab\n[conf: gitlab.url]")
+                .requires_all(GITLAB_ARGUMENTS)
+        )
+        .arg(
+            Arg::with_name(LOCAL_PATH)
+                .long(LOCAL_PATH)
+                .takes_value(true)
+                .value_name("path")
+                .env(LOCAL_PATH_ENV)
+                .help("file to read tickets from. Use - for stdin\n[conf: local.path]")
+        )
+        .arg(
+            Arg::with_name(LOCAL_FORMAT)
+                .long(LOCAL_FORMAT)
+                .takes_value(true)
+                .possible_values(LOCAL_FORMAT_POSSIBLE)
+                .value_name("format")
+                .env(LOCAL_FORMAT_ENV)
+                .help("format of the ticket file. Defaults to the file extension\n[conf: local.format]")
+                .requires(LOCAL_PATH)
+        )
         .get_matches()
 }
 
 fn create_default_config() -> config::Config {
     config::Config {
-            pdf: config::PDfDimension::default(),
+            pdf: PDfDimension::default(),
             printer: Some(Printer {
                 media: String::from("Custom.62x100m2"),
                 orientation: String::from("landscape"),
                 number_of_copies: 2,
                 name: String::from("<printer name>"),
+                uri: None,
+                job_timeout: None,
+                duplex: None,
+                label: None,
+                receipt: None,
             }),
+            sheet: None,
             trello: Some(Trello {
                 app_key: String::from("<trello app key>"),
                 token: String::from("<trello user token>"),
                 print_label: String::from("<label to find tickets>"),
                 limit_to_boards: vec![String::from("<Optional boards to limit search. Empty array to search all boards.>")],
+                subtitle: TrelloSubtitle::Id,
+                after_print: None,
+                api_url: None,
             }),
             jira: Some(Jira {
                 host: String::from("<jira host>"),
+                base_url: None,
                 user: String::from("<jira user>"),
                 token: String::from("<jira user token>"),
+                auth: JiraAuth::Basic,
+                api_version: String::from("2"),
                 print_label: String::from("<label to find tickets>"),
                 limit_to_types: vec![String::from("<Optional types to limit search to. Empty array to search all types.>")],
                 limit_to_projects: vec![String::from("<Optional projects to limit search. Empty array to search all projects.>")],
+                jql: None,
+                fields: BTreeMap::new(),
+                after_print: None,
+            }),
+            github: Some(Github {
+                token: String::from("<github access token>"),
+                print_label: String::from("<label to find issues>"),
+                limit_to_repositories: vec![String::from("<Optional owner/repo to limit search. Empty array to search all repositories.>")],
+                api_url: None,
+            }),
+            gitlab: Some(Gitlab {
+                token: String::from("<gitlab access token>"),
+                print_label: String::from("<label to find issues>"),
+                limit_to_projects: vec![String::from("<Optional group/project to limit search.>")],
+                limit_to_groups: vec![String::from("<Optional groups to limit search. Empty arrays for projects and groups search everything.>")],
+                merge_requests: false,
+                url: None,
             }),
+            local: None,
             global: None
         }
 }
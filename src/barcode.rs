@@ -0,0 +1,120 @@
+use std::iter;
+
+/// Code 39 characters with their bar and space widths. Each pattern
+/// alternates bar and space, `1` marks a wide element.
+const CODE39: &[(char, &str)] = &[
+    ('0', "000110100"),
+    ('1', "100100001"),
+    ('2', "001100001"),
+    ('3', "101100000"),
+    ('4', "000110001"),
+    ('5', "100110000"),
+    ('6', "001110000"),
+    ('7', "000100101"),
+    ('8', "100100100"),
+    ('9', "001100100"),
+    ('A', "100001001"),
+    ('B', "001001001"),
+    ('C', "101001000"),
+    ('D', "000011001"),
+    ('E', "100011000"),
+    ('F', "001011000"),
+    ('G', "000001101"),
+    ('H', "100001100"),
+    ('I', "001001100"),
+    ('J', "000011100"),
+    ('K', "100000011"),
+    ('L', "001000011"),
+    ('M', "101000010"),
+    ('N', "000010011"),
+    ('O', "100010010"),
+    ('P', "001010010"),
+    ('Q', "000000111"),
+    ('R', "100000110"),
+    ('S', "001000110"),
+    ('T', "000010110"),
+    ('U', "110000001"),
+    ('V', "011000001"),
+    ('W', "111000000"),
+    ('X', "010010001"),
+    ('Y', "110010000"),
+    ('Z', "011010000"),
+    ('-', "010000101"),
+    ('.', "110000100"),
+    (' ', "011000100"),
+    ('$', "010101000"),
+    ('/', "010100010"),
+    ('+', "010001010"),
+    ('%', "000101010"),
+];
+const START_STOP: &str = "010010100";
+const WIDE: usize = 3;
+
+/// Encodes `data` as Code 39 including start and stop characters.
+/// Returns one entry per module, `true` for a bar. Lowercase letters
+/// are printed uppercase, other unsupported characters return `None`.
+pub fn code39(data: &str) -> Option<Vec<bool>> {
+    let mut patterns = vec![START_STOP];
+    for c in data.chars() {
+        let c = c.to_ascii_uppercase();
+        let (_, pattern) =
+            CODE39.iter().find(|(key, _)| *key == c)?;
+        patterns.push(pattern);
+    }
+    patterns.push(START_STOP);
+
+    let mut modules = Vec::new();
+    for (index, pattern) in patterns.iter().enumerate() {
+        if index > 0 {
+            modules.push(false);
+        }
+        for (element, width) in pattern.chars().enumerate() {
+            let bar = element % 2 == 0;
+            let width = if width == '1' { WIDE } else { 1 };
+            modules.extend(iter::repeat_n(bar, width));
+        }
+    }
+    Some(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(modules: &[bool]) -> String {
+        modules
+            .iter()
+            .map(|&bar| if bar { '1' } else { '0' })
+            .collect()
+    }
+
+    #[test]
+    fn code39_encodes_start_data_and_stop() {
+        let modules = code39("A").unwrap();
+        // every character is 15 modules wide, followed by a gap
+        assert_eq!(modules.len(), 3 * 15 + 2);
+        assert_eq!(
+            bars(&modules),
+            [
+                "100010111011101",
+                "0",
+                "111010100010111",
+                "0",
+                "100010111011101",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn code39_prints_lowercase_as_uppercase() {
+        assert_eq!(code39("abc-1"), code39("ABC-1"));
+    }
+
+    #[test]
+    fn code39_rejects_unsupported_characters() {
+        assert!(code39("A_B").is_none());
+        assert!(code39("\u{e4}").is_none());
+        assert_eq!(code39("").unwrap().len(), 2 * 15 + 1);
+    }
+}
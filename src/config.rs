@@ -1,7 +1,8 @@
-use crate::Result;
+use crate::{layout::Color, Result};
 use directories::BaseDirs;
 use failure::ResultExt;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
@@ -10,10 +11,18 @@ pub struct Config {
     #[serde(default)]
     pub printer: Option<Printer>,
     #[serde(default)]
+    pub sheet: Option<Sheet>,
+    #[serde(default)]
     pub trello: Option<Trello>,
     #[serde(default)]
     pub jira: Option<Jira>,
     #[serde(default)]
+    pub github: Option<Github>,
+    #[serde(default)]
+    pub gitlab: Option<Gitlab>,
+    #[serde(default)]
+    pub local: Option<Local>,
+    #[serde(default)]
     pub global: Option<Global>,
 }
 
@@ -21,6 +30,16 @@ pub struct Config {
 pub struct Global {
     pub poll: Option<u64>,
     pub out_dir: Option<String>,
+    /// File keeping consumed tickets until they are printed.
+    /// Defaults to `journal.json` in the local data directory
+    #[serde(default)]
+    pub journal: Option<String>,
+    /// Seconds before a tracker request is aborted. Defaults to 30
+    #[serde(default)]
+    pub http_timeout: Option<u64>,
+    /// Attempts after a failed tracker request. Defaults to 3
+    #[serde(default)]
+    pub http_retries: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,16 +48,118 @@ pub struct Trello {
     pub token: String,
     pub print_label: String,
     pub limit_to_boards: Vec<String>,
+    #[serde(default)]
+    pub subtitle: TrelloSubtitle,
+    #[serde(default)]
+    pub after_print: Option<TrelloAfterPrint>,
+    /// Defaults to `https://api.trello.com/1`
+    #[serde(default)]
+    pub api_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrelloSubtitle {
+    /// The id of the card
+    #[default]
+    Id,
+    /// The name of the list containing the card
+    List,
+}
+
+/// Actions executed once a card has been printed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrelloAfterPrint {
+    /// Name of a list on the same board to move the card to
+    #[serde(default)]
+    pub move_to_list: Option<String>,
+    /// Comment to add. `{printer}` and `{time}` are replaced
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Name of a label on the same board to add, e.g. `printed`
+    #[serde(default)]
+    pub add_label: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Jira {
+    #[serde(default)]
     pub host: String,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
     pub user: String,
     pub token: String,
+    #[serde(default)]
+    pub auth: JiraAuth,
+    #[serde(default = "default_jira_api_version")]
+    pub api_version: String,
     pub print_label: String,
     pub limit_to_types: Vec<String>,
     pub limit_to_projects: Vec<String>,
+    #[serde(default)]
+    pub jql: Option<String>,
+    /// Additional fields to print, mapping a name to the jira field id
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default)]
+    pub after_print: Option<JiraAfterPrint>,
+}
+
+/// Actions executed once an issue has been printed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JiraAfterPrint {
+    /// Name of the status to transition the issue into
+    #[serde(default)]
+    pub transition: Option<String>,
+    /// Comment to add. `{printer}` and `{time}` are replaced
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Label to add, e.g. `printed`
+    #[serde(default)]
+    pub add_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JiraAuth {
+    /// User and api token, used by Jira Cloud
+    #[default]
+    Basic,
+    /// Personal access token, used by Jira Server and Data Center
+    Bearer,
+}
+
+fn default_jira_api_version() -> String {
+    String::from("2")
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Github {
+    pub token: String,
+    pub print_label: String,
+    pub limit_to_repositories: Vec<String>,
+    #[serde(default)]
+    pub api_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Gitlab {
+    pub token: String,
+    pub print_label: String,
+    pub limit_to_projects: Vec<String>,
+    pub limit_to_groups: Vec<String>,
+    #[serde(default)]
+    pub merge_requests: bool,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Local {
+    pub path: String,
+    #[serde(default)]
+    pub format: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,6 +172,37 @@ pub struct PDfDimension {
     pub title_seperator_margin: f32,
     pub qrcode_seperator_margin: f32,
     pub subtitle_size: f32,
+    /// Lines printed above the subtitle. Placeholders like
+    /// `{fields.priority}` are replaced with the ticket values
+    #[serde(default)]
+    pub field_lines: Vec<String>,
+    /// Optional layout file replacing the classic card. See
+    /// `layout::Layout`
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// Optional ttf font used for all card text
+    #[serde(default)]
+    pub font: Option<String>,
+    /// Shrinks long titles down to this font size instead of
+    /// cutting them off
+    #[serde(default)]
+    pub title_min_size: Option<f32>,
+    /// Title font size before shrinking. Defaults to the size given
+    /// by `title_lines`
+    #[serde(default)]
+    pub title_max_size: Option<f32>,
+    /// Lines printed on the back of each card, e.g.
+    /// `{fields.description}`. Long text is cut off to fit
+    #[serde(default)]
+    pub back_lines: Vec<String>,
+    /// Optional layout file for the back of each card, replacing
+    /// `back_lines`
+    #[serde(default)]
+    pub back_layout: Option<String>,
+    /// Colours of ticket values like priorities or label names used
+    /// by band regions with a `value`. Keys are matched ignoring case.
+    #[serde(default)]
+    pub colors: BTreeMap<String, Color>,
 }
 
 impl Default for PDfDimension {
@@ -64,6 +216,14 @@ impl Default for PDfDimension {
             title_seperator_margin: 4.0,
             qrcode_seperator_margin: 4.0,
             subtitle_size: 4.0,
+            field_lines: Vec::new(),
+            layout: None,
+            font: None,
+            title_min_size: None,
+            title_max_size: None,
+            back_lines: Vec::new(),
+            back_layout: None,
+            colors: BTreeMap::new(),
         }
     }
 }
@@ -74,14 +234,137 @@ pub struct Printer {
     pub orientation: String,
     pub number_of_copies: u16,
     pub name: String,
+    /// IPP uri of the printer, like `ipp://printer.local/ipp/print`.
+    /// Defaults to the cups queue `name` on this machine
+    #[serde(default)]
+    pub uri: Option<String>,
+    /// Seconds to wait for a print job to complete before the
+    /// ticket is finished. Jobs still pending are canceled. Defaults
+    /// to 300, 0 does not wait
+    #[serde(default)]
+    pub job_timeout: Option<u64>,
+    /// Prints both sides of the paper, required for backs
+    #[serde(default)]
+    pub duplex: Option<Duplex>,
+    /// Sends raster labels straight to a label printer instead of
+    /// printing pdfs through cups
+    #[serde(default)]
+    pub label: Option<LabelPrinter>,
+    /// Prints tickets as text on an ESC/POS receipt printer instead
+    /// of printing pdfs through cups
+    #[serde(default)]
+    pub receipt: Option<ReceiptPrinter>,
+}
+
+/// A thermal label printer driven in its own command language
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LabelPrinter {
+    pub language: LabelLanguage,
+    /// `tcp://<host>[:<port>]` for network printers, the port
+    /// defaults to 9100. Anything else is a file or device path like
+    /// `/dev/usb/lp0`.
+    pub output: String,
+    /// Resolution of the print head. Defaults to 203 for zpl and
+    /// 300 for brother-ql
+    #[serde(default)]
+    pub dpi: Option<u32>,
+}
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LabelLanguage {
+    /// Zebra Programming Language
+    Zpl,
+    /// Raster commands of the Brother QL series
+    BrotherQl,
 }
 
-impl Config {
-    pub fn service_available(&self) -> bool {
-        self.trello.is_some() || self.jira.is_some()
+/// A thermal receipt printer speaking ESC/POS
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReceiptPrinter {
+    /// Network address or device, like `printer.label.output`
+    pub output: String,
+    /// Characters per line, 32 for 58 mm and 48 for 80 mm paper.
+    /// Defaults to 32
+    #[serde(default)]
+    pub columns: Option<usize>,
+}
+
+/// The edge the paper is flipped around when printing both sides
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Duplex {
+    #[default]
+    LongEdge,
+    ShortEdge,
+}
+
+/// Places several cards on each page of a larger sheet, e.g. for
+/// office printers without label rolls
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Sheet {
+    /// `a4`, `a3`, `letter`, `legal` or `<width>x<height>` in
+    /// millimeter
+    #[serde(default = "default_sheet_size")]
+    pub size: String,
+    /// Cards per row. Defaults to as many as fit
+    #[serde(default)]
+    pub columns: Option<u32>,
+    /// Cards per column. Defaults to as many as fit
+    #[serde(default)]
+    pub rows: Option<u32>,
+    /// Unprinted border of the sheet
+    #[serde(default = "default_sheet_margin")]
+    pub margin: f32,
+    /// Space between two cards
+    #[serde(default)]
+    pub gutter: f32,
+    /// Marks in the margin showing where to cut
+    #[serde(default = "default_cut_marks")]
+    pub cut_marks: bool,
+    /// Dashed line across the middle of the sheet for folding it in
+    /// half
+    #[serde(default)]
+    pub fold_line: bool,
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        Self {
+            size: default_sheet_size(),
+            columns: None,
+            rows: None,
+            margin: default_sheet_margin(),
+            gutter: 0.0,
+            cut_marks: default_cut_marks(),
+            fold_line: false,
+        }
     }
 }
 
+fn default_sheet_size() -> String {
+    String::from("a4")
+}
+
+const fn default_sheet_margin() -> f32 {
+    10.0
+}
+
+const fn default_cut_marks() -> bool {
+    true
+}
+
 pub fn get() -> Result<Config> {
     let mut settings = config::Config::default();
     let _ = settings
@@ -91,7 +374,9 @@ pub fn get() -> Result<Config> {
             )
             .required(false),
         )
-        .expect("Configuration froozen 1");
+        .with_context(|_| {
+            "Could not load configuration".to_string()
+        })?;
     let home: Option<String> = BaseDirs::new().and_then(|dir| {
         dir.config_dir().to_str().map(|str| str.into())
     });
@@ -99,15 +384,33 @@ pub fn get() -> Result<Config> {
         dir.push_str("/ticket_printer/ticket_printer");
         let _ = settings
             .merge(config::File::with_name(&dir).required(false))
-            .expect("Configuration froozen 2");
+            .with_context(|_| {
+                "Could not load configuration".to_string()
+            })?;
     }
     let _ = settings
         .merge(
             config::File::with_name("ticket_printer")
                 .required(false),
         )
-        .expect("Configuration froozen 3");
+        .with_context(|_| {
+            "Could not load configuration".to_string()
+        })?;
     Ok(settings.try_into().with_context(|_| {
         "Could not load configuration".to_string()
     })?)
 }
+
+/// Checks settings the types can not express. Runs once after the
+/// arguments are merged, before any ticket is consumed.
+pub fn validate(config: &Config) -> Result<()> {
+    let poll =
+        config.global.as_ref().and_then(|global| global.poll);
+    if poll.is_some() && config.local.is_some() {
+        return Err(failure::format_err!(
+            "global.poll can not be used with the local source. Its tickets carry no print marker, so they would be printed on every poll"
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,671 @@
+use crate::{
+    config::PDfDimension,
+    layout::{Content, FontName, Layout, TextFont},
+    markup::Style,
+};
+use failure::ResultExt;
+use pdf_canvas::{BuiltinFont as BuiltinMetrics, FontSource};
+use printpdf::BuiltinFont;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+};
+use subsetter::Profile;
+use ttf_parser::{Font, GlyphId, OutlineBuilder, TableName};
+
+/// Share of the line height above the baseline for builtin fonts
+const BUILTIN_BASELINE: f32 = 0.8;
+/// Line segments replacing a curve of a glyph outline
+const CURVE_STEPS: u16 = 8;
+/// Builtin fonts as regular, bold, italic and bold italic variant
+const FAMILIES: &[[TextFont; 4]] = &[
+    [
+        TextFont::Helvetica,
+        TextFont::HelveticaBold,
+        TextFont::HelveticaOblique,
+        TextFont::HelveticaBoldOblique,
+    ],
+    [
+        TextFont::Times,
+        TextFont::TimesBold,
+        TextFont::TimesItalic,
+        TextFont::TimesBoldItalic,
+    ],
+    [
+        TextFont::Courier,
+        TextFont::CourierBold,
+        TextFont::CourierOblique,
+        TextFont::CourierBoldOblique,
+    ],
+];
+
+/// A truetype font read from disk. Opentype fonts are only accepted
+/// with truetype outlines, as fonts are embedded as `FontFile2`.
+#[derive(Debug)]
+pub struct FontFile {
+    pub path: String,
+    data: Vec<u8>,
+}
+
+impl FontFile {
+    pub fn load(path: &str) -> Result<Self, failure::Error> {
+        let data = fs::read(path).with_context(|_| {
+            format!("Could not read font {}", path)
+        })?;
+        let Some(face) = Font::from_data(&data, 0) else {
+            return Err(failure::format_err!(
+                "{} is not a valid ttf font",
+                path
+            ));
+        };
+        if !face.has_table(TableName::GlyphData) {
+            return Err(failure::format_err!(
+                "{} has no truetype outlines. Fonts with CFF outlines, like most otf fonts, are not supported",
+                path
+            ));
+        }
+        Ok(Self {
+            path: path.into(),
+            data,
+        })
+    }
+
+    // `load` only accepts data the parser reads
+    #[allow(clippy::expect_used)]
+    fn face(&self) -> Font<'_> {
+        Font::from_data(&self.data, 0)
+            .expect("FONT VALIDATED ON LOAD")
+    }
+
+    /// Width of `text` using the glyph advances of the font. Characters
+    /// without glyph are measured as the missing glyph box.
+    pub fn width(&self, size: f32, text: &str) -> f32 {
+        let face = self.face();
+        let units: u32 = text
+            .chars()
+            .map(|c| {
+                let glyph =
+                    face.glyph_index(c).unwrap_or(GlyphId(0));
+                u32::from(
+                    face.glyph_hor_advance(glyph).unwrap_or(0),
+                )
+            })
+            .sum();
+        units as f32 * size
+            / f32::from(face.units_per_em().unwrap_or(1000))
+    }
+
+    /// Closed outlines of the glyphs of `text` in millimeter. The
+    /// first glyph starts at `x` on the `baseline`.
+    pub fn outlines(
+        &self,
+        size: f32,
+        text: &str,
+        x: f32,
+        baseline: f32,
+    ) -> Vec<Vec<(f32, f32)>> {
+        let face = self.face();
+        let mut outline = Outline {
+            contours: Vec::new(),
+            scale: size
+                / f32::from(face.units_per_em().unwrap_or(1000)),
+            x,
+            baseline,
+        };
+        for c in text.chars() {
+            let glyph = face.glyph_index(c).unwrap_or(GlyphId(0));
+            let _ = face.outline_glyph(glyph, &mut outline);
+            outline.x += outline.scale
+                * f32::from(
+                    face.glyph_hor_advance(glyph).unwrap_or(0),
+                );
+        }
+        outline.contours
+    }
+
+    fn baseline(&self) -> f32 {
+        let face = self.face();
+        let ascender = f32::from(face.ascender());
+        let height = ascender - f32::from(face.descender());
+        if height > 0.0 {
+            ascender / height
+        } else {
+            BUILTIN_BASELINE
+        }
+    }
+
+    /// Returns the font reduced to the glyphs needed for `chars`. Glyph
+    /// ids stay the same. Falls back to the whole font if subsetting
+    /// fails.
+    // the fallback is reported on stderr
+    #[allow(clippy::print_stderr)]
+    pub fn subset(&self, chars: &BTreeSet<char>) -> Vec<u8> {
+        let face = self.face();
+        let mut glyphs: Vec<u16> = chars
+            .iter()
+            .filter_map(|c| face.glyph_index(*c))
+            .map(|glyph| glyph.0)
+            .collect();
+        glyphs.push(0);
+        subsetter::subset(&self.data, 0, Profile::pdf(&glyphs))
+            .unwrap_or_else(|err| {
+                eprintln!(
+                    "WARN: Could not subset {}: {}",
+                    self.path, err
+                );
+                self.data.clone()
+            })
+    }
+}
+
+/// Collects the points of a glyph outline, moved to the position of
+/// the glyph and scaled to millimeter
+struct Outline {
+    contours: Vec<Vec<(f32, f32)>>,
+    scale: f32,
+    x: f32,
+    baseline: f32,
+}
+
+impl Outline {
+    const fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            x.mul_add(self.scale, self.x),
+            y.mul_add(self.scale, self.baseline),
+        )
+    }
+
+    fn current(&self) -> (f32, f32) {
+        self.contours
+            .last()
+            .and_then(|contour| contour.last())
+            .copied()
+            .unwrap_or((self.x, self.baseline))
+    }
+
+    /// Adds a curve as short lines. `at` returns the point at a
+    /// share of the curve.
+    fn curve(&mut self, at: impl Fn(f32) -> (f32, f32)) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.extend((1..=CURVE_STEPS).map(|step| {
+                at(f32::from(step) / f32::from(CURVE_STEPS))
+            }));
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let start = self.point(x, y);
+        self.contours.push(vec![start]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let end = self.point(x, y);
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push(end);
+        }
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (
+            self.current(),
+            self.point(x1, y1),
+            self.point(x, y),
+        );
+        self.curve(|t| {
+            let (w0, w1, w2) = (
+                (1.0 - t) * (1.0 - t),
+                2.0 * (1.0 - t) * t,
+                t * t,
+            );
+            (
+                w2.mul_add(p2.0, w0.mul_add(p0.0, w1 * p1.0)),
+                w2.mul_add(p2.1, w0.mul_add(p0.1, w1 * p1.1)),
+            )
+        });
+    }
+
+    fn curve_to(
+        &mut self,
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        x: f32,
+        y: f32,
+    ) {
+        let (p0, p1, p2, p3) = (
+            self.current(),
+            self.point(x1, y1),
+            self.point(x2, y2),
+            self.point(x, y),
+        );
+        self.curve(|t| {
+            let rest = 1.0 - t;
+            let (w0, w1, w2, w3) = (
+                rest * rest * rest,
+                3.0 * rest * rest * t,
+                3.0 * rest * t * t,
+                t * t * t,
+            );
+            (
+                w3.mul_add(
+                    p3.0,
+                    w2.mul_add(p2.0, w0.mul_add(p0.0, w1 * p1.0)),
+                ),
+                w3.mul_add(
+                    p3.1,
+                    w2.mul_add(p2.1, w0.mul_add(p0.1, w1 * p1.1)),
+                ),
+            )
+        });
+    }
+
+    // contours are always filled as closed shapes
+    fn close(&mut self) {}
+}
+
+/// All font files used by the cards, loaded once per batch
+#[derive(Debug)]
+pub struct Fonts {
+    default: Option<String>,
+    files: BTreeMap<String, FontFile>,
+    /// Whether the standard pdf fonts can be used. Label printers
+    /// only receive bitmaps, so all their text needs a font file.
+    builtin: bool,
+}
+
+impl Fonts {
+    pub fn load(
+        pdf: &PDfDimension,
+        layouts: &[&Layout],
+        builtin: bool,
+    ) -> Result<Self, failure::Error> {
+        let mut paths: Vec<&String> = pdf.font.iter().collect();
+        let regions =
+            layouts.iter().flat_map(|layout| &layout.regions);
+        for region in regions {
+            if let Content::Text(ref text) = region.content {
+                match text.font {
+                    Some(FontName::File(ref path)) => paths.push(path),
+                    _ if !builtin && pdf.font.is_none() => {
+                        return Err(failure::format_err!(
+                            "Region {} needs a font file for label printers. Set pdf.font or a font file for the region",
+                            region.name
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut files = BTreeMap::new();
+        for path in paths {
+            if !files.contains_key(path) {
+                let _ = files
+                    .insert(path.clone(), FontFile::load(path)?);
+            }
+        }
+        Ok(Self {
+            default: pdf.font.clone(),
+            files,
+            builtin,
+        })
+    }
+
+    /// Returns the font of a text region. Regions without font use
+    /// `pdf.font` or Helvetica. Without builtin fonts `pdf.font`
+    /// replaces them.
+    pub fn resolve(
+        &self,
+        name: Option<&FontName>,
+    ) -> CardFont<'_> {
+        let path = match name {
+            Some(FontName::Builtin(font)) if self.builtin => {
+                return CardFont::Builtin(*font)
+            }
+            Some(FontName::File(path)) => Some(path),
+            _ => self.default.as_ref(),
+        };
+        path.and_then(|path| self.files.get(path)).map_or(
+            CardFont::Builtin(TextFont::Helvetica),
+            CardFont::File,
+        )
+    }
+
+    /// Returns the variant of a font for a markdown style. Fonts stay
+    /// unchanged if the variant would be a missing builtin font.
+    pub fn styled<'a>(
+        &self,
+        font: CardFont<'a>,
+        style: Style,
+    ) -> CardFont<'a> {
+        match font.styled(style) {
+            CardFont::Builtin(_) if !self.builtin => font,
+            styled => styled,
+        }
+    }
+}
+
+/// The font of a text region
+#[allow(variant_size_differences)]
+#[derive(Debug, Clone, Copy)]
+pub enum CardFont<'a> {
+    Builtin(TextFont),
+    File(&'a FontFile),
+}
+
+impl PartialEq for CardFont<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (*self, *other) {
+            (CardFont::Builtin(a), CardFont::Builtin(b)) => {
+                a == b
+            }
+            (CardFont::File(a), CardFont::File(b)) => {
+                a.path == b.path
+            }
+            _ => false,
+        }
+    }
+}
+
+impl CardFont<'_> {
+    /// Returns the variant of the font for a markdown style. Font
+    /// files have no variants, only code switches to Courier.
+    pub fn styled(self, style: Style) -> Self {
+        match self {
+            CardFont::Builtin(font) => {
+                CardFont::Builtin(styled(font, style))
+            }
+            CardFont::File(_) if style == Style::Code => {
+                CardFont::Builtin(TextFont::Courier)
+            }
+            CardFont::File(_) => self,
+        }
+    }
+
+    pub fn width(&self, size: f32, text: &str) -> f32 {
+        match *self {
+            CardFont::Builtin(font) => {
+                builtin(font).1.get_width(size, text)
+            }
+            CardFont::File(file) => file.width(size, text),
+        }
+    }
+
+    /// Share of the line height above the baseline
+    pub fn baseline(&self) -> f32 {
+        match *self {
+            CardFont::Builtin(_) => BUILTIN_BASELINE,
+            CardFont::File(file) => file.baseline(),
+        }
+    }
+}
+
+/// Returns the variant of a builtin font for a markdown style. Bold
+/// and italic add up, so a bold font stays bold in italic text.
+fn styled(font: TextFont, style: Style) -> TextFont {
+    let (family, variant) = FAMILIES
+        .iter()
+        .enumerate()
+        .find_map(|(family, fonts)| {
+            fonts
+                .iter()
+                .position(|f| *f == font)
+                .map(|variant| (family, variant))
+        })
+        .unwrap_or((0, 0));
+    let (family, variant) = match style {
+        Style::Regular => (family, variant),
+        Style::Bold => (family, variant | 1),
+        Style::Italic => (family, variant | 2),
+        Style::BoldItalic => (family, variant | 3),
+        // Courier is the third family
+        Style::Code => (2, variant & 1),
+    };
+    FAMILIES
+        .get(family)
+        .and_then(|fonts| fonts.get(variant))
+        .copied()
+        .unwrap_or(font)
+}
+
+/// Returns the pdf font together with the metrics used for measuring
+pub const fn builtin(
+    font: TextFont,
+) -> (BuiltinFont, BuiltinMetrics) {
+    match font {
+        TextFont::Helvetica => {
+            (BuiltinFont::Helvetica, BuiltinMetrics::Helvetica)
+        }
+        TextFont::HelveticaBold => (
+            BuiltinFont::HelveticaBold,
+            BuiltinMetrics::Helvetica_Bold,
+        ),
+        TextFont::HelveticaOblique => (
+            BuiltinFont::HelveticaOblique,
+            BuiltinMetrics::Helvetica_Oblique,
+        ),
+        TextFont::HelveticaBoldOblique => (
+            BuiltinFont::HelveticaBoldOblique,
+            BuiltinMetrics::Helvetica_BoldOblique,
+        ),
+        TextFont::Times => {
+            (BuiltinFont::TimesRoman, BuiltinMetrics::Times_Roman)
+        }
+        TextFont::TimesBold => {
+            (BuiltinFont::TimesBold, BuiltinMetrics::Times_Bold)
+        }
+        TextFont::TimesItalic => (
+            BuiltinFont::TimesItalic,
+            BuiltinMetrics::Times_Italic,
+        ),
+        TextFont::TimesBoldItalic => (
+            BuiltinFont::TimesBoldItalic,
+            BuiltinMetrics::Times_BoldItalic,
+        ),
+        TextFont::Courier => {
+            (BuiltinFont::Courier, BuiltinMetrics::Courier)
+        }
+        TextFont::CourierBold => (
+            BuiltinFont::CourierBold,
+            BuiltinMetrics::Courier_Bold,
+        ),
+        TextFont::CourierOblique => (
+            BuiltinFont::CourierOblique,
+            BuiltinMetrics::Courier_Oblique,
+        ),
+        TextFont::CourierBoldOblique => (
+            BuiltinFont::CourierBoldOblique,
+            BuiltinMetrics::Courier_BoldOblique,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Align, Region, TextBox, VAlign};
+
+    #[test]
+    fn styles_add_up_within_a_family() {
+        assert_eq!(
+            styled(TextFont::Helvetica, Style::Bold),
+            TextFont::HelveticaBold
+        );
+        assert_eq!(
+            styled(TextFont::HelveticaBold, Style::Italic),
+            TextFont::HelveticaBoldOblique
+        );
+        assert_eq!(
+            styled(TextFont::TimesItalic, Style::Regular),
+            TextFont::TimesItalic
+        );
+        assert_eq!(
+            styled(TextFont::Times, Style::BoldItalic),
+            TextFont::TimesBoldItalic
+        );
+    }
+
+    #[test]
+    fn code_switches_to_courier_keeping_bold() {
+        assert_eq!(
+            styled(TextFont::TimesItalic, Style::Code),
+            TextFont::Courier
+        );
+        assert_eq!(
+            styled(TextFont::HelveticaBold, Style::Code),
+            TextFont::CourierBold
+        );
+    }
+
+    #[test]
+    fn builtin_widths_scale_with_the_size() {
+        let font = CardFont::Builtin(TextFont::Helvetica);
+        let small = font.width(5.0, "Ticket");
+        assert!(small > 0.0);
+        assert!(
+            2.0_f32
+                .mul_add(-small, font.width(10.0, "Ticket"))
+                .abs()
+                < 0.001
+        );
+        // every Courier character is 0.6 of the size wide
+        let courier = CardFont::Builtin(TextFont::Courier);
+        assert!(
+            (courier.width(10.0, "abcd") - 24.0).abs() < 0.001
+        );
+    }
+
+    fn text_layout(font: Option<FontName>) -> Layout {
+        Layout {
+            regions: vec![Region {
+                name: "title".into(),
+                x: 0.0,
+                y: 0.0,
+                width: 10.0,
+                height: 10.0,
+                content: Content::Text(TextBox {
+                    text: "{titel}".into(),
+                    lines: 1,
+                    size: None,
+                    min_size: None,
+                    align: Align::Left,
+                    valign: VAlign::Top,
+                    font,
+                    markdown: false,
+                }),
+            }],
+            colors: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn label_printers_need_a_font_file() {
+        let pdf = PDfDimension::default();
+        let layout = text_layout(None);
+        assert!(Fonts::load(&pdf, &[&layout], true).is_ok());
+        let err =
+            Fonts::load(&pdf, &[&layout], false).unwrap_err();
+        assert!(err.to_string().contains("title"));
+        let builtin =
+            text_layout(Some(FontName::Builtin(TextFont::Times)));
+        assert!(Fonts::load(&pdf, &[&builtin], false).is_err());
+    }
+
+    #[test]
+    fn missing_font_files_are_reported() {
+        let pdf = PDfDimension {
+            font: Some("/nonexistent/font.ttf".into()),
+            ..PDfDimension::default()
+        };
+        assert!(Fonts::load(&pdf, &[], true).is_err());
+    }
+
+    #[test]
+    fn regions_fall_back_to_helvetica() {
+        let fonts =
+            Fonts::load(&PDfDimension::default(), &[], true)
+                .unwrap();
+        assert_eq!(
+            fonts.resolve(None),
+            CardFont::Builtin(TextFont::Helvetica)
+        );
+        assert_eq!(
+            fonts.resolve(Some(&FontName::Builtin(
+                TextFont::Times
+            ))),
+            CardFont::Builtin(TextFont::Times)
+        );
+    }
+
+    /// Subset of Source Sans Pro with truetype outlines
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fonts/SourceSansPro-Regular-Tiny.ttf"
+    );
+
+    #[test]
+    fn font_files_measure_the_glyph_advances() {
+        let font = FontFile::load(FIXTURE).unwrap();
+        // 536 + 246 + 456 + 495 + 496 + 338 of 1000 units per em
+        assert!(
+            (font.width(10.0, "Ticket") - 25.67).abs() < 0.001
+        );
+        assert!(
+            (font.width(20.0, "Ticket") - 51.34).abs() < 0.001
+        );
+        // without glyph, measured as the 653 units wide missing glyph
+        assert!(
+            (font.width(10.0, "\u{e9}") - 6.53).abs() < 0.001
+        );
+        assert!(font.width(10.0, "").abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn subsets_keep_the_used_glyphs() {
+        let font = FontFile::load(FIXTURE).unwrap();
+        let chars = "Ti".chars().collect();
+        let subset = FontFile {
+            path: font.path.clone(),
+            data: font.subset(&chars),
+        };
+        assert!(subset.data.len() * 2 < font.data.len());
+        assert!(subset.face().has_table(TableName::GlyphData));
+        assert!(
+            (subset.width(10.0, "Ti") - font.width(10.0, "Ti"))
+                .abs()
+                < 0.001
+        );
+        assert_eq!(
+            subset.face().glyph_index('T'),
+            font.face().glyph_index('T')
+        );
+        assert!(!subset.outlines(10.0, "T", 0.0, 0.0).is_empty());
+        assert!(subset.outlines(10.0, "A", 0.0, 0.0).is_empty());
+    }
+
+    #[test]
+    fn fonts_without_truetype_outlines_are_rejected() {
+        let mut data = fs::read(FIXTURE).unwrap();
+        // renaming the glyf table in the table directory leaves a
+        // font like one with CFF outlines
+        let directory = 12 + 16 * 13;
+        let tag = data[..directory]
+            .windows(4)
+            .position(|window| window == b"glyf")
+            .unwrap();
+        data[tag..tag + 4].copy_from_slice(b"zzzz");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cff.otf");
+        fs::write(&path, data).unwrap();
+        let err =
+            FontFile::load(path.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("no truetype outlines"));
+        let pdf = PDfDimension {
+            font: Some(path.to_str().unwrap().into()),
+            ..PDfDimension::default()
+        };
+        assert!(Fonts::load(&pdf, &[], true).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::layout::Color;
+
+/// An issue type icon on a unit square: a coloured background with a
+/// white symbol on top. Points start at the lower left corner.
+#[derive(Debug)]
+pub struct Icon {
+    pub background: Color,
+    pub symbol: &'static [(f32, f32)],
+}
+
+/// Icons of the common issue types, looked up by lower case name
+const ICONS: &[(&str, Icon)] = &[
+    (
+        "bug",
+        Icon {
+            background: Color::rgb(0xe5, 0x49, 0x3a),
+            // a dot, drawn as octagon
+            symbol: &[
+                (0.60, 0.74),
+                (0.74, 0.60),
+                (0.74, 0.40),
+                (0.60, 0.26),
+                (0.40, 0.26),
+                (0.26, 0.40),
+                (0.26, 0.60),
+                (0.40, 0.74),
+            ],
+        },
+    ),
+    (
+        "story",
+        Icon {
+            background: Color::rgb(0x63, 0xba, 0x3c),
+            // a bookmark
+            symbol: &[
+                (0.30, 0.80),
+                (0.70, 0.80),
+                (0.70, 0.20),
+                (0.50, 0.36),
+                (0.30, 0.20),
+            ],
+        },
+    ),
+    (
+        "task",
+        Icon {
+            background: Color::rgb(0x4b, 0xad, 0xe8),
+            // a check mark
+            symbol: &[
+                (0.18, 0.52),
+                (0.29, 0.63),
+                (0.42, 0.50),
+                (0.71, 0.79),
+                (0.82, 0.68),
+                (0.42, 0.28),
+            ],
+        },
+    ),
+    (
+        "subtask",
+        Icon {
+            background: Color::rgb(0x4b, 0xad, 0xe8),
+            // two overlapping squares
+            symbol: &[
+                (0.20, 0.80),
+                (0.62, 0.80),
+                (0.62, 0.62),
+                (0.80, 0.62),
+                (0.80, 0.20),
+                (0.38, 0.20),
+                (0.38, 0.38),
+                (0.20, 0.38),
+            ],
+        },
+    ),
+    (
+        "epic",
+        Icon {
+            background: Color::rgb(0x90, 0x4e, 0xe2),
+            // a lightning bolt
+            symbol: &[
+                (0.56, 0.86),
+                (0.28, 0.44),
+                (0.48, 0.44),
+                (0.42, 0.14),
+                (0.72, 0.56),
+                (0.52, 0.56),
+            ],
+        },
+    ),
+];
+
+/// Returns the icon of an issue type like `Bug` or `Sub-task`
+pub fn find(issue_type: &str) -> Option<&'static Icon> {
+    let name = issue_type.trim().to_lowercase().replace('-', "");
+    ICONS
+        .iter()
+        .find(|(icon_name, _)| *icon_name == name)
+        .map(|(_, icon)| icon)
+}
@@ -0,0 +1,674 @@
+use crate::{config::Printer, Result};
+use failure::ResultExt;
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use std::{
+    convert::TryFrom,
+    env,
+    io::Read,
+    result, thread,
+    time::{Duration, Instant},
+};
+
+const VERSION: [u8; 2] = [1, 1];
+const REQUEST_ID: u32 = 1;
+const PRINT_JOB: u16 = 0x0002;
+const CANCEL_JOB: u16 = 0x0008;
+const GET_JOB_ATTRIBUTES: u16 = 0x0009;
+const OPERATION_ATTRIBUTES: u8 = 0x01;
+const JOB_ATTRIBUTES: u8 = 0x02;
+const END_OF_ATTRIBUTES: u8 = 0x03;
+/// Tags up to this one start attribute groups, the others are values
+const LAST_DELIMITER: u8 = 0x0f;
+/// Status codes above are errors
+const LAST_SUCCESS: u16 = 0x00ff;
+const IPP_PORT: u16 = 631;
+const TIMEOUT: Duration = Duration::from_mins(1);
+/// Time between two job state queries
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const JOB_CANCELED: i32 = 7;
+const JOB_ABORTED: i32 = 8;
+const JOB_COMPLETED: i32 = 9;
+const USER: &str = "ticket-printer";
+
+/// Attribute value, written with the matching value tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i32),
+    Boolean(bool),
+    Enum(i32),
+    Name(String),
+    Keyword(String),
+    Uri(String),
+    Charset(String),
+    Language(String),
+    MimeType(String),
+    Other(u8, Vec<u8>),
+}
+
+impl Value {
+    const fn tag(&self) -> u8 {
+        match self {
+            Self::Integer(_) => 0x21,
+            Self::Boolean(_) => 0x22,
+            Self::Enum(_) => 0x23,
+            Self::Name(_) => 0x42,
+            Self::Keyword(_) => 0x44,
+            Self::Uri(_) => 0x45,
+            Self::Charset(_) => 0x47,
+            Self::Language(_) => 0x48,
+            Self::MimeType(_) => 0x49,
+            Self::Other(tag, _) => *tag,
+        }
+    }
+
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Self::Integer(value) | Self::Enum(value) => {
+                value.to_be_bytes().to_vec()
+            }
+            Self::Boolean(value) => vec![u8::from(value)],
+            Self::Name(text)
+            | Self::Keyword(text)
+            | Self::Uri(text)
+            | Self::Charset(text)
+            | Self::Language(text)
+            | Self::MimeType(text) => text.into_bytes(),
+            Self::Other(_, bytes) => bytes,
+        }
+    }
+
+    fn parse(tag: u8, bytes: &[u8]) -> Self {
+        let integer =
+            <[u8; 4]>::try_from(bytes).map(i32::from_be_bytes);
+        let text = || String::from_utf8_lossy(bytes).into_owned();
+        match (tag, integer) {
+            (0x21, Ok(value)) => Self::Integer(value),
+            (0x23, Ok(value)) => Self::Enum(value),
+            (0x22, _) => Self::Boolean(bytes.first() == Some(&1)),
+            (0x41..=0x42, _) => Self::Name(text()),
+            (0x44, _) => Self::Keyword(text()),
+            (0x45, _) => Self::Uri(text()),
+            (0x47, _) => Self::Charset(text()),
+            (0x48, _) => Self::Language(text()),
+            (0x49, _) => Self::MimeType(text()),
+            _ => Self::Other(tag, bytes.to_vec()),
+        }
+    }
+}
+
+/// An IPP request, started with the operation attributes every
+/// request needs
+#[derive(Debug)]
+pub struct Request {
+    data: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(operation: u16, printer_uri: &str) -> Self {
+        let mut data = VERSION.to_vec();
+        data.extend(&operation.to_be_bytes());
+        data.extend(&REQUEST_ID.to_be_bytes());
+        data.push(OPERATION_ATTRIBUTES);
+        Self { data }
+            .attribute(
+                "attributes-charset",
+                Value::Charset("utf-8".into()),
+            )
+            .attribute(
+                "attributes-natural-language",
+                Value::Language("en".into()),
+            )
+            .attribute(
+                "printer-uri",
+                Value::Uri(printer_uri.into()),
+            )
+            .attribute(
+                "requesting-user-name",
+                Value::Name(
+                    env::var("USER")
+                        .unwrap_or_else(|_| USER.into()),
+                ),
+            )
+    }
+
+    /// Starts the next attribute group, like the job attributes
+    pub fn group(mut self, tag: u8) -> Self {
+        self.data.push(tag);
+        self
+    }
+
+    pub fn attribute(mut self, name: &str, value: Value) -> Self {
+        self.data.push(value.tag());
+        let bytes = value.bytes();
+        push_string(&mut self.data, name.as_bytes());
+        push_string(&mut self.data, &bytes);
+        self
+    }
+
+    /// Sends the request with an optional document and returns the
+    /// response if the printer reports success
+    pub fn send(
+        mut self,
+        uri: &str,
+        document: &[u8],
+    ) -> result::Result<Response, failure::Error> {
+        self.data.push(END_OF_ATTRIBUTES);
+        self.data.extend(document);
+        let mut body = Vec::new();
+        let _ = Client::builder()
+            .timeout(TIMEOUT)
+            .build()?
+            .post(http_url(uri)?)
+            .header(CONTENT_TYPE, "application/ipp")
+            .body(self.data)
+            .send()
+            .and_then(reqwest::Response::error_for_status)?
+            .read_to_end(&mut body)?;
+        let response =
+            Response::parse(&body).ok_or_else(|| {
+                failure::format_err!(
+                    "Invalid IPP response from {}",
+                    uri
+                )
+            })?;
+        if response.status > LAST_SUCCESS {
+            return Err(failure::format_err!(
+                "Printer {} failed with IPP status {:#06x}: {}",
+                uri,
+                response.status,
+                response
+                    .text("status-message")
+                    .unwrap_or_default()
+            ));
+        }
+        Ok(response)
+    }
+}
+
+/// Status and attributes of all groups of an IPP response
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub attributes: Vec<(String, Value)>,
+}
+
+impl Response {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let status = u16::from_be_bytes(
+            <[u8; 2]>::try_from(data.get(2..4)?).ok()?,
+        );
+        let mut attributes: Vec<(String, Value)> = Vec::new();
+        let mut rest = data.get(8..)?;
+        loop {
+            let (&tag, after) = rest.split_first()?;
+            rest = after;
+            if tag == END_OF_ATTRIBUTES {
+                break;
+            } else if tag <= LAST_DELIMITER {
+                continue;
+            }
+            let (name, after) = read_string(rest)?;
+            let (value, after) = read_string(after)?;
+            rest = after;
+            let value = Value::parse(tag, value);
+            // additional values of a set have no name
+            let name = if name.is_empty() {
+                attributes.last()?.0.clone()
+            } else {
+                String::from_utf8_lossy(name).into_owned()
+            };
+            attributes.push((name, value));
+        }
+        Some(Self { status, attributes })
+    }
+
+    /// First value of an attribute
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i32> {
+        match self.value(name)? {
+            Value::Integer(value) | Value::Enum(value) => {
+                Some(*value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.value(name)? {
+            Value::Name(text) | Value::Keyword(text) => {
+                Some(text)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The configured `printer.uri` or the cups queue `printer.name` on
+/// this machine
+pub fn printer_uri(printer: &Printer) -> String {
+    printer.uri.clone().unwrap_or_else(|| {
+        format!(
+            "ipp://localhost:{}/printers/{}",
+            IPP_PORT, printer.name
+        )
+    })
+}
+
+/// Submits a pdf as a single job and returns its id
+pub fn print_job(
+    printer_uri: &str,
+    job_name: &str,
+    pdf: &[u8],
+    attributes: Vec<(&str, Value)>,
+) -> Result<i32> {
+    let request = Request::new(PRINT_JOB, printer_uri)
+        .attribute("job-name", Value::Name(job_name.into()))
+        .attribute(
+            "document-format",
+            Value::MimeType("application/pdf".into()),
+        )
+        .group(JOB_ATTRIBUTES);
+    let response = attributes
+        .into_iter()
+        .fold(request, |request, (name, value)| {
+            request.attribute(name, value)
+        })
+        .send(printer_uri, pdf)
+        .with_context(|_| {
+            format!("Unable to print {}", job_name)
+        })?;
+    let job = response.integer("job-id").ok_or_else(|| {
+        failure::format_err!(
+            "Printer {} returned no job id",
+            printer_uri
+        )
+    })?;
+    Ok(job)
+}
+
+/// The state a print job ended in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Completed,
+    Canceled,
+    Aborted,
+}
+
+/// Polls the state of a job until it ends. Fails if it is still
+/// pending or processing after `timeout`.
+pub fn wait_for_job(
+    printer_uri: &str,
+    job: i32,
+    timeout: Duration,
+) -> Result<JobState> {
+    let started = Instant::now();
+    loop {
+        let response =
+            Request::new(GET_JOB_ATTRIBUTES, printer_uri)
+                .attribute("job-id", Value::Integer(job))
+                .attribute(
+                    "requested-attributes",
+                    Value::Keyword("job-state".into()),
+                )
+                .send(printer_uri, &[])
+                .with_context(|_| {
+                    format!("Unable to query print job {}", job)
+                })?;
+        match response.integer("job-state") {
+            Some(JOB_COMPLETED) => {
+                return Ok(JobState::Completed)
+            }
+            Some(JOB_CANCELED) => return Ok(JobState::Canceled),
+            Some(JOB_ABORTED) => return Ok(JobState::Aborted),
+            _ => {}
+        }
+        if started.elapsed() >= timeout {
+            return Err(failure::format_err!(
+                "Print job {} did not complete within {} seconds",
+                job,
+                timeout.as_secs()
+            )
+            .into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Cancels a job, so it is not printed anymore. Fails if the job
+/// already ended or the printer refuses to cancel it.
+pub fn cancel_job(printer_uri: &str, job: i32) -> Result<()> {
+    let _ = Request::new(CANCEL_JOB, printer_uri)
+        .attribute("job-id", Value::Integer(job))
+        .send(printer_uri, &[])
+        .with_context(|_| {
+            format!("Unable to cancel print job {}", job)
+        })?;
+    Ok(())
+}
+
+/// `ipp` and `ipps` uris are http on port 631 unless given otherwise
+fn http_url(uri: &str) -> result::Result<Url, failure::Error> {
+    let schemes =
+        [("ipps://", "https://"), ("ipp://", "http://")];
+    let http = schemes
+        .iter()
+        .find(|(ipp, _)| uri.starts_with(ipp))
+        .map(|(ipp, http)| uri.replacen(ipp, http, 1));
+    let mut url =
+        Url::parse(http.as_ref().map_or(uri, String::as_str))
+            .with_context(|_| {
+                format!("Invalid printer uri {}", uri)
+            })?;
+    if http.is_some() && url.port().is_none() {
+        url.set_port(Some(IPP_PORT)).map_err(|()| {
+            failure::format_err!("Invalid printer uri {}", uri)
+        })?;
+    }
+    Ok(url)
+}
+
+/// Writes the length as two bytes, followed by the bytes
+fn push_string(data: &mut Vec<u8>, bytes: &[u8]) {
+    let length = u16::try_from(bytes.len()).unwrap_or(u16::MAX);
+    data.extend(&length.to_be_bytes());
+    data.extend(bytes.iter().take(usize::from(length)));
+}
+
+fn read_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let length = u16::from_be_bytes(
+        <[u8; 2]>::try_from(data.get(..2)?).ok()?,
+    );
+    let end = 2 + usize::from(length);
+    Some((data.get(2..end)?, data.get(end..)?))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        net::{TcpListener, TcpStream},
+        thread::JoinHandle,
+    };
+
+    fn encode(request: Request) -> Vec<u8> {
+        let mut data = request.data;
+        data.push(END_OF_ATTRIBUTES);
+        data
+    }
+
+    /// IPP response with the given status and operation attributes
+    pub fn response(
+        status: u16,
+        attributes: Vec<(&str, Value)>,
+    ) -> Vec<u8> {
+        let mut data = VERSION.to_vec();
+        data.extend(&status.to_be_bytes());
+        data.extend(&REQUEST_ID.to_be_bytes());
+        data.push(OPERATION_ATTRIBUTES);
+        let response = attributes.into_iter().fold(
+            Request { data },
+            |request, (name, value)| {
+                request.attribute(name, value)
+            },
+        );
+        encode(response)
+    }
+
+    /// Reads one http request and returns its body
+    fn read_body(stream: &mut TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = stream.read(&mut buffer).unwrap();
+            assert!(read > 0, "Connection closed early");
+            data.extend(&buffer[..read]);
+            let header_end = data
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n");
+            if let Some(end) = header_end {
+                let headers =
+                    String::from_utf8_lossy(&data[..end])
+                        .to_lowercase();
+                let length = headers
+                    .lines()
+                    .find_map(|line| {
+                        line.strip_prefix("content-length:")
+                    })
+                    .and_then(|length| length.trim().parse().ok())
+                    .unwrap_or(0);
+                if data.len() >= end + 4 + length {
+                    return data[end + 4..end + 4 + length]
+                        .to_vec();
+                }
+            }
+        }
+    }
+
+    /// Answers one request per response and returns the printer uri
+    /// together with the received request bodies
+    pub fn serve(
+        responses: Vec<Vec<u8>>,
+    ) -> (String, JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let body = read_body(&mut stream);
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/ipp\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        response.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&response).unwrap();
+                    body
+                })
+                .collect()
+        });
+        (
+            format!("ipp://127.0.0.1:{}/printers/test", port),
+            server,
+        )
+    }
+
+    #[test]
+    fn requests_start_with_version_operation_and_id() {
+        let data =
+            encode(Request::new(PRINT_JOB, "ipp://host/p"));
+        assert_eq!(&data[..9], &[1, 1, 0, 2, 0, 0, 0, 1, 1]);
+        assert_eq!(data[9], 0x47);
+        assert_eq!(&data[10..12], &[0, 18]);
+        assert_eq!(&data[12..30], b"attributes-charset");
+        assert_eq!(&data[30..32], &[0, 5]);
+        assert_eq!(&data[32..37], b"utf-8");
+        assert_eq!(data.last(), Some(&END_OF_ATTRIBUTES));
+    }
+
+    #[test]
+    fn attributes_survive_a_round_trip() {
+        let request = Request::new(PRINT_JOB, "ipp://host/p")
+            .attribute("job-name", Value::Name("Cards".into()))
+            .group(JOB_ATTRIBUTES)
+            .attribute("copies", Value::Integer(2))
+            .attribute("fit-to-page", Value::Boolean(true))
+            .attribute("orientation-requested", Value::Enum(4))
+            .attribute("media", Value::Keyword("A4".into()))
+            // additional values of a set are written without name
+            .attribute("", Value::Keyword("A5".into()))
+            .attribute("", Value::Keyword("A6".into()))
+            .attribute("custom", Value::Other(0x30, vec![1, 2]));
+        // the operation takes the place of the status
+        let response = Response::parse(&encode(request)).unwrap();
+        assert_eq!(response.status, PRINT_JOB);
+        assert_eq!(
+            response.value("printer-uri"),
+            Some(&Value::Uri("ipp://host/p".into()))
+        );
+        assert_eq!(response.text("job-name"), Some("Cards"));
+        assert_eq!(response.integer("copies"), Some(2));
+        assert_eq!(
+            response.value("fit-to-page"),
+            Some(&Value::Boolean(true))
+        );
+        assert_eq!(
+            response.integer("orientation-requested"),
+            Some(4)
+        );
+        let media: Vec<&Value> = response
+            .attributes
+            .iter()
+            .filter(|(name, _)| name == "media")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(
+            media,
+            [
+                &Value::Keyword("A4".into()),
+                &Value::Keyword("A5".into()),
+                &Value::Keyword("A6".into()),
+            ]
+        );
+        assert_eq!(
+            response.value("custom"),
+            Some(&Value::Other(0x30, vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn truncated_responses_are_rejected() {
+        let data =
+            response(0, vec![("job-id", Value::Integer(42))]);
+        assert!(Response::parse(&data).is_some());
+        for end in 0..data.len() {
+            assert!(
+                Response::parse(&data[..end]).is_none(),
+                "{} bytes",
+                end
+            );
+        }
+        // a set can not start with an additional value
+        let mut nameless = response(0, vec![]);
+        let _ = nameless.pop();
+        let nameless = encode(
+            Request { data: nameless }
+                .attribute("", Value::Integer(1)),
+        );
+        assert!(Response::parse(&nameless).is_none());
+    }
+
+    #[test]
+    fn uris_use_http_on_the_ipp_port() {
+        assert_eq!(
+            http_url("ipp://printer/ipp/print").unwrap().as_str(),
+            "http://printer:631/ipp/print"
+        );
+        assert_eq!(
+            http_url("ipps://printer:8631/ipp").unwrap().as_str(),
+            "https://printer:8631/ipp"
+        );
+        assert_eq!(
+            http_url("http://printer/ipp").unwrap().as_str(),
+            "http://printer/ipp"
+        );
+        assert!(http_url("printer").is_err());
+    }
+
+    #[test]
+    fn print_job_submits_the_pdf_and_waits_for_it() {
+        let (uri, server) = serve(vec![
+            response(0, vec![("job-id", Value::Integer(42))]),
+            response(
+                0,
+                vec![("job-state", Value::Enum(JOB_COMPLETED))],
+            ),
+        ]);
+        let job = print_job(
+            &uri,
+            "Cards",
+            b"%PDF-1.3",
+            vec![("copies", Value::Integer(2))],
+        )
+        .unwrap();
+        assert_eq!(job, 42);
+        let state =
+            wait_for_job(&uri, job, Duration::from_secs(1))
+                .unwrap();
+        assert_eq!(state, JobState::Completed);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].ends_with(b"\x03%PDF-1.3"));
+        let print = Response::parse(&requests[0]).unwrap();
+        assert_eq!(print.status, PRINT_JOB);
+        assert_eq!(print.text("job-name"), Some("Cards"));
+        assert_eq!(print.integer("copies"), Some(2));
+        let query = Response::parse(&requests[1]).unwrap();
+        assert_eq!(query.status, GET_JOB_ATTRIBUTES);
+        assert_eq!(query.integer("job-id"), Some(42));
+    }
+
+    #[test]
+    fn canceled_and_aborted_jobs_are_reported() {
+        let (uri, server) = serve(vec![
+            response(
+                0,
+                vec![("job-state", Value::Enum(JOB_CANCELED))],
+            ),
+            response(
+                0,
+                vec![("job-state", Value::Enum(JOB_ABORTED))],
+            ),
+        ]);
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            wait_for_job(&uri, 1, timeout).unwrap(),
+            JobState::Canceled
+        );
+        assert_eq!(
+            wait_for_job(&uri, 1, timeout).unwrap(),
+            JobState::Aborted
+        );
+        let _ = server.join().unwrap();
+    }
+
+    #[test]
+    fn error_statuses_fail_the_request() {
+        let (uri, server) = serve(vec![response(
+            0x0400,
+            vec![(
+                "status-message",
+                Value::Keyword("bad request".into()),
+            )],
+        )]);
+        let err = print_job(&uri, "Cards", b"", Vec::new())
+            .unwrap_err();
+        let message = format!("{:?}", err);
+        assert!(message.contains("0x0400"), "{}", message);
+        assert!(message.contains("bad request"), "{}", message);
+        let _ = server.join().unwrap();
+    }
+
+    #[test]
+    fn cancel_job_sends_the_job_id() {
+        let (uri, server) = serve(vec![
+            response(0, vec![]),
+            response(0x0507, vec![]),
+        ]);
+        cancel_job(&uri, 42).unwrap();
+        // the job already ended
+        assert!(cancel_job(&uri, 42).is_err());
+        let requests = server.join().unwrap();
+        let cancel = Response::parse(&requests[0]).unwrap();
+        assert_eq!(cancel.status, CANCEL_JOB);
+        assert_eq!(cancel.integer("job-id"), Some(42));
+    }
+}
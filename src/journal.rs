@@ -0,0 +1,216 @@
+use crate::{config::Config, services::Ticket, Result};
+use directories::BaseDirs;
+use failure::ResultExt;
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+};
+
+const DIR_NAME: &str = "ticket_printer";
+const FILE_NAME: &str = "journal.json";
+
+/// Tickets which are consumed, but neither printed nor reverted yet.
+/// Kept on disk, so tickets of an interrupted run can be marked for
+/// printing again.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Uses `global.journal` or `journal.json` in the local data
+    /// directory. Returns `None` if neither is available.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .global
+            .as_ref()
+            .and_then(|global| global.journal.as_ref())
+            .map(PathBuf::from)
+            .or_else(|| {
+                BaseDirs::new().map(|dirs| {
+                    dirs.data_local_dir()
+                        .join(DIR_NAME)
+                        .join(FILE_NAME)
+                })
+            })
+            .map(|path| Self { path })
+    }
+
+    #[cfg(test)]
+    pub const fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn tickets(&self) -> Result<Vec<Ticket>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = fs::read_to_string(&self.path).with_context(
+            |_| {
+                format!(
+                    "Could not read journal {}",
+                    self.path.display()
+                )
+            },
+        )?;
+        let tickets =
+            serde_json::from_str(&text).with_context(|_| {
+                format!(
+                    "Could not parse journal {}",
+                    self.path.display()
+                )
+            })?;
+        Ok(tickets)
+    }
+
+    /// Renames an unreadable journal to `journal.corrupt`, so new
+    /// tickets can be journaled again. Returns the new path.
+    pub fn set_aside(&self) -> Result<PathBuf> {
+        let path = self.path.with_extension("corrupt");
+        fs::rename(&self.path, &path).with_context(|_| {
+            format!(
+                "Could not move journal {} aside",
+                self.path.display()
+            )
+        })?;
+        Ok(path)
+    }
+
+    /// Records a ticket before it is consumed
+    pub fn add(&self, ticket: &Ticket) -> Result<()> {
+        let mut tickets = self.tickets()?;
+        tickets.push(ticket.clone());
+        self.write(&tickets)
+    }
+
+    pub fn remove(&self, ticket: &Ticket) -> Result<()> {
+        let mut tickets = self.tickets()?;
+        let count = tickets.len();
+        tickets.retain(|entry| {
+            entry.source != ticket.source || entry.id != ticket.id
+        });
+        if tickets.len() == count {
+            return Ok(());
+        }
+        self.write(&tickets)
+    }
+
+    /// Replaces the journal by renaming a synced temporary file, so a
+    /// crash never leaves a partially written journal behind
+    fn write(&self, tickets: &[Ticket]) -> Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let write =
+            || -> std::result::Result<(), failure::Error> {
+                if let Some(dir) = self.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut file = File::create(&tmp_path)?;
+                file.write_all(
+                    serde_json::to_string_pretty(tickets)?
+                        .as_bytes(),
+                )?;
+                file.sync_all()?;
+                fs::rename(&tmp_path, &self.path)?;
+                Ok(())
+            };
+        write().with_context(|_| {
+            format!(
+                "Could not write journal {}",
+                self.path.display()
+            )
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn journal() -> (TempDir, Journal) {
+        let dir = tempdir().unwrap();
+        let journal =
+            Journal::new(dir.path().join("data").join(FILE_NAME));
+        (dir, journal)
+    }
+
+    fn ticket(source: &str, id: &str) -> Ticket {
+        Ticket {
+            id: id.into(),
+            label_id: "print".into(),
+            titel: "Titel".into(),
+            subtitel: format!("#{}", id),
+            url: String::new(),
+            source: source.into(),
+            fields: vec![(
+                "list".to_string(),
+                "Todo".to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn ids(journal: &Journal) -> Vec<String> {
+        journal
+            .tickets()
+            .unwrap()
+            .into_iter()
+            .map(|ticket| {
+                format!("{} {}", ticket.source, ticket.id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn missing_journal_is_empty() {
+        let (_dir, journal) = journal();
+        assert!(journal.tickets().unwrap().is_empty());
+        // removing creates no file
+        journal.remove(&ticket("Trello", "1")).unwrap();
+        assert!(!journal.path.exists());
+    }
+
+    #[test]
+    fn tickets_are_added_and_removed() {
+        let (_dir, journal) = journal();
+        journal.add(&ticket("Trello", "1")).unwrap();
+        journal.add(&ticket("Trello", "2")).unwrap();
+        journal.add(&ticket("GitHub", "1")).unwrap();
+        assert_eq!(
+            ids(&journal),
+            ["Trello 1", "Trello 2", "GitHub 1"]
+        );
+        let tickets = journal.tickets().unwrap();
+        assert_eq!(tickets[0].subtitel, "#1");
+        assert_eq!(tickets[0].fields["list"], "Todo");
+        journal.remove(&ticket("Trello", "1")).unwrap();
+        journal.remove(&ticket("Jira", "2")).unwrap();
+        assert_eq!(ids(&journal), ["Trello 2", "GitHub 1"]);
+    }
+
+    #[test]
+    fn writing_leaves_no_temporary_file() {
+        let (dir, journal) = journal();
+        journal.add(&ticket("Trello", "1")).unwrap();
+        let files: Vec<_> = fs::read_dir(dir.path().join("data"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, [FILE_NAME]);
+    }
+
+    #[test]
+    fn corrupt_journal_is_an_error_and_can_be_set_aside() {
+        let (_dir, journal) = journal();
+        journal.add(&ticket("Trello", "1")).unwrap();
+        fs::write(&journal.path, "[{").unwrap();
+        assert!(journal.tickets().is_err());
+        assert!(journal.add(&ticket("Trello", "2")).is_err());
+        let aside = journal.set_aside().unwrap();
+        assert_eq!(fs::read_to_string(aside).unwrap(), "[{");
+        assert!(journal.tickets().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    config::{LabelLanguage, LabelPrinter, Printer},
+    pdf,
+    raster::{self, Bitmap},
+    Result,
+};
+use failure::ResultExt;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    net::{TcpStream, ToSocketAddrs},
+    result,
+    time::Duration,
+};
+
+const TCP_PREFIX: &str = "tcp://";
+/// Raw printing port of network printers
+const RAW_PORT: u16 = 9100;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const ZPL_DPI: u32 = 203;
+const BROTHER_DPI: u32 = 300;
+/// Dots of the print head of 62 mm Brother QL printers
+const BROTHER_PINS: usize = 720;
+/// Zero bytes clearing a previously aborted job
+const BROTHER_INVALIDATE: usize = 200;
+/// IPP orientation-requested value of landscape
+const LANDSCAPE: i32 = 4;
+
+/// Resolution the cards are rendered in
+pub fn dpi(label: &LabelPrinter) -> u32 {
+    label.dpi.unwrap_or(match label.language {
+        LabelLanguage::Zpl => ZPL_DPI,
+        LabelLanguage::BrotherQl => BROTHER_DPI,
+    })
+}
+
+/// Prints the labels of one ticket `printer.number_of_copies` times.
+/// Labels are turned a quarter for the landscape orientation.
+pub fn print(
+    printer: &Printer,
+    label: &LabelPrinter,
+    labels: &[Bitmap],
+) -> Result<()> {
+    let labels: Vec<Bitmap> =
+        if pdf::orientation(&printer.orientation)
+            == Some(LANDSCAPE)
+        {
+            labels.iter().map(Bitmap::rotated).collect()
+        } else {
+            labels.to_vec()
+        };
+    let data = match label.language {
+        LabelLanguage::Zpl => {
+            zpl(&labels, printer.number_of_copies)
+        }
+        LabelLanguage::BrotherQl => {
+            brother_ql(&labels, printer.number_of_copies)?
+        }
+    };
+    send(&label.output, &data).with_context(|_| {
+        format!("Could not send labels to {}", label.output)
+    })?;
+    Ok(())
+}
+
+/// One ZPL format per label, each printing the whole label as graphic
+fn zpl(labels: &[Bitmap], copies: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    for label in labels {
+        let bytes_per_row = (label.width + 7) >> 3;
+        let total = bytes_per_row * label.height;
+        data.extend(
+            format!(
+                "^XA^PW{}^LL{}^LH0,0^FO0,0^GFA,{},{},{},",
+                label.width,
+                label.height,
+                total,
+                total,
+                bytes_per_row
+            )
+            .into_bytes(),
+        );
+        for byte in label.rows().flat_map(raster::pack) {
+            data.extend(format!("{:02X}", byte).into_bytes());
+        }
+        data.extend(
+            format!("^FS^PQ{}^XZ\n", copies).into_bytes(),
+        );
+    }
+    data
+}
+
+/// Raster commands of the Brother QL series. The media is not
+/// validated, so continuous and die cut labels work alike.
+fn brother_ql(labels: &[Bitmap], copies: u16) -> Result<Vec<u8>> {
+    if let Some(label) =
+        labels.iter().find(|label| label.width > BROTHER_PINS)
+    {
+        return Err(failure::format_err!(
+            "Labels are {} dots wide, but Brother QL print heads only have {}. Try another printer.orientation",
+            label.width,
+            BROTHER_PINS
+        )
+        .into());
+    }
+    let mut data = vec![0; BROTHER_INVALIDATE];
+    // initialize, raster mode, auto cut, cut every label, no margin
+    data.extend(b"\x1b@\x1bia\x01\x1biM\x40\x1biA\x01\x1biK\x08\x1bid\x00\x00");
+    let pages: Vec<&Bitmap> =
+        (0..copies).flat_map(|_| labels).collect();
+    for (index, label) in pages.iter().enumerate() {
+        data.extend(b"\x1biz\x80\x00\x00\x00");
+        data.extend(&(label.height as u32).to_le_bytes());
+        data.extend(&[u8::from(index > 0), 0]);
+        // labels are centered on the head and printed mirrored
+        let padding = (BROTHER_PINS - label.width) >> 1;
+        for row in label.rows() {
+            let dots: Vec<bool> = std::iter::repeat_n(
+                false,
+                BROTHER_PINS - padding - label.width,
+            )
+            .chain(row.iter().rev().copied())
+            .chain(std::iter::repeat_n(false, padding))
+            .collect();
+            let bytes = raster::pack(&dots);
+            data.extend(&[b'g', 0, bytes.len() as u8]);
+            data.extend(bytes);
+        }
+        // form feed between labels, print and feed after the last
+        data.push(if index + 1 == pages.len() {
+            0x1a
+        } else {
+            0x0c
+        });
+    }
+    Ok(data)
+}
+
+/// Writes the data to a network printer or a file or device
+pub fn send(
+    output: &str,
+    data: &[u8],
+) -> result::Result<(), failure::Error> {
+    if output.starts_with(TCP_PREFIX) {
+        let address = output.trim_start_matches(TCP_PREFIX);
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, RAW_PORT)
+        };
+        let socket = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                failure::format_err!(
+                    "Unknown printer {}",
+                    address
+                )
+            })?;
+        let mut stream =
+            TcpStream::connect_timeout(&socket, TIMEOUT)?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        stream.write_all(data)?;
+    } else {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(output)?
+            .write_all(data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{layout::Color, pdf::Canvas};
+    use std::{io::Read, net::TcpListener, thread};
+
+    /// 10 x 2 dots with the left 4 dots of both rows printed
+    fn bitmap() -> Bitmap {
+        let mut bitmap = Bitmap::new(1.0, 0.2, 254);
+        bitmap.fill_polygon(
+            Color::rgb(0, 0, 0),
+            &[(0.0, 0.0), (0.4, 0.0), (0.4, 0.2), (0.0, 0.2)],
+        );
+        bitmap
+    }
+
+    #[test]
+    fn zpl_prints_labels_as_graphic_fields() {
+        let data = zpl(&[bitmap()], 2);
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "^XA^PW10^LL2^LH0,0^FO0,0^GFA,4,4,2,F000F000^FS^PQ2^XZ\n"
+        );
+    }
+
+    #[test]
+    fn brother_ql_sends_mirrored_raster_lines() {
+        let data = brother_ql(&[bitmap()], 2).unwrap();
+        let (invalidate, data) =
+            data.split_at(BROTHER_INVALIDATE);
+        assert!(invalidate.iter().all(|&byte| byte == 0));
+        let init: &[u8] = b"\x1b@\x1bia\x01\x1biM\x40\x1biA\x01\x1biK\x08\x1bid\x00\x00";
+        let (start, data) = data.split_at(init.len());
+        assert_eq!(start, init);
+
+        let mut row = vec![b'g', 0, 90];
+        row.extend(vec![0; 90]);
+        // the 4 dots end up right of the center once mirrored
+        row[3 + 45] = 0x78;
+        let mut page =
+            b"\x1biz\x80\x00\x00\x00\x02\x00\x00\x00".to_vec();
+        page.extend(&[0, 0]);
+        page.extend(&row);
+        page.extend(&row);
+        page.push(0x0c);
+        page.extend(b"\x1biz\x80\x00\x00\x00\x02\x00\x00\x00");
+        page.extend(&[1, 0]);
+        page.extend(&row);
+        page.extend(&row);
+        page.push(0x1a);
+        assert_eq!(data, page);
+    }
+
+    #[test]
+    fn brother_ql_rejects_labels_wider_than_the_head() {
+        let label = Bitmap::new(100.0, 10.0, 300);
+        assert!(label.width > BROTHER_PINS);
+        assert!(brother_ql(&[label], 1).is_err());
+    }
+
+    #[test]
+    fn send_writes_to_network_printers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut data = Vec::new();
+            let _ = stream.read_to_end(&mut data).unwrap();
+            data
+        });
+        send(&format!("tcp://127.0.0.1:{}", port), b"^XA^XZ")
+            .unwrap();
+        assert_eq!(server.join().unwrap(), b"^XA^XZ");
+    }
+
+    #[test]
+    fn send_writes_to_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("labels.zpl");
+        let output = path.to_str().unwrap();
+        send(output, b"first").unwrap();
+        send(output, b"^XA^XZ").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"^XA^XZ");
+    }
+}
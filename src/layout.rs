@@ -0,0 +1,554 @@
+use crate::config::PDfDimension;
+use failure::ResultExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap, convert::TryFrom, fs, path::Path,
+};
+
+/// The regions drawn onto a card, in drawing order
+#[derive(Deserialize, Debug)]
+pub struct Layout {
+    pub regions: Vec<Region>,
+    /// Lower case ticket values and their band colour, taken from
+    /// `pdf.colors`
+    #[serde(skip)]
+    pub colors: BTreeMap<String, Color>,
+}
+
+/// A rectangle on the card. `x` and `y` are the lower left corner,
+/// measured from the lower left corner of the card.
+#[derive(Deserialize, Debug)]
+pub struct Region {
+    #[serde(default)]
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(flatten)]
+    pub content: Content,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Content {
+    Text(TextBox),
+    Qrcode {
+        #[serde(default = "default_qrcode_data")]
+        data: String,
+    },
+    /// Code 39 barcode stretched over the region
+    Barcode {
+        #[serde(default = "default_barcode_data")]
+        data: String,
+    },
+    /// Rectangle or corner triangle filled with `color`. With a
+    /// `value` like `{fields.priority}` the colour comes from
+    /// `pdf.colors` and `color` is only the fallback.
+    Band {
+        #[serde(default)]
+        color: Option<Color>,
+        #[serde(default)]
+        value: Option<String>,
+        #[serde(default)]
+        shape: Shape,
+    },
+    Image {
+        path: String,
+    },
+    /// Issue type icon drawn into the largest square of the region
+    Icon {
+        #[serde(default = "default_icon_data")]
+        data: String,
+    },
+}
+
+/// Text wrapped into at most `lines` lines. Every line of `text` starts
+/// a new paragraph, paragraphs without any value are skipped. The font
+/// size defaults to the height of the region divided by `lines`.
+#[derive(Deserialize, Debug)]
+pub struct TextBox {
+    pub text: String,
+    #[serde(default = "default_lines")]
+    pub lines: u32,
+    #[serde(default)]
+    pub size: Option<f32>,
+    /// Enables fitting. The font shrinks from `size` down to
+    /// `min_size` until the text fits, using as many lines as the
+    /// region allows. Text still too long is ellipsized.
+    #[serde(default)]
+    pub min_size: Option<f32>,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub valign: VAlign,
+    /// Builtin font or path to a ttf file. Defaults to
+    /// `pdf.font`
+    #[serde(default)]
+    pub font: Option<FontName>,
+    /// Reads the filled in text as markdown, printing bold, italic
+    /// and code in the matching font and lists with bullets
+    #[serde(default)]
+    pub markdown: bool,
+}
+
+const fn default_lines() -> u32 {
+    1
+}
+
+fn default_qrcode_data() -> String {
+    "{url}".into()
+}
+
+fn default_barcode_data() -> String {
+    "{key}".into()
+}
+
+fn default_icon_data() -> String {
+    "{fields.issuetype}".into()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Area of a band. Triangles fill half of the region with the right
+/// angle in the named corner.
+#[derive(
+    Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    #[default]
+    Rectangle,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FontName {
+    Builtin(TextFont),
+    File(String),
+}
+
+#[derive(
+    Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextFont {
+    #[default]
+    Helvetica,
+    HelveticaBold,
+    HelveticaOblique,
+    HelveticaBoldOblique,
+    Times,
+    TimesBold,
+    TimesItalic,
+    TimesBoldItalic,
+    Courier,
+    CourierBold,
+    CourierOblique,
+    CourierBoldOblique,
+}
+
+/// A colour written as hex string like `#ff8800`
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.trim_start_matches('#');
+        let channel = |index: usize| {
+            let c = hex.get(index..index + 2)?;
+            u8::from_str_radix(c, 16).ok()
+        };
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(red), Some(green), Some(blue)) => {
+                Ok(Self { red, green, blue })
+            }
+            _ => Err(format!(
+                "Invalid colour {}. Expected #rrggbb",
+                value
+            )),
+        }
+    }
+}
+
+impl Color {
+    pub const fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            color.red, color.green, color.blue
+        )
+    }
+}
+
+impl Layout {
+    /// Reads the layout file configured in `pdf.layout` or falls back
+    /// to the classic card built from the pdf dimensions
+    pub fn load(
+        pdf: &PDfDimension,
+    ) -> Result<Self, failure::Error> {
+        let layout = match pdf.layout {
+            Some(ref path) => Self::from_file(path)?,
+            None => Self::from_dimension(pdf),
+        };
+        Ok(layout.with_colors(pdf))
+    }
+
+    /// Reads the layout of the back configured in `pdf.back_layout`
+    /// or builds it from `pdf.back_lines`. Returns `None` if cards
+    /// have no back.
+    pub fn load_back(
+        pdf: &PDfDimension,
+    ) -> Result<Option<Self>, failure::Error> {
+        let layout = if let Some(ref path) = pdf.back_layout {
+            Self::from_file(path)?
+        } else if pdf.back_lines.is_empty() {
+            return Ok(None);
+        } else {
+            Self::back_from_dimension(pdf)
+        };
+        Ok(Some(layout.with_colors(pdf)))
+    }
+
+    fn with_colors(mut self, pdf: &PDfDimension) -> Self {
+        self.colors = pdf
+            .colors
+            .iter()
+            .map(|(value, color)| (value.to_lowercase(), *color))
+            .collect();
+        self
+    }
+
+    fn from_file(path: &str) -> Result<Self, failure::Error> {
+        let text =
+            fs::read_to_string(path).with_context(|_| {
+                format!("Could not read layout file {}", path)
+            })?;
+        let format = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("toml")
+            .to_lowercase();
+        let layout = match format.as_str() {
+            "toml" => toml::from_str(&text)
+                .map_err(failure::Error::from),
+            "yaml" | "yml" => serde_yaml::from_str(&text)
+                .map_err(failure::Error::from),
+            "json" => serde_json::from_str(&text)
+                .map_err(failure::Error::from),
+            _ => Err(failure::format_err!(
+                "Unsupported layout file format {}",
+                format
+            )),
+        }
+        .with_context(|_| {
+            format!("Could not parse layout file {}", path)
+        })?;
+        Ok(layout)
+    }
+
+    /// QR code bottom left, title on top, subtitle and field lines
+    /// bottom right
+    pub fn from_dimension(pdf: &PDfDimension) -> Self {
+        let half_height = (pdf.height / 2.0)
+            - pdf.margin
+            - (pdf.title_seperator_margin / 2.0);
+        let text_width = 2.0_f32.mul_add(-pdf.margin, pdf.width);
+        let mut regions = vec![
+            Region {
+                name: "qrcode".into(),
+                x: pdf.margin,
+                y: pdf.margin,
+                width: (pdf.width / 2.0)
+                    - pdf.margin
+                    - (pdf.qrcode_seperator_margin / 2.0),
+                height: half_height,
+                content: Content::Qrcode {
+                    data: default_qrcode_data(),
+                },
+            },
+            Region {
+                name: "title".into(),
+                x: pdf.margin,
+                y: pdf.height - half_height,
+                width: text_width,
+                height: half_height,
+                content: Content::Text(TextBox {
+                    text: "{titel}".into(),
+                    lines: pdf.title_lines,
+                    size: pdf.title_max_size,
+                    min_size: pdf.title_min_size,
+                    align: Align::Center,
+                    valign: VAlign::Top,
+                    font: None,
+                    markdown: false,
+                }),
+            },
+            Region {
+                name: "subtitle".into(),
+                x: pdf.margin,
+                y: pdf.margin,
+                width: text_width,
+                height: pdf.subtitle_size,
+                content: Content::Text(TextBox {
+                    text: "{subtitel}".into(),
+                    lines: 1,
+                    size: Some(pdf.subtitle_size),
+                    min_size: None,
+                    align: Align::Right,
+                    valign: VAlign::Bottom,
+                    font: None,
+                    markdown: false,
+                }),
+            },
+        ];
+        if !pdf.field_lines.is_empty() {
+            let lines = pdf.field_lines.len() as u32;
+            regions.push(Region {
+                name: "field_lines".into(),
+                x: pdf.margin,
+                y: pdf.margin + pdf.subtitle_size,
+                width: text_width,
+                height: pdf.subtitle_size * lines as f32,
+                content: Content::Text(TextBox {
+                    text: pdf.field_lines.join("\n"),
+                    lines,
+                    size: Some(pdf.subtitle_size),
+                    min_size: None,
+                    align: Align::Right,
+                    valign: VAlign::Bottom,
+                    font: None,
+                    markdown: false,
+                }),
+            });
+        }
+        Self {
+            regions,
+            colors: BTreeMap::new(),
+        }
+    }
+
+    /// Subtitle on top, back lines filling the rest of the card
+    fn back_from_dimension(pdf: &PDfDimension) -> Self {
+        let text_width = 2.0_f32.mul_add(-pdf.margin, pdf.width);
+        let text_height = 2.0_f32
+            .mul_add(-pdf.margin, pdf.height)
+            - pdf.subtitle_size
+            - pdf.title_seperator_margin;
+        let lines =
+            ((text_height / pdf.subtitle_size) + 0.001).floor();
+        let regions = vec![
+            Region {
+                name: "subtitle".into(),
+                x: pdf.margin,
+                y: pdf.height - pdf.margin - pdf.subtitle_size,
+                width: text_width,
+                height: pdf.subtitle_size,
+                content: Content::Text(TextBox {
+                    text: "{subtitel}".into(),
+                    lines: 1,
+                    size: Some(pdf.subtitle_size),
+                    min_size: None,
+                    align: Align::Right,
+                    valign: VAlign::Top,
+                    font: None,
+                    markdown: false,
+                }),
+            },
+            Region {
+                name: "back_lines".into(),
+                x: pdf.margin,
+                y: pdf.margin,
+                width: text_width,
+                height: text_height,
+                content: Content::Text(TextBox {
+                    text: pdf.back_lines.join("\n"),
+                    lines: lines.max(1.0) as u32,
+                    size: Some(pdf.subtitle_size),
+                    min_size: None,
+                    align: Align::Left,
+                    valign: VAlign::Top,
+                    font: None,
+                    markdown: true,
+                }),
+            },
+        ];
+        Self {
+            regions,
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_parse_with_defaults() {
+        let layout: Layout = toml::from_str(
+            r#"
+            [[regions]]
+            type = "text"
+            text = "{titel}"
+            x = 1.0
+            y = 2.0
+            width = 30.0
+            height = 10.0
+            lines = 2
+
+            [[regions]]
+            type = "barcode"
+            x = 0.0
+            y = 0.0
+            width = 40.0
+            height = 5.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(layout.regions.len(), 2);
+        match layout.regions[0].content {
+            Content::Text(ref text) => {
+                assert_eq!(text.text, "{titel}");
+                assert_eq!(text.lines, 2);
+                assert!(text.size.is_none());
+                assert!(!text.markdown);
+            }
+            ref content => panic!("Unexpected {:?}", content),
+        }
+        match layout.regions[1].content {
+            Content::Barcode { ref data } => {
+                assert_eq!(data, "{key}")
+            }
+            ref content => panic!("Unexpected {:?}", content),
+        }
+    }
+
+    #[test]
+    fn unknown_region_types_are_rejected() {
+        let layout = toml::from_str::<Layout>(
+            r#"
+            [[regions]]
+            type = "chart"
+            x = 0.0
+            y = 0.0
+            width = 1.0
+            height = 1.0
+            "#,
+        );
+        assert!(layout.is_err());
+    }
+
+    #[test]
+    fn classic_card_fills_the_dimension() {
+        let pdf = PDfDimension {
+            field_lines: vec!["{fields.priority}".into()],
+            ..PDfDimension::default()
+        };
+        let layout = Layout::from_dimension(&pdf);
+        let names = layout
+            .regions
+            .iter()
+            .map(|region| region.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            names,
+            ["qrcode", "title", "subtitle", "field_lines"]
+        );
+        for region in &layout.regions {
+            assert!(region.x >= pdf.margin);
+            assert!(region.y >= pdf.margin);
+            assert!(
+                region.x + region.width <= pdf.width - pdf.margin
+            );
+            // like the original card the title reaches the top edge
+            assert!(region.y + region.height <= pdf.height);
+        }
+    }
+
+    #[test]
+    fn cards_without_back_lines_have_no_back() {
+        let pdf = PDfDimension::default();
+        assert!(Layout::load_back(&pdf).unwrap().is_none());
+    }
+
+    #[test]
+    fn back_lines_fill_the_back_as_markdown() {
+        let pdf = PDfDimension {
+            back_lines: vec![
+                "{fields.description}".into(),
+                "{fields.check_items}".into(),
+            ],
+            ..PDfDimension::default()
+        };
+        let back = Layout::load_back(&pdf).unwrap().unwrap();
+        assert_eq!(back.regions.len(), 2);
+        match back.regions[1].content {
+            Content::Text(ref text) => {
+                assert_eq!(
+                    text.text,
+                    "{fields.description}\n{fields.check_items}"
+                );
+                assert!(text.markdown);
+                // 46 mm below the subtitle hold 11 lines of 4 mm
+                assert_eq!(text.lines, 11);
+            }
+            ref content => panic!("Unexpected {:?}", content),
+        }
+    }
+
+    #[test]
+    fn colors_are_parsed_from_hex() {
+        assert_eq!(
+            Color::try_from(String::from("#ff8800")),
+            Ok(Color::rgb(0xff, 0x88, 0x00))
+        );
+        assert_eq!(
+            Color::try_from(String::from("0A0b0C")),
+            Ok(Color::rgb(0x0a, 0x0b, 0x0c))
+        );
+        assert!(Color::try_from(String::from("#fff")).is_err());
+        assert!(Color::try_from(String::from("#gg0000")).is_err());
+        assert!(
+            Color::try_from(String::from("#ff88001")).is_err()
+        );
+        assert_eq!(
+            String::from(Color::rgb(0xff, 0x88, 0x00)),
+            "#ff8800"
+        );
+    }
+}
@@ -15,12 +15,90 @@
 //! title_seperator_margin = 4.0
 //! qrcode_seperator_margin = 4.0
 //! subtitle_size = 4.0
+//! # Optional font sizes for long titles. The title shrinks from
+//! # title_max_size (defaults to the size given by title_lines) down to
+//! # title_min_size until it fits. Only then it is cut off with "…"
+//! # title_min_size = 5.0
+//! # title_max_size = 12.0
+//! # Optional lines printed above the subtitle
+//! # Placeholders are replaced with ticket values, e.g. {fields.priority}
+//! # Lines without any value are skipped
+//! # field_lines = ["{fields.priority} {fields.points}", "{fields.assignee}"]
+//! # Optional layout file replacing the classic card described above
+//! # See the layout section below and config/layout.toml
+//! # layout = 'layout.toml'
+//! # Optional ttf font used for all card text. Embedded as
+//! # subset, required for characters outside of latin-1
+//! # font = '/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf'
+//! # Optional back side printed behind each card, e.g. with
+//! # printer.duplex. Lines are wrapped and cut off to fit, placeholders
+//! # are replaced with ticket values. Jira and Trello provide
+//! # {fields.description}, Trello cards also {fields.check_items}.
+//! # Markdown and Jira rich text descriptions keep their styling
+//! # back_lines = ["{fields.description}", "{fields.check_items}"]
+//! # Optional layout file for the back replacing back_lines
+//! # back_layout = 'back.toml'
+//! # Optional colours of ticket values for layout bands, e.g. Jira
+//! # priorities or Trello label names. Trello label colours like
+//! # green are known without entry
+//! # [pdf.colors]
+//! # highest = '#d32f2f'
+//! # low = '#43a047'
 //!
 //! [printer]
 //! media = 'Custom.62x100m'
 //! orientation = 'landscape'
 //! number_of_copies = 2
 //! name = '<printer name>'
+//! # Optional, IPP uri of the printer. Defaults to the cups queue name
+//! # on this machine, ipp://localhost:631/printers/<name>
+//! # uri = 'ipp://printer.local/ipp/print'
+//! # Optional, seconds to wait for the printer to complete a job.
+//! # Tickets of canceled or aborted jobs are marked for printing again,
+//! # so are those of jobs canceled after the timeout.
+//! # Defaults to 300, 0 does not wait
+//! # job_timeout = 300
+//! # Optional, prints both sides of the paper. Flips the paper around
+//! # its long-edge or short-edge
+//! # duplex = 'long-edge'
+//!
+//! # Optional, sends raster labels straight to a zebra (zpl) or brother
+//! # ql (brother-ql) label printer instead of printing pdfs over IPP.
+//! # printer.media and printer.name are ignored, landscape turns the
+//! # labels a quarter. Text needs pdf.font or fonts from files, backs
+//! # are printed as extra labels and sheets are not supported
+//! # [printer.label]
+//! # language = 'zpl'
+//! # tcp://<host>[:<port>] for network printers, port 9100 by default,
+//! # or a device like /dev/usb/lp0
+//! # output = 'tcp://192.168.1.50'
+//! # Defaults to 203 for zpl and 300 for brother-ql
+//! # dpi = 203
+//!
+//! # Optional, prints tickets as text on an ESC/POS receipt printer
+//! # instead of printing pdfs over IPP: title, key, pdf.field_lines and a
+//! # QR code of the url. Layouts and backs are not used, sheets and
+//! # printer.label are not supported
+//! # [printer.receipt]
+//! # Same as printer.label.output
+//! # output = 'tcp://192.168.1.60'
+//! # Characters per line, 32 for 58 mm and 48 for 80 mm paper
+//! # columns = 32
+//!
+//! # Optional, places many cards on each page of a sheet instead of
+//! # printing one pdf per card. All tickets of a run are printed as a
+//! # single job, printer.media and printer.orientation are ignored
+//! # [sheet]
+//! # a4, a3, letter, legal or <width>x<height> in millimeter
+//! # size = 'a4'
+//! # Defaults to as many cards as fit
+//! # columns = 2
+//! # rows = 4
+//! # margin = 10.0
+//! # gutter = 0.0
+//! # cut_marks = true
+//! # Dashed line across the middle of the sheet
+//! # fold_line = false
 //!
 //! # Comment out or remove if trello is not needed
 //! [trello]
@@ -31,14 +109,32 @@
 //! print_label = '<LABEL>'
 //! # Use an empty array to search all boards
 //! limit_to_boards = ["Example Board"]
+//! # Print the card id or the list name as subtitle
+//! # subtitle = 'list'
+//! # Cards provide the fields list, description, due, members,
+//! # labels, label_color, checklist, check_items and details for
+//! # pdf.field_lines. details combines the others, e.g.
+//! # "Due 2019-11-03 · @alice @bob · 3/5 ✓"
+//! # Optional actions once a card has been printed
+//! # [trello.after_print]
+//! # move_to_list = 'Printed'
+//! # comment = 'Printed on {printer} at {time}'
+//! # add_label = 'printed'
 //!
 //! # Comment out or remove if jira is not needed
 //! [jira]
 //! # Hostname only. Http is not supported
 //! # Example: test.atlassian.com
 //! host = '<JIRA HOSTNAME>'
+//! # Replaces host for Jira Server / Data Center with a context path
+//! # Example: https://intranet/jira/
+//! # base_url = '<JIRA URL>'
 //! user = '<USERNAME OR MAIL>'
 //! token = '<USER TOKEN>'
+//! # basic for user and api token, bearer for personal access tokens
+//! # auth = 'basic'
+//! # Version of the rest api used for search and label updates
+//! # api_version = '2'
 //! # Tickets are filtered using the following label
 //! # Label is removed after printing
 //! print_label = '<LABEL>'
@@ -46,11 +142,62 @@
 //! limit_to_types = ["Issue"]
 //! # Use an empty array to search all boards
 //! limit_to_projects = ["Example Board"]
+//! # Optional query replacing the limits above
+//! # {print_label} is replaced with the quoted print label
+//! # jql = 'labels = {print_label} AND sprint in openSprints() AND assignee = currentUser()'
+//! # Optional fields available as {fields.<name>} in pdf.field_lines
+//! # Maps a name to the jira field id, custom fields included.
+//! # description, priority and issuetype are always available
+//! # [jira.fields]
+//! # priority = 'priority'
+//! # assignee = 'assignee'
+//! # points = 'customfield_10016'
+//! # Optional actions once an issue has been printed
+//! # [jira.after_print]
+//! # transition = 'Ready'
+//! # comment = 'Printed on {printer} at {time}'
+//! # add_label = 'printed'
+//!
+//! # Comment out or remove if github is not needed
+//! [github]
+//! token = '<USER TOKEN>'
+//! # Issues are filtered using the following label
+//! # Label is removed after printing
+//! print_label = '<LABEL>'
+//! # Use an empty array to search all repositories
+//! limit_to_repositories = ["owner/repo"]
+//! # Only required for GitHub Enterprise
+//! # Example: https://github.example.com/api/v3
+//! # api_url = '<API URL>'
+//!
+//! # Comment out or remove if gitlab is not needed
+//! [gitlab]
+//! token = '<USER TOKEN>'
+//! # Issues are filtered using the following label
+//! # Label is removed after printing
+//! print_label = '<LABEL>'
+//! # Use empty arrays for projects and groups to search everything
+//! limit_to_projects = ["group/project"]
+//! limit_to_groups = []
+//! # Also print merge requests carrying the label
+//! merge_requests = false
+//! # Only required for self-hosted instances
+//! # url = 'https://gitlab.example.com'
+//!
+//! # Comment out or remove if no local ticket file is needed
+//! [local]
+//! # Path to a csv, json, yaml or toml file. Use '-' for stdin
+//! # Columns or keys: id (optional), titel, subtitel, url
+//! # Toml files list the tickets as [[tickets]]
+//! # Every run prints the whole file, so global.poll is not allowed
+//! path = 'tickets.csv'
+//! # Optional, defaults to the file extension
+//! # format = 'csv'
 //! ```
 //!
 //! There is also an example configuration available in the
-//! config folder. It is not necessary to define both jira and
-//! trello. Simply remove the configuration part for the service
+//! config folder. It is not necessary to define every
+//! service. Simply remove the configuration part for the service
 //! you don't want to use.
 //!
 //! There are three different locations for the configuration file.
@@ -68,9 +215,68 @@
 //! # qrcode
 //!
 //! The qrcode contains a directlink to the trello or jira
-//! ticket. It will, however, only show up if the url is small
-//! enough. In the default configuration, the url size may not
-//! be bigger then 23 lettes.
+//! ticket. Urls are shortened if possible, longer urls result
+//! in a denser qrcode.
+//!
+//! # Layout
+//!
+//! All dimensions are given in millimeter. Without `pdf.layout`
+//! the card shows the qrcode bottom left, the title on top and
+//! the subtitle bottom right. A layout file replaces this with a
+//! list of regions drawn in order. Each region has a lower left
+//! corner `x`, `y` (measured from the lower left corner of the
+//! card), a `width`, a `height` and one of the following types:
+//!   * `text`: `text` with placeholders like `{titel}`, `{key}`,
+//!     `{url}` or `{fields.priority}`. Optional `lines`, `size`,
+//!     `min_size` (shrinks the font down to this size until the
+//!     text fits, longer text ends with "…"),
+//!     `align` (left, center, right), `valign` (top, center,
+//!     bottom), `font` (helvetica, times or courier, each also
+//!     as -bold, helvetica and courier as -oblique and
+//!     -bold-oblique, times as -italic and -bold-italic, or the
+//!     path to a ttf file, defaults to `pdf.font`) and
+//!     `markdown` (prints bold, italic, code and lists of the
+//!     filled text, on by default for `pdf.back_lines`)
+//!   * `qrcode`: `data`, defaults to `{url}`
+//!   * `barcode`: Code 39 barcode of `data`, defaults to `{key}`
+//!   * `band`: rectangle filled with `color`, e.g. `#ff8800`.
+//!     With `value`, e.g. `{fields.priority}`, the colour comes
+//!     from `pdf.colors` and `color` is the fallback. `shape`
+//!     top-left, top-right, bottom-left or bottom-right fills the
+//!     triangle in that corner instead
+//!   * `image`: png or jpeg file at `path`
+//!   * `icon`: bug, story, task, subtask or epic icon for `data`,
+//!     defaults to `{fields.issuetype}`
+//!
+//! Layout files may be written in toml, yaml or json. See
+//! `config/layout.toml` for an example. `pdf.back_layout` uses
+//! the same format for the back of the card.
+//!
+//! # Recovery
+//!
+//! Tickets are written to a journal before their print marker
+//! is removed and leave it once they are printed or the marker
+//! is restored. If a run is interrupted, e.g. by a crash or a
+//! killed process, the next start restores the marker of every
+//! ticket left in the journal, so it is printed again. The
+//! journal defaults to `journal.json` in the local data
+//! directory, e.g. `~/.local/share/ticket_printer`, and can be
+//! moved using `global.journal`.
+//! A journal which cannot be read is reported and renamed to
+//! `journal.corrupt`. Tickets of a print job which is still
+//! pending when its timeout ends are left consumed and are not
+//! restored, as the printer may still print them.
+//!
+//! Requests to the trackers are retried if they cannot connect
+//! or hit a rate limit. `GET` and `PUT` requests are also
+//! retried if they time out or fail with a server error, unlike
+//! `POST`, `PATCH` and `DELETE`, which may already have taken
+//! effect. Retries wait about twice as long as the one before,
+//! or as long as the tracker asks for using `Retry-After`.
+//! `global.http_timeout` and `global.http_retries` change the
+//! defaults of 30 seconds and 3 retries. Tickets whose print
+//! marker cannot be restored are reported and stay in the
+//! journal.
 //!
 //! # Installation
 //!
@@ -89,11 +295,9 @@
 #![warn(
     absolute_paths_not_starting_with_crate,
     anonymous_parameters,
-    box_pointers,
     deprecated_in_future,
     elided_lifetimes_in_paths,
     explicit_outlives_requirements,
-    indirect_structural_match,
     keyword_idents,
     macro_use_extern_crate,
     meta_variable_misuse,
@@ -126,82 +330,130 @@
 #![allow(clippy::multiple_crate_versions)]
 // Allow some pedanctic
 #![allow(
-    clippy::integer_arithmetic,
+    clippy::arithmetic_side_effects,
     clippy::float_arithmetic,
     clippy::cast_precision_loss,
     clippy::cast_possible_truncation,
-    clippy::cast_sign_loss
+    clippy::cast_sign_loss,
+    clippy::uninlined_format_args,
+    clippy::doc_paragraphs_missing_punctuation,
+    clippy::semicolon_if_nothing_returned,
+    clippy::redundant_closure_for_method_calls
 )]
 // Allow some restriction
 #![allow(
+    clippy::blanket_clippy_restriction_lints,
     clippy::missing_docs_in_private_items,
     clippy::implicit_return,
     clippy::print_stdout,
-    clippy::wildcard_enum_match_arm
+    clippy::wildcard_enum_match_arm,
+    clippy::question_mark_used,
+    clippy::arbitrary_source_item_ordering,
+    clippy::missing_trait_methods,
+    clippy::missing_inline_in_public_items,
+    clippy::single_call_fn,
+    clippy::shadow_reuse,
+    clippy::shadow_same,
+    clippy::shadow_unrelated,
+    clippy::min_ident_chars,
+    clippy::single_char_lifetime_names,
+    clippy::let_underscore_untyped,
+    clippy::as_conversions,
+    clippy::ref_patterns,
+    clippy::pattern_type_mismatch,
+    clippy::unused_trait_names,
+    clippy::std_instead_of_core,
+    clippy::std_instead_of_alloc,
+    clippy::str_to_string,
+    clippy::default_numeric_fallback,
+    clippy::big_endian_bytes,
+    clippy::little_endian_bytes,
+    clippy::separated_literal_suffix,
+    clippy::absolute_paths,
+    clippy::mod_module_files,
+    clippy::allow_attributes,
+    clippy::allow_attributes_without_reason,
+    clippy::partial_pub_fields,
+    clippy::impl_trait_in_params,
+    clippy::else_if_without_else,
+    clippy::integer_division_remainder_used
+)]
+// Tests may panic
+#![cfg_attr(
+    test,
+    allow(
+        clippy::unwrap_used,
+        clippy::indexing_slicing,
+        clippy::panic,
+        clippy::assertions_on_result_states
+    )
 )]
 
 mod args;
+mod barcode;
 mod config;
+mod font;
+mod icon;
+mod ipp;
+mod journal;
+mod label;
+mod layout;
+mod markup;
 mod pdf;
+mod raster;
+mod receipt;
 mod services;
+mod sheet;
 
-use crate::{
-    config::Config, pdf::print_tickets, services::Ticket,
-};
+use crate::{pdf::print_tickets, services::Sources};
 use exitfailure::ExitFailure;
 use human_panic::setup_panic;
 use std::{env, process::exit, result, thread, time::Duration};
 
 type Result<T> = result::Result<T, ExitFailure>;
 
+// setup_panic uses the deprecated PanicInfo of human-panic, errors
+// of a run are reported on stderr with their causes
+#[allow(deprecated, clippy::print_stderr, clippy::use_debug)]
 fn main() -> Result<()> {
     setup_panic!();
     let args = args::handle()?;
     let mut config = config::get()?;
     args.merge_config(&mut config)?;
-    if !config.service_available() {
+    config::validate(&config)?;
+    pdf::validate(&config)?;
+    let sources = Sources::from_config(&config);
+    if sources.is_empty() {
         eprintln!("No Service configured. You may want to adopt the configuration file.");
         exit(1);
     }
+    sources.validate()?;
+    sources.recover()?;
+    let poll = config.global.as_ref().and_then(|g| g.poll);
     loop {
         let mut tickets = Vec::new();
-        if let Err(err) = fetch_tickets(&config, &mut tickets) {
-            revert_tickets(&config, &tickets);
-            return Err(err);
+        let mut failed = sources.fetch_tickets(&mut tickets);
+        match print_tickets(&config, &sources, &mut tickets) {
+            Ok(count) => failed += count,
+            Err(err) => {
+                eprintln!("ERROR: {:?}", err);
+                failed += tickets.len().max(1);
+                sources.revert_tickets(&tickets);
+            }
         }
-        if let Err(err) = print_tickets(&config, &mut tickets) {
-            revert_tickets(&config, &tickets);
-            return Err(err);
+        if failed > 0 {
+            eprintln!(
+                "WARN: {} failures in this run. Failed tickets are printed again on the next run",
+                failed
+            );
         }
-        if let Some(secs) =
-            config.global.as_ref().and_then(|g| g.poll)
-        {
-            thread::sleep(Duration::from_secs(secs));
-        } else {
-            break;
+        match poll {
+            Some(secs) => {
+                thread::sleep(Duration::from_secs(secs))
+            }
+            None if failed > 0 => exit(1),
+            None => break,
         }
     }
     Ok(())
 }
-
-fn fetch_tickets(
-    config: &Config,
-    tickets: &mut Vec<Ticket>,
-) -> Result<()> {
-    if let Some(ref trello) = config.trello {
-        services::trello::fetch_tickets(trello, tickets)?;
-    }
-    if let Some(ref jira) = config.jira {
-        services::jira::fetch_tickets(jira, tickets)?;
-    }
-    Ok(())
-}
-
-fn revert_tickets(config: &Config, tickets: &[Ticket]) {
-    if let Some(ref trello) = config.trello {
-        services::trello::revert_tickets(trello, tickets);
-    }
-    if let Some(ref jira) = config.jira {
-        services::jira::revert_tickets(jira, tickets);
-    }
-}
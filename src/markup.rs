@@ -0,0 +1,717 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde_json::Value;
+use std::{mem, slice};
+
+/// Characters escaped when writing text as markdown
+const MARKDOWN_SPECIAL: &str = "\\`*_[]#<>~|";
+
+/// Font style of a run of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Code,
+}
+
+/// Text printed in a single style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Runs starting on a new line
+pub type Paragraph = Vec<Run>;
+
+/// Every line of `text` as a paragraph without any styling
+pub fn plain(text: &str) -> Vec<Paragraph> {
+    text.lines()
+        .map(|line| {
+            vec![Run {
+                text: line.into(),
+                style: Style::Regular,
+            }]
+        })
+        .collect()
+}
+
+/// Converts markdown into styled paragraphs. Headings are printed
+/// bold, list items start with a bullet or their number and task list
+/// items with `[ ]` or `[x]`. Images, html and rules are dropped,
+/// links are reduced to their text.
+pub fn markdown(text: &str) -> Vec<Paragraph> {
+    let options = Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TABLES;
+    let mut builder = Builder::default();
+    for event in Parser::new_ext(text, options) {
+        builder.handle(event);
+    }
+    builder.finish_paragraph();
+    builder.paragraphs
+}
+
+#[derive(Debug, Default)]
+struct Builder {
+    paragraphs: Vec<Paragraph>,
+    current: Paragraph,
+    bold: u32,
+    italic: u32,
+    images: u32,
+    code_block: bool,
+    /// Next number of each open list, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// Marker of a list item printed in front of its first text
+    marker: Option<(String, String)>,
+}
+
+impl Builder {
+    fn handle(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(&tag),
+            Event::End(tag) => self.end(&tag),
+            Event::Text(text) => {
+                if self.images > 0 {
+                    return;
+                }
+                if self.code_block {
+                    for line in text.lines() {
+                        self.push(line, Style::Code);
+                        self.finish_paragraph();
+                    }
+                } else {
+                    self.push(&text, self.style());
+                }
+            }
+            Event::Code(text) => self.push(&text, Style::Code),
+            // descriptions are written line by line, so every
+            // break keeps its line like in the Trello card view
+            Event::SoftBreak | Event::HardBreak => {
+                self.finish_paragraph()
+            }
+            Event::TaskListMarker(checked) => {
+                if let Some((indent, _)) = self.marker.take() {
+                    let mark =
+                        if checked { "[x] " } else { "[ ] " };
+                    self.push_text(
+                        &format!("{}{}", indent, mark),
+                        Style::Regular,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: &Tag<'_>) {
+        match tag {
+            Tag::Heading(_) | Tag::Strong => self.bold += 1,
+            Tag::Emphasis => self.italic += 1,
+            Tag::Image(..) => self.images += 1,
+            Tag::CodeBlock(_) => {
+                self.finish_paragraph();
+                self.code_block = true;
+            }
+            Tag::List(start) => {
+                self.finish_paragraph();
+                self.lists.push(*start);
+            }
+            Tag::Item => {
+                self.finish_paragraph();
+                let indent = "  "
+                    .repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        let marker = format!("{}. ", number);
+                        *number += 1;
+                        marker
+                    }
+                    _ => String::from("\u{2022} "),
+                };
+                self.marker = Some((indent, marker));
+            }
+            Tag::TableCell if !self.current.is_empty() => {
+                self.push_text(" | ", Style::Regular);
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: &Tag<'_>) {
+        match tag {
+            Tag::Heading(_) => {
+                self.bold = self.bold.saturating_sub(1);
+                self.finish_paragraph();
+            }
+            Tag::Strong => {
+                self.bold = self.bold.saturating_sub(1);
+            }
+            Tag::Emphasis => {
+                self.italic = self.italic.saturating_sub(1);
+            }
+            Tag::Image(..) => {
+                self.images = self.images.saturating_sub(1);
+            }
+            Tag::CodeBlock(_) => {
+                self.finish_paragraph();
+                self.code_block = false;
+            }
+            Tag::List(_) => {
+                let _ = self.lists.pop();
+            }
+            Tag::Paragraph
+            | Tag::Item
+            | Tag::TableHead
+            | Tag::TableRow => self.finish_paragraph(),
+            _ => {}
+        }
+    }
+
+    const fn style(&self) -> Style {
+        match (self.bold > 0, self.italic > 0) {
+            (false, false) => Style::Regular,
+            (true, false) => Style::Bold,
+            (false, true) => Style::Italic,
+            (true, true) => Style::BoldItalic,
+        }
+    }
+
+    /// Adds text, preceded by the marker of the current list item
+    fn push(&mut self, text: &str, style: Style) {
+        if let Some((indent, marker)) = self.marker.take() {
+            self.push_text(
+                &format!("{}{}", indent, marker),
+                Style::Regular,
+            );
+        }
+        self.push_text(text, style);
+    }
+
+    fn push_text(&mut self, text: &str, style: Style) {
+        match self.current.last_mut() {
+            Some(run) if run.style == style => {
+                run.text.push_str(text)
+            }
+            _ => self.current.push(Run {
+                text: text.into(),
+                style,
+            }),
+        }
+    }
+
+    fn finish_paragraph(&mut self) {
+        let paragraph = mem::take(&mut self.current);
+        if paragraph.iter().any(|run| !run.text.trim().is_empty())
+        {
+            self.paragraphs.push(paragraph);
+        }
+    }
+}
+
+/// Converts a document in the atlassian document format, as used by
+/// the jira api version 3, into markdown. Media and unknown nodes
+/// without content are dropped.
+pub fn adf_to_markdown(doc: &Value) -> String {
+    let mut blocks = Vec::new();
+    adf_blocks(doc, "", &mut blocks);
+    blocks.join("\n\n")
+}
+
+/// Whether the value looks like an atlassian document
+pub fn is_adf(value: &Value) -> bool {
+    value.get("type").and_then(Value::as_str) == Some("doc")
+}
+
+#[allow(clippy::too_many_lines)]
+fn adf_blocks(
+    node: &Value,
+    indent: &str,
+    blocks: &mut Vec<String>,
+) {
+    let content = children(node);
+    match node_type(node) {
+        "paragraph" => {
+            let text = adf_inline(content);
+            if !text.trim().is_empty() {
+                blocks.push(format!("{}{}", indent, text));
+            }
+        }
+        "heading" => {
+            let level = node
+                .pointer("/attrs/level")
+                .and_then(Value::as_u64)
+                .unwrap_or(1)
+                .clamp(1, 6) as usize;
+            blocks.push(format!(
+                "{}{} {}",
+                indent,
+                "#".repeat(level),
+                adf_inline(content)
+            ));
+        }
+        "bulletList" | "orderedList" | "taskList" => {
+            let start = node
+                .pointer("/attrs/order")
+                .and_then(Value::as_u64)
+                .unwrap_or(1);
+            let items: Vec<String> = content
+                .iter()
+                .zip(start..)
+                .map(|(item, number)| {
+                    adf_list_item(
+                        item,
+                        node_type(node),
+                        number,
+                        indent,
+                    )
+                })
+                .collect();
+            blocks.push(items.join("\n"));
+        }
+        "codeBlock" => {
+            let code = content
+                .iter()
+                .filter_map(|text| {
+                    text.get("text").and_then(Value::as_str)
+                })
+                .collect::<String>();
+            let code = code
+                .lines()
+                .map(|line| format!("{}{}", indent, line))
+                .collect::<Vec<String>>()
+                .join("\n");
+            blocks.push(format!(
+                "{}```\n{}\n{}```",
+                indent, code, indent
+            ));
+        }
+        "blockquote" => {
+            let mut quoted = Vec::new();
+            for child in content {
+                adf_blocks(child, "", &mut quoted);
+            }
+            let quoted = quoted
+                .join("\n\n")
+                .lines()
+                .map(|line| format!("{}> {}", indent, line))
+                .collect::<Vec<String>>()
+                .join("\n");
+            blocks.push(quoted);
+        }
+        "table" => {
+            for row in content {
+                let cells: Vec<String> = children(row)
+                    .iter()
+                    .map(|cell| {
+                        let mut cell_blocks = Vec::new();
+                        for child in children(cell) {
+                            adf_blocks(
+                                child,
+                                "",
+                                &mut cell_blocks,
+                            );
+                        }
+                        cell_blocks.join(" ")
+                    })
+                    .collect();
+                blocks.push(format!(
+                    "{}{}",
+                    indent,
+                    cells.join(" \\| ")
+                ));
+            }
+        }
+        "expand" | "nestedExpand" => {
+            if let Some(title) = node
+                .pointer("/attrs/title")
+                .and_then(Value::as_str)
+            {
+                blocks.push(format!(
+                    "{}**{}**",
+                    indent,
+                    escape(title)
+                ));
+            }
+            for child in content {
+                adf_blocks(child, indent, blocks);
+            }
+        }
+        "rule" | "media" | "mediaSingle" | "mediaGroup"
+        | "extension" => {}
+        _ if node.get("text").is_some() => {
+            blocks.push(format!(
+                "{}{}",
+                indent,
+                adf_inline(slice::from_ref(node))
+            ));
+        }
+        _ => {
+            for child in content {
+                adf_blocks(child, indent, blocks);
+            }
+        }
+    }
+}
+
+fn adf_list_item(
+    item: &Value,
+    list_type: &str,
+    number: u64,
+    indent: &str,
+) -> String {
+    let marker = match list_type {
+        "orderedList" => format!("{}. ", number),
+        "taskList" => {
+            let done = item
+                .pointer("/attrs/state")
+                .and_then(Value::as_str)
+                == Some("DONE");
+            String::from(if done { "- [x] " } else { "- [ ] " })
+        }
+        _ => String::from("- "),
+    };
+    // task items contain inline nodes directly
+    if node_type(item) == "taskItem" {
+        return format!(
+            "{}{}{}",
+            indent,
+            marker,
+            adf_inline(children(item))
+        );
+    }
+    let nested =
+        format!("{}{}", indent, " ".repeat(marker.len()));
+    let mut item_blocks = Vec::new();
+    for child in children(item) {
+        adf_blocks(child, &nested, &mut item_blocks);
+    }
+    let text = item_blocks.join("\n");
+    match text.get(nested.len()..) {
+        Some(rest) if text.starts_with(&nested) => {
+            format!("{}{}{}", indent, marker, rest)
+        }
+        _ => format!("{}{}{}", indent, marker, text.trim_start()),
+    }
+}
+
+fn adf_inline(nodes: &[Value]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        let attr = |name: &str| {
+            node.pointer(&format!("/attrs/{}", name))
+                .and_then(Value::as_str)
+        };
+        match node_type(node) {
+            "text" => {
+                let value = node
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                text.push_str(&adf_marks(node, value));
+            }
+            "hardBreak" => text.push_str("\\\n"),
+            "mention" | "status" => text.push_str(&escape(
+                attr("text").unwrap_or_default(),
+            )),
+            "emoji" => text.push_str(
+                attr("text")
+                    .or_else(|| attr("shortName"))
+                    .unwrap_or_default(),
+            ),
+            "inlineCard" => text.push_str(&escape(
+                attr("url").unwrap_or_default(),
+            )),
+            "date" => {
+                let date = attr("timestamp")
+                    .and_then(|millis| millis.parse::<i64>().ok())
+                    .and_then(|millis| millis.checked_div(1000))
+                    .and_then(|secs| {
+                        time::at_utc(time::Timespec::new(secs, 0))
+                            .strftime("%Y-%m-%d")
+                            .map(|date| date.to_string())
+                            .ok()
+                    })
+                    .unwrap_or_default();
+                text.push_str(&date);
+            }
+            _ => text.push_str(&adf_inline(children(node))),
+        }
+    }
+    text
+}
+
+/// Applies the marks of a text node like `strong` or `code`
+fn adf_marks(node: &Value, value: &str) -> String {
+    let marks: Vec<&str> = node
+        .get("marks")
+        .and_then(Value::as_array)
+        .map(|marks| marks.iter().map(node_type).collect())
+        .unwrap_or_default();
+    if marks.contains(&"code") {
+        return if value.contains('`') {
+            format!("`` {} ``", value)
+        } else {
+            format!("`{}`", value)
+        };
+    }
+    let mut text = escape(value);
+    // markdown emphasis must not start or end with whitespace
+    let trimmed = text.trim().to_string();
+    if trimmed.is_empty() {
+        return text;
+    }
+    let mut styled = trimmed.clone();
+    if marks.contains(&"em") {
+        styled = format!("*{}*", styled);
+    }
+    if marks.contains(&"strong") {
+        styled = format!("**{}**", styled);
+    }
+    text = text.replacen(&trimmed, &styled, 1);
+    text
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if MARKDOWN_SPECIAL.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn node_type(node: &Value) -> &str {
+    node.get("type").and_then(Value::as_str).unwrap_or_default()
+}
+
+fn children(node: &Value) -> &[Value] {
+    node.get("content")
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(text: &str, style: Style) -> Run {
+        Run {
+            text: text.into(),
+            style,
+        }
+    }
+
+    fn texts(paragraphs: &[Paragraph]) -> Vec<String> {
+        paragraphs
+            .iter()
+            .map(|paragraph| {
+                paragraph
+                    .iter()
+                    .map(|run| run.text.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn doc(content: &Value) -> Value {
+        json!({ "type": "doc", "version": 1, "content": content })
+    }
+
+    fn paragraph(content: &Value) -> Value {
+        json!({ "type": "paragraph", "content": content })
+    }
+
+    fn text(value: &str, marks: &[&str]) -> Value {
+        let marks: Vec<Value> = marks
+            .iter()
+            .map(|mark| json!({ "type": mark }))
+            .collect();
+        json!({ "type": "text", "text": value, "marks": marks })
+    }
+
+    #[test]
+    fn plain_text_keeps_every_line() {
+        assert_eq!(
+            plain("one *two*\nthree"),
+            vec![
+                vec![run("one *two*", Style::Regular)],
+                vec![run("three", Style::Regular)],
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_styles_become_runs() {
+        assert_eq!(
+            markdown("Some **bold**, *italic* and `code`"),
+            vec![vec![
+                run("Some ", Style::Regular),
+                run("bold", Style::Bold),
+                run(", ", Style::Regular),
+                run("italic", Style::Italic),
+                run(" and ", Style::Regular),
+                run("code", Style::Code),
+            ]]
+        );
+        assert_eq!(
+            markdown("***both***"),
+            vec![vec![run("both", Style::BoldItalic)]]
+        );
+    }
+
+    #[test]
+    fn headings_are_bold_paragraphs() {
+        assert_eq!(
+            markdown("# Title\nline one\nline two"),
+            vec![
+                vec![run("Title", Style::Bold)],
+                vec![run("line one", Style::Regular)],
+                vec![run("line two", Style::Regular)],
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_get_markers_and_indentation() {
+        assert_eq!(
+            texts(&markdown(
+                "- one\n- two\n  1. first\n  2. second"
+            )),
+            [
+                "\u{2022} one",
+                "\u{2022} two",
+                "  1. first",
+                "  2. second"
+            ]
+        );
+        assert_eq!(
+            texts(&markdown("3. three\n4. four")),
+            ["3. three", "4. four"]
+        );
+    }
+
+    #[test]
+    fn task_lists_are_checked_boxes() {
+        assert_eq!(
+            texts(&markdown("- [ ] open\n- [x] done")),
+            ["[ ] open", "[x] done"]
+        );
+    }
+
+    #[test]
+    fn code_blocks_keep_their_lines() {
+        assert_eq!(
+            markdown("```\nlet a = 1;\nlet b = 2;\n```"),
+            vec![
+                vec![run("let a = 1;", Style::Code)],
+                vec![run("let b = 2;", Style::Code)],
+            ]
+        );
+    }
+
+    #[test]
+    fn images_are_dropped_and_links_reduced_to_text() {
+        assert_eq!(
+            texts(&markdown(
+                "![logo](logo.png)\n\nSee [the docs](https://example.com)"
+            )),
+            ["See the docs"]
+        );
+    }
+
+    #[test]
+    fn adf_marks_become_markdown() {
+        let doc = doc(&json!([paragraph(&json!([
+            text("bold", &["strong"]),
+            text(" and ", &[]),
+            text("italic ", &["em"]),
+            text("a_b", &[]),
+            text(" ", &[]),
+            text("x", &["code"]),
+        ]))]));
+        assert!(is_adf(&doc));
+        assert_eq!(
+            adf_to_markdown(&doc),
+            "**bold** and *italic* a\\_b `x`"
+        );
+    }
+
+    #[test]
+    fn adf_blocks_become_markdown() {
+        let doc = doc(&json!([
+            {
+                "type": "heading",
+                "attrs": { "level": 2 },
+                "content": [text("Steps", &[])]
+            },
+            {
+                "type": "orderedList",
+                "content": [
+                    {
+                        "type": "listItem",
+                        "content": [paragraph(&json!([text("first", &[])]))]
+                    },
+                    {
+                        "type": "listItem",
+                        "content": [paragraph(&json!([text("second", &[])]))]
+                    }
+                ]
+            },
+            {
+                "type": "taskList",
+                "content": [{
+                    "type": "taskItem",
+                    "attrs": { "state": "DONE" },
+                    "content": [text("tested", &[])]
+                }]
+            },
+            {
+                "type": "codeBlock",
+                "content": [text("cargo test", &[])]
+            },
+            { "type": "rule" }
+        ]));
+        assert_eq!(
+            adf_to_markdown(&doc),
+            "## Steps\n\n1. first\n2. second\n\n- [x] tested\n\n```\ncargo test\n```"
+        );
+    }
+
+    #[test]
+    fn adf_converts_to_styled_runs() {
+        let doc = doc(&json!([
+            paragraph(&json!([
+                text("Hello ", &[]),
+                text("world", &["strong"]),
+                { "type": "hardBreak" },
+                { "type": "mention", "attrs": { "text": "@jane" } }
+            ])),
+            {
+                "type": "bulletList",
+                "content": [{
+                    "type": "listItem",
+                    "content": [paragraph(&json!([text("item", &["em"])]))]
+                }]
+            }
+        ]));
+        assert_eq!(
+            markdown(&adf_to_markdown(&doc)),
+            vec![
+                vec![
+                    run("Hello ", Style::Regular),
+                    run("world", Style::Bold),
+                ],
+                vec![run("@jane", Style::Regular)],
+                vec![
+                    run("\u{2022} ", Style::Regular),
+                    run("item", Style::Italic),
+                ],
+            ]
+        );
+    }
+}
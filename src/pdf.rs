@@ -1,23 +1,197 @@
-use crate::{config::Config, services::Ticket, Result};
+// Warnings and errors are reported on stderr, errors with their
+// causes through Debug
+#![allow(clippy::print_stderr, clippy::use_debug)]
+
+use crate::{
+    barcode,
+    config::{
+        Config, Duplex, LabelPrinter, PDfDimension, Printer,
+        ReceiptPrinter, Sheet,
+    },
+    font::{self, CardFont, FontFile, Fonts},
+    icon::{self, Icon},
+    ipp::{self, JobState, Value},
+    label,
+    layout::{
+        Align, Color, Content, Layout, Region, Shape, TextBox,
+        VAlign,
+    },
+    markup::{self, Run},
+    raster::Bitmap,
+    receipt,
+    services::{Sources, Ticket},
+    sheet::{Grid, Segment},
+    Result,
+};
 use failure::ResultExt;
-use image::{Luma, Pixel};
-use pdf_canvas::{BuiltinFont, FontSource};
-use pdfpdf::{Alignment, Font, Image, Pdf, Point, Size};
-use qrcode::{EcLevel, QrCode, Version};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Luma};
+use printpdf::{
+    ColorBits, ColorSpace, CurTransMat, CustomPdfConformance,
+    Image, ImageXObject, IndirectFontRef, Line, LineDashPattern,
+    Mm, PdfConformance, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, Pt, Px, Rgb,
+};
+use qrcode::{EcLevel, QrCode};
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::BufWriter,
+    iter, mem,
     path::{Path, PathBuf},
-    process::Command,
+    result, slice,
+    time::Duration,
 };
 use tempfile::tempdir;
 use urlshortener::{client::UrlShortener, providers::Provider};
 
+/// Factor applied to the font size while fitting text into a region
+const FIT_STEP: f32 = 0.95;
+const ELLIPSIS: char = '\u{2026}';
+/// Line width of cut marks and fold lines in points
+const GUIDE_THICKNESS: f64 = 0.25;
+/// Length of the dashes and gaps of the fold line in points
+const FOLD_DASH: i64 = 4;
+const BLACK: Color = Color::rgb(0, 0, 0);
+const WHITE: Color = Color::rgb(0xff, 0xff, 0xff);
+/// Seconds to wait for a print job to complete
+const JOB_TIMEOUT: u64 = 300;
+/// Values of the IPP orientation-requested attribute
+const ORIENTATIONS: &[(&str, i32)] = &[
+    ("portrait", 3),
+    ("landscape", 4),
+    ("reverse-landscape", 5),
+    ("reverse-portrait", 6),
+];
+/// Colours of the Trello label colour names
+const LABEL_COLORS: &[(&str, Color)] = &[
+    ("green", Color::rgb(0x61, 0xbd, 0x4f)),
+    ("yellow", Color::rgb(0xf2, 0xd6, 0x00)),
+    ("orange", Color::rgb(0xff, 0x9f, 0x1a)),
+    ("red", Color::rgb(0xeb, 0x5a, 0x46)),
+    ("purple", Color::rgb(0xc3, 0x77, 0xe0)),
+    ("blue", Color::rgb(0x00, 0x79, 0xbf)),
+    ("sky", Color::rgb(0x00, 0xc2, 0xe0)),
+    ("lime", Color::rgb(0x51, 0xe8, 0x98)),
+    ("pink", Color::rgb(0xff, 0x78, 0xcb)),
+    ("black", Color::rgb(0x34, 0x45, 0x63)),
+];
+
+/// The way tickets are printed
+enum Mode<'a> {
+    Receipt(&'a Printer, &'a ReceiptPrinter),
+    Label(&'a Printer, &'a LabelPrinter),
+    Sheet(&'a Sheet),
+    /// One pdf per ticket, printed by cups if a printer is configured
+    Card,
+}
+
+impl<'a> Mode<'a> {
+    fn from_config(config: &'a Config) -> Result<Self> {
+        let printer = config.printer.as_ref();
+        let receipt = printer.and_then(|printer| {
+            printer
+                .receipt
+                .as_ref()
+                .map(|receipt| (printer, receipt))
+        });
+        let label = printer.and_then(|printer| {
+            printer.label.as_ref().map(|label| (printer, label))
+        });
+        let mode = match (receipt, label, config.sheet.as_ref()) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+                return Err(failure::err_msg(
+                    "Receipt printers can not print sheets or labels. Remove either printer.receipt or sheet and printer.label",
+                )
+                .into())
+            }
+            (_, Some(_), Some(_)) => {
+                return Err(failure::err_msg(
+                    "Label printers can not print sheets. Remove either sheet or printer.label",
+                )
+                .into())
+            }
+            (Some((printer, receipt)), None, None) => {
+                Mode::Receipt(printer, receipt)
+            }
+            (None, Some((printer, label)), None) => {
+                Mode::Label(printer, label)
+            }
+            (None, None, Some(sheet)) => Mode::Sheet(sheet),
+            (None, None, None) => Mode::Card,
+        };
+        Ok(mode)
+    }
+}
+
+/// Card layouts and fonts shared by all tickets of a run
+struct Cards {
+    layout: Layout,
+    back: Option<Layout>,
+    fonts: Fonts,
+}
+
+impl Cards {
+    fn load(config: &Config, mode: &Mode<'_>) -> Result<Self> {
+        let layout = Layout::load(&config.pdf)?;
+        let back = Layout::load_back(&config.pdf)?;
+        let builtin = !matches!(*mode, Mode::Label(..));
+        let fonts = Fonts::load(
+            &config.pdf,
+            &iter::once(&layout).chain(&back).collect::<Vec<_>>(),
+            builtin,
+        )?;
+        Ok(Self {
+            layout,
+            back,
+            fonts,
+        })
+    }
+}
+
+/// Checks the layouts, fonts and printer settings once on start.
+/// Errors in them affect every ticket, so no ticket is consumed.
+pub fn validate(config: &Config) -> Result<()> {
+    let mode = Mode::from_config(config)?;
+    let _ = Cards::load(config, &mode)?;
+    match mode {
+        Mode::Sheet(sheet) => {
+            let _ =
+                Grid::sheet(sheet, &config.pdf, duplex(config))?;
+        }
+        Mode::Card => {
+            if let Some(ref printer) = config.printer {
+                if orientation(&printer.orientation).is_none() {
+                    return Err(
+                        unknown_orientation(printer).into()
+                    );
+                }
+            }
+        }
+        Mode::Receipt(..) | Mode::Label(..) => {}
+    }
+    Ok(())
+}
+
+fn duplex(config: &Config) -> Duplex {
+    config
+        .printer
+        .as_ref()
+        .and_then(|printer| printer.duplex)
+        .unwrap_or_default()
+}
+
+/// Prints the tickets and finishes them in their source. Tickets
+/// failing to print are reverted and counted, an error means none
+/// of the remaining tickets could be printed.
+#[allow(clippy::too_many_lines)]
 pub fn print_tickets(
     config: &Config,
+    sources: &Sources<'_>,
     tickets: &mut Vec<Ticket>,
-) -> Result<()> {
+) -> Result<usize> {
     if tickets.is_empty() {
         println!("No tickets marked for printing.");
-        return Ok(());
+        return Ok(0);
     }
     let (tmp_dir, pdf_path) = if let Some(out_dir) =
         config.global.as_ref().and_then(|g| g.out_dir.as_ref())
@@ -30,30 +204,176 @@ pub fn print_tickets(
         let path = dir.path().to_path_buf();
         (Some(dir), path)
     };
+    let mode = Mode::from_config(config)?;
+    let Cards {
+        layout,
+        back,
+        fonts,
+    } = Cards::load(config, &mode)?;
     if config.printer.is_none() {
         println!(
             "Missing printer configuration. Only saving pdfs."
         );
     }
-    while !tickets.is_empty() {
-        if let Some(mut ticket) = tickets.last_mut() {
-            minify_url_if_possible(&mut ticket);
-            let pdf = create_pdf(config, &pdf_path, ticket)?;
-            print_pdf(config, &pdf)?;
-            println!(
-                "Printed: {} - {}",
-                ticket.id, ticket.subtitel
-            );
+    let failed = match mode {
+        Mode::Receipt(printer, receipt) => {
+            print_each(config, sources, tickets, |ticket| {
+                receipt::print(
+                    printer,
+                    receipt,
+                    &config.pdf,
+                    ticket,
+                )?;
+                Ok(JobEnd::Printed)
+            })
         }
-        let _ = tickets.pop();
-    }
+        Mode::Label(printer, label) => {
+            let dpi = label::dpi(label);
+            print_each(config, sources, tickets, |ticket| {
+                // backs follow their front as separate label
+                let labels = iter::once(&layout)
+                    .chain(&back)
+                    .map(|layout| {
+                        render_label(
+                            layout,
+                            &fonts,
+                            ticket,
+                            &config.pdf,
+                            dpi,
+                        )
+                    })
+                    .collect::<result::Result<Vec<_>, _>>()?;
+                label::print(printer, label, &labels)?;
+                Ok(JobEnd::Printed)
+            })
+        }
+        Mode::Sheet(sheet) => {
+            let grid =
+                Grid::sheet(sheet, &config.pdf, duplex(config))?;
+            tickets.iter_mut().for_each(minify_url_if_possible);
+            // all tickets share one job, so they fail together
+            let printed = create_pdf(
+                &layout,
+                back.as_ref(),
+                &fonts,
+                &grid,
+                &sheet_path(&pdf_path),
+                "Tickets",
+                tickets,
+            )
+            .and_then(|pdf| {
+                print_pdf(config, &grid, &pdf, "Tickets")
+            });
+            let failed = match printed {
+                Ok(end) => {
+                    finish_job(config, sources, tickets, end)
+                }
+                Err(err) => {
+                    eprintln!(
+                        "ERROR: Could not print sheet: {:?}",
+                        err
+                    );
+                    sources.revert_tickets(tickets);
+                    tickets.len()
+                }
+            };
+            tickets.clear();
+            failed
+        }
+        Mode::Card => {
+            let grid = Grid::card(&config.pdf);
+            print_each(config, sources, tickets, |ticket| {
+                let pdf = create_pdf(
+                    &layout,
+                    back.as_ref(),
+                    &fonts,
+                    &grid,
+                    &card_path(&pdf_path, ticket),
+                    &ticket.titel,
+                    slice::from_ref(ticket),
+                )?;
+                print_pdf(config, &grid, &pdf, &ticket.titel)
+            })
+        }
+    };
     if let Some(tmp_dir) = tmp_dir {
         drop(tmp_dir);
     }
-    Ok(())
+    Ok(failed)
+}
+
+/// Prints the tickets one by one. A ticket failing to print is
+/// reported and reverted, the others are printed anyway. Returns the
+/// number of failed tickets.
+fn print_each(
+    config: &Config,
+    sources: &Sources<'_>,
+    tickets: &mut Vec<Ticket>,
+    mut print: impl FnMut(&Ticket) -> Result<JobEnd>,
+) -> usize {
+    let mut failed = 0;
+    while let Some(mut ticket) = tickets.pop() {
+        minify_url_if_possible(&mut ticket);
+        match print(&ticket) {
+            Ok(end) => {
+                failed += finish_job(
+                    config,
+                    sources,
+                    slice::from_ref(&ticket),
+                    end,
+                )
+            }
+            Err(err) => {
+                eprintln!(
+                    "ERROR: Could not print {} - {}: {:?}",
+                    ticket.id, ticket.subtitel, err
+                );
+                sources.revert_tickets(slice::from_ref(&ticket));
+                failed += 1;
+            }
+        }
+    }
+    failed
+}
+
+/// Finishes the tickets of a printed job and reverts those of a
+/// canceled one. Tickets of a pending job stay consumed, they are
+/// counted as failed.
+fn finish_job(
+    config: &Config,
+    sources: &Sources<'_>,
+    tickets: &[Ticket],
+    end: JobEnd,
+) -> usize {
+    match end {
+        JobEnd::Printed => {
+            for ticket in tickets {
+                sources.finish_ticket(
+                    ticket,
+                    config.printer.as_ref(),
+                );
+                println!(
+                    "Printed: {} - {}",
+                    ticket.id, ticket.subtitel
+                );
+            }
+            0
+        }
+        JobEnd::Canceled => {
+            sources.revert_tickets(tickets);
+            0
+        }
+        JobEnd::Pending => {
+            sources.leave_consumed(tickets);
+            tickets.len()
+        }
+    }
 }
 
 fn minify_url_if_possible(ticket: &mut Ticket) {
+    if ticket.url.is_empty() {
+        return;
+    }
     if let Ok(shortener) = UrlShortener::new() {
         let providers = [
             Provider::BamBz,
@@ -78,183 +398,1703 @@ fn minify_url_if_possible(ticket: &mut Ticket) {
     }
 }
 
+fn card_path(pdf_dir: &Path, ticket: &Ticket) -> PathBuf {
+    let file_name: String = ticket
+        .id
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    pdf_dir.join(format!("{}.pdf", file_name))
+}
+
+/// Sheets contain many tickets, so they are named after the time
+fn sheet_path(pdf_dir: &Path) -> PathBuf {
+    let created_at = time::now()
+        .strftime("%Y-%m-%d_%H-%M-%S")
+        .map(|created_at| created_at.to_string())
+        .unwrap_or_default();
+    pdf_dir.join(format!("sheets_{}.pdf", created_at))
+}
+
+/// Creates a pdf with the cards of all tickets placed as given by
+/// `grid`. New pages are added once a page is full. With a `back`
+/// layout every page is followed by the backs of its cards.
 fn create_pdf(
-    config: &Config,
-    pdf_dir: &Path,
-    ticket: &Ticket,
+    layout: &Layout,
+    back: Option<&Layout>,
+    fonts: &Fonts,
+    grid: &Grid,
+    pdf_path: &Path,
+    title: &str,
+    tickets: &[Ticket],
 ) -> Result<PathBuf> {
-    let pdf_path = pdf_dir.join(format!("{}.pdf", ticket.id));
-    let mut pdf = Pdf::new();
-    let _ = pdf.add_page(Size {
-        width: config.pdf.width,
-        height: config.pdf.height,
-    });
+    let (doc, page, layer) = PdfDocument::new(
+        title,
+        mm(grid.width),
+        mm(grid.height),
+        "Cards",
+    );
+    // skips the embedded icc profile, which is larger than the card
+    let doc = doc.with_conformance(PdfConformance::Custom(
+        CustomPdfConformance::default(),
+    ));
+    // text is wrapped first, so embedded fonts only need the glyphs
+    // actually printed. Backs follow the fronts.
+    let mut cards: Vec<Vec<Option<TextLines<'_>>>> = tickets
+        .iter()
+        .map(|ticket| wrap_card(layout, fonts, ticket))
+        .chain(back.into_iter().flat_map(|back| {
+            tickets
+                .iter()
+                .map(move |ticket| wrap_card(back, fonts, ticket))
+        }))
+        .collect();
+    embed_fonts(&doc, &mut cards)
+        .with_context(|_| "Could not embed fonts".to_string())?;
+    let (front_cards, back_cards) = cards.split_at(tickets.len());
+    let per_page = grid.cards_per_page().max(1);
+    let mut first_layer =
+        Some(doc.get_page(page).get_layer(layer));
+    let mut next_layer = || {
+        first_layer.take().unwrap_or_else(|| {
+            let (page, layer) = doc.add_page(
+                mm(grid.width),
+                mm(grid.height),
+                "Cards",
+            );
+            doc.get_page(page).get_layer(layer)
+        })
+    };
+    let mut back_pages = back_cards.chunks(per_page);
+    for (page_tickets, page_fronts) in
+        tickets.chunks(per_page).zip(front_cards.chunks(per_page))
+    {
+        let layer = next_layer();
+        draw_cards(
+            &layer,
+            layout,
+            grid.positions(),
+            page_tickets,
+            page_fronts,
+        )?;
+        draw_guides(&layer, grid);
+        if let (Some(back), Some(page_backs)) =
+            (back, back_pages.next())
+        {
+            let layer = next_layer();
+            draw_cards(
+                &layer,
+                back,
+                grid.back_positions(),
+                page_tickets,
+                page_backs,
+            )?;
+        }
+    }
+    let file = File::create(pdf_path).with_context(|_| {
+        "could not create pdf file".to_string()
+    })?;
+    doc.save(&mut BufWriter::new(file)).with_context(|_| {
+        "could not create pdf file".to_string()
+    })?;
+    Ok(pdf_path.to_path_buf())
+}
 
-    let width = (config.pdf.width / 2.0)
-        - config.pdf.margin
-        - (config.pdf.qrcode_seperator_margin / 2.0);
-    let height = (config.pdf.height / 2.0)
-        - config.pdf.margin
-        - (config.pdf.title_seperator_margin / 2.0);
-    if let Some(qrcode) = setup_qrcode(ticket, width, height) {
-        let dim = qrcode.dimensions();
-        let mut image_bytes: Vec<u8> =
-            Vec::with_capacity((dim.0 * dim.1 * 3) as usize);
-        for (_, _, pixel) in qrcode.enumerate_pixels() {
-            let [r, g, b]: [u8; 3] = pixel.to_rgb().0;
-            image_bytes.push(r);
-            image_bytes.push(g);
-            image_bytes.push(b);
-        }
-        let image = Image::new(&image_bytes, dim.0, dim.1);
-        let mut center_qrcode_y = (height - dim.1 as f32) / 2.0;
-        if 0.0 > center_qrcode_y {
-            center_qrcode_y = 0.0;
-        }
-        let _ = pdf.add_image_at(
-            image,
-            Point {
-                x: config.pdf.margin,
-                y: config.pdf.margin + center_qrcode_y,
-            },
+/// Surface the regions of a card are drawn on. Coordinates are in
+/// millimeter from the lower left corner of the card.
+pub trait Canvas {
+    fn fill_polygon(
+        &mut self,
+        color: Color,
+        points: &[(f32, f32)],
+    );
+
+    /// Draws an image with its lower left corner at `x`, `y` and
+    /// pixels `scale` millimeter wide
+    fn draw_image(
+        &mut self,
+        image: DynamicImage,
+        x: f32,
+        y: f32,
+        scale: f32,
+    );
+
+    /// Draws black text starting at `x` on the `baseline`
+    fn draw_text(
+        &mut self,
+        piece: &Piece<'_>,
+        size: f32,
+        x: f32,
+        baseline: f32,
+    );
+}
+
+impl Canvas for PdfLayerReference {
+    fn fill_polygon(
+        &mut self,
+        color: Color,
+        points: &[(f32, f32)],
+    ) {
+        self.set_fill_color(pdf_color(color));
+        self.add_shape(Line {
+            points: points
+                .iter()
+                .map(|&(x, y)| (Point::new(mm(x), mm(y)), false))
+                .collect(),
+            is_closed: true,
+            has_fill: true,
+            has_stroke: false,
+            is_clipping_path: false,
+        });
+    }
+
+    fn draw_image(
+        &mut self,
+        image: DynamicImage,
+        x: f32,
+        y: f32,
+        scale: f32,
+    ) {
+        let (width, height) = image.dimensions();
+        // qr codes stay sharp, photos are smoothed
+        let (color_space, interpolate, pixels) = match image {
+            DynamicImage::ImageLuma8(image) => {
+                (ColorSpace::Greyscale, false, image.into_raw())
+            }
+            image => {
+                (ColorSpace::Rgb, true, image.to_rgb().into_raw())
+            }
+        };
+        let image = ImageXObject::new(
+            Px(width as usize),
+            Px(height as usize),
+            color_space,
+            ColorBits::Bit8,
+            interpolate,
+            None,
+            None,
+            pixels,
+        );
+        // at 72 dpi one pixel equals one point
+        Image::from(image).add_to_layer(
+            self.clone(),
+            Some(mm(x)),
+            Some(mm(y)),
+            None,
+            Some(pt(scale)),
+            Some(pt(scale)),
+            Some(72.0),
         );
     }
-    setup_titel(config, ticket, &mut pdf);
-    pdf.font(Font::Helvetica, config.pdf.subtitle_size)
-        .draw_text(
-            Point {
-                x: config.pdf.width - config.pdf.margin,
-                y: config.pdf.margin,
-            },
-            Alignment::BottomRight,
-            &ticket.subtitel,
+
+    fn draw_text(
+        &mut self,
+        piece: &Piece<'_>,
+        size: f32,
+        x: f32,
+        baseline: f32,
+    ) {
+        if let Some(ref font_ref) = piece.font_ref {
+            self.set_fill_color(pdf_color(BLACK));
+            self.use_text(
+                piece.text.as_str(),
+                pt(size),
+                mm(x),
+                mm(baseline),
+                font_ref,
+            );
+        }
+    }
+}
+
+/// Draws one card per ticket at the given lower left corners
+fn draw_cards(
+    layer: &PdfLayerReference,
+    layout: &Layout,
+    positions: impl Iterator<Item = (f32, f32)>,
+    tickets: &[Ticket],
+    cards: &[Vec<Option<TextLines<'_>>>],
+) -> result::Result<(), failure::Error> {
+    for ((x, y), (ticket, texts)) in
+        positions.zip(tickets.iter().zip(cards))
+    {
+        layer.save_graphics_state();
+        layer.set_ctm(CurTransMat::Translate(mm(x), mm(y)));
+        draw_card(&mut layer.clone(), layout, texts, ticket)?;
+        layer.restore_graphics_state();
+    }
+    Ok(())
+}
+
+/// Draws a card as bitmap in the resolution of a label printer
+fn render_label(
+    layout: &Layout,
+    fonts: &Fonts,
+    ticket: &Ticket,
+    pdf: &PDfDimension,
+    dpi: u32,
+) -> result::Result<Bitmap, failure::Error> {
+    let texts = wrap_card(layout, fonts, ticket);
+    let mut bitmap = Bitmap::new(pdf.width, pdf.height, dpi);
+    draw_card(&mut bitmap, layout, &texts, ticket)?;
+    Ok(bitmap)
+}
+
+/// Wraps the text regions of a card. Other regions are `None`.
+fn wrap_card<'a>(
+    layout: &Layout,
+    fonts: &'a Fonts,
+    ticket: &Ticket,
+) -> Vec<Option<TextLines<'a>>> {
+    layout
+        .regions
+        .iter()
+        .map(|region| match region.content {
+            Content::Text(ref text) => {
+                Some(wrap_region(region, text, fonts, ticket))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Draws a card with its lower left corner at the origin
+fn draw_card(
+    canvas: &mut impl Canvas,
+    layout: &Layout,
+    texts: &[Option<TextLines<'_>>],
+    ticket: &Ticket,
+) -> result::Result<(), failure::Error> {
+    for (region, text) in layout.regions.iter().zip(texts) {
+        draw_region(
+            canvas,
+            region,
+            text.as_ref(),
+            ticket,
+            &layout.colors,
         )
-        .write_to(pdf_path.clone())
         .with_context(|_| {
-            "could not create pdf file".to_string()
+            format!("Could not draw region {}", region.name)
         })?;
-    Ok(pdf_path)
-}
-
-fn setup_titel(config: &Config, ticket: &Ticket, pdf: &mut Pdf) {
-    let text_width = config.pdf.width - (2.0 * config.pdf.margin);
-    let text_height = (config.pdf.height / 2.0)
-        - config.pdf.margin
-        - (config.pdf.title_seperator_margin / 2.0);
-    let fontsize = text_height / (config.pdf.title_lines as f32);
-
-    let words = ticket.titel.split_whitespace();
-    let mut current_line = String::new();
-    let mut line = 0;
-    let font = BuiltinFont::Helvetica;
-    let _ = pdf.font(Font::Helvetica, fontsize);
-    for mut word in words {
-        let word_whitespace = format!(" {}", word);
-        if !current_line.is_empty() {
-            word = &word_whitespace;
-        }
-        while line < config.pdf.title_lines {
-            if font.get_width(
-                fontsize,
-                &format!("{}{}", current_line, word),
-            ) < text_width
+    }
+    Ok(())
+}
+
+/// Draws cut marks and the fold line of a sheet
+fn draw_guides(layer: &PdfLayerReference, grid: &Grid) {
+    if !grid.cut_marks && !grid.fold_line {
+        return;
+    }
+    layer.set_outline_color(pdf_color(BLACK));
+    layer.set_outline_thickness(GUIDE_THICKNESS);
+    if grid.cut_marks {
+        for mark in grid.cut_marks() {
+            draw_line(layer, mark);
+        }
+    }
+    if grid.fold_line {
+        layer.set_line_dash_pattern(LineDashPattern::new(
+            0,
+            Some(FOLD_DASH),
+            Some(FOLD_DASH),
+            None,
+            None,
+            None,
+            None,
+        ));
+        draw_line(layer, grid.fold_line());
+        layer.set_line_dash_pattern(LineDashPattern::default());
+    }
+}
+
+fn draw_line(layer: &PdfLayerReference, [from, to]: Segment) {
+    layer.add_shape(Line {
+        points: vec![
+            (Point::new(mm(from.0), mm(from.1)), false),
+            (Point::new(mm(to.0), mm(to.1)), false),
+        ],
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    });
+}
+
+fn draw_region(
+    canvas: &mut impl Canvas,
+    region: &Region,
+    text: Option<&TextLines<'_>>,
+    ticket: &Ticket,
+    colors: &BTreeMap<String, Color>,
+) -> result::Result<(), failure::Error> {
+    match region.content {
+        Content::Text(ref text_box) => {
+            if let Some(text) = text {
+                draw_text(canvas, region, text_box, text);
+            }
+        }
+        Content::Qrcode { ref data } => {
+            if let Some(qrcode) = fill_placeholders(data, ticket)
+                .and_then(|data| setup_qrcode(&data))
             {
-                current_line.push_str(word);
-                break;
-            } else if current_line.is_empty() {
-                for c in word.chars() {
-                    let cur_width = font.get_width(
-                        fontsize,
-                        &format!("{}{}", current_line, c),
-                    );
-                    if cur_width < text_width {
-                        current_line.push(c);
-                    } else {
-                        break;
-                    }
-                }
-                break;
+                draw_image(
+                    canvas,
+                    DynamicImage::ImageLuma8(qrcode),
+                    region,
+                );
+            }
+        }
+        Content::Barcode { ref data } => {
+            if let Some(data) = fill_placeholders(data, ticket) {
+                draw_barcode(canvas, region, &data);
+            }
+        }
+        Content::Band {
+            color,
+            ref value,
+            shape,
+        } => {
+            let color = value
+                .as_ref()
+                .and_then(|value| {
+                    fill_placeholders(value, ticket)
+                })
+                .and_then(|value| band_color(&value, colors))
+                .or(color);
+            if let Some(color) = color {
+                fill_band(canvas, color, region, shape);
+            }
+        }
+        Content::Image { ref path } => {
+            let image = image::open(path).with_context(|_| {
+                format!("Could not read image {}", path)
+            })?;
+            draw_image(canvas, image, region);
+        }
+        Content::Icon { ref data } => {
+            if let Some(icon) = fill_placeholders(data, ticket)
+                .and_then(|data| icon::find(&data))
+            {
+                draw_icon(canvas, region, icon);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Wrapped lines of a text region together with their font
+struct TextLines<'a> {
+    font: CardFont<'a>,
+    size: f32,
+    lines: Vec<Pieces<'a>>,
+}
+
+/// Text printed in a single font
+#[derive(Debug, Clone)]
+pub struct Piece<'a> {
+    pub text: String,
+    pub font: CardFont<'a>,
+    font_ref: Option<IndirectFontRef>,
+}
+
+/// Pieces printed next to each other, either a line or a word
+type Pieces<'a> = Vec<Piece<'a>>;
+
+fn wrap_region<'a>(
+    region: &Region,
+    text: &TextBox,
+    fonts: &'a Fonts,
+    ticket: &Ticket,
+) -> TextLines<'a> {
+    let font = fonts.resolve(text.font.as_ref());
+    // values like descriptions may contain paragraphs on their own
+    let paragraphs: Vec<Vec<Pieces<'a>>> = text
+        .text
+        .lines()
+        .filter_map(|paragraph| {
+            fill_placeholders(paragraph, ticket)
+        })
+        .flat_map(|filled| {
+            if text.markdown {
+                markup::markdown(&filled)
             } else {
-                let _ = pdf.draw_text(
-                    Point {
-                        x: config.pdf.width / 2.0,
-                        y: config.pdf.height
-                            - line as f32 * fontsize,
-                    },
-                    Alignment::TopCenter,
-                    &current_line,
+                markup::plain(&filled)
+            }
+        })
+        .map(|paragraph| words(&paragraph, font, fonts))
+        .collect();
+    let mut size = text.size.unwrap_or_else(|| {
+        region.height / text.lines.max(1) as f32
+    });
+    let mut wrapped = wrap_paragraphs(
+        &paragraphs,
+        size,
+        region.width,
+        text.lines,
+    );
+    if let Some(min_size) = text.min_size {
+        while !wrapped.fits() && size > min_size {
+            size = (size * FIT_STEP).max(min_size);
+            // smaller fonts leave room for additional lines
+            let lines =
+                (region.height / size + 0.001).floor() as u32;
+            wrapped = wrap_paragraphs(
+                &paragraphs,
+                size,
+                region.width,
+                lines.max(text.lines),
+            );
+        }
+    }
+    if wrapped.truncated {
+        if let Some(last) = wrapped.lines.last_mut() {
+            ellipsize(last, font, size, region.width);
+        }
+    }
+    TextLines {
+        font,
+        size,
+        lines: wrapped.lines,
+    }
+}
+
+/// Splits a paragraph at whitespace into words. A word may change
+/// its font in the middle, for example if only a part is bold.
+fn words<'a>(
+    paragraph: &[Run],
+    font: CardFont<'a>,
+    fonts: &Fonts,
+) -> Vec<Pieces<'a>> {
+    let mut words = Vec::new();
+    let mut word: Pieces<'a> = Vec::new();
+    for run in paragraph {
+        let font = fonts.styled(font, run.style);
+        for (index, part) in
+            run.text.split(char::is_whitespace).enumerate()
+        {
+            if index > 0 {
+                if words.is_empty()
+                    && word
+                        .iter()
+                        .all(|p| p.text.trim().is_empty())
+                {
+                    // keeps the indentation of nested list items
+                    push_text(&mut word, " ", font);
+                } else if !word.is_empty() {
+                    words.push(mem::take(&mut word));
+                }
+            }
+            push_text(&mut word, part, font);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Appends text to the last piece if it uses the same font
+fn push_text<'a>(
+    line: &mut Pieces<'a>,
+    text: &str,
+    font: CardFont<'a>,
+) {
+    if text.is_empty() {
+        return;
+    }
+    match line.last_mut() {
+        Some(last) if last.font == font => {
+            last.text.push_str(text)
+        }
+        _ => line.push(Piece {
+            text: text.into(),
+            font,
+            font_ref: None,
+        }),
+    }
+}
+
+fn line_width(line: &[Piece<'_>], size: f32) -> f32 {
+    line.iter()
+        .map(|piece| piece.font.width(size, &piece.text))
+        .sum()
+}
+
+/// Adds the fonts of all text regions to the document. Font files are
+/// subset to the characters used on the cards.
+fn embed_fonts(
+    doc: &PdfDocumentReference,
+    cards: &mut [Vec<Option<TextLines<'_>>>],
+) -> result::Result<(), failure::Error> {
+    let mut used: BTreeMap<&str, (&FontFile, BTreeSet<char>)> =
+        BTreeMap::new();
+    let pieces = cards
+        .iter()
+        .flatten()
+        .flatten()
+        .flat_map(|text| &text.lines)
+        .flatten();
+    for piece in pieces {
+        if let CardFont::File(file) = piece.font {
+            used.entry(file.path.as_str())
+                .or_insert_with(|| (file, BTreeSet::new()))
+                .1
+                .extend(piece.text.chars());
+        }
+    }
+    let mut embedded = BTreeMap::new();
+    for (path, (file, chars)) in used {
+        let subset = file.subset(&chars);
+        let _ = embedded.insert(
+            path,
+            doc.add_external_font(subset.as_slice())?,
+        );
+    }
+    let pieces = cards
+        .iter_mut()
+        .flatten()
+        .flatten()
+        .flat_map(|text| &mut text.lines)
+        .flatten();
+    for piece in pieces {
+        piece.font_ref = match piece.font {
+            CardFont::Builtin(font) => {
+                Some(doc.add_builtin_font(font::builtin(font).0)?)
+            }
+            CardFont::File(file) => {
+                embedded.get(file.path.as_str()).cloned()
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Draws the wrapped text of a text box using the alignment of the box
+fn draw_text(
+    canvas: &mut impl Canvas,
+    region: &Region,
+    text_box: &TextBox,
+    text: &TextLines<'_>,
+) {
+    let size = text.size;
+    let block_height = text.lines.len() as f32 * size;
+    let top = match text_box.valign {
+        VAlign::Top => region.y + region.height,
+        VAlign::Center => {
+            region.y + f32::midpoint(region.height, block_height)
+        }
+        VAlign::Bottom => region.y + block_height,
+    };
+    for (index, line) in text.lines.iter().enumerate() {
+        let width = line_width(line, size);
+        let mut x = match text_box.align {
+            Align::Left => region.x,
+            Align::Center => {
+                region.x + (region.width - width) / 2.0
+            }
+            Align::Right => region.x + region.width - width,
+        };
+        let baseline = (index as f32 + text.font.baseline())
+            .mul_add(-size, top);
+        for piece in line {
+            canvas.draw_text(piece, size, x, baseline);
+            x += piece.font.width(size, &piece.text);
+        }
+    }
+}
+
+/// Lines of wrapped text and whether anything had to be cut
+#[derive(Debug, Default)]
+struct Wrapped<'a> {
+    lines: Vec<Pieces<'a>>,
+    /// Text was dropped because all lines are used
+    truncated: bool,
+    /// A word was broken over multiple lines
+    split_words: bool,
+}
+
+impl Wrapped<'_> {
+    const fn fits(&self) -> bool {
+        !self.truncated && !self.split_words
+    }
+}
+
+fn wrap_paragraphs<'a>(
+    paragraphs: &[Vec<Pieces<'a>>],
+    size: f32,
+    width: f32,
+    max_lines: u32,
+) -> Wrapped<'a> {
+    let mut wrapped = Wrapped::default();
+    for words in paragraphs {
+        wrap_text(
+            words,
+            size,
+            width,
+            max_lines as usize,
+            &mut wrapped,
+        );
+    }
+    wrapped
+}
+
+/// Splits words into lines not wider than `width`. Words too long for
+/// a line of their own are broken over multiple lines.
+fn wrap_text<'a>(
+    words: &[Pieces<'a>],
+    size: f32,
+    width: f32,
+    max_lines: usize,
+    wrapped: &mut Wrapped<'a>,
+) {
+    let mut current_line: Pieces<'a> = Vec::new();
+    for word in words {
+        let mut word = word.clone();
+        loop {
+            if wrapped.lines.len() >= max_lines {
+                wrapped.truncated = true;
+                return;
+            }
+            let mut candidate = current_line.clone();
+            if let Some(font) = candidate.last().map(|p| p.font) {
+                push_text(&mut candidate, " ", font);
+            }
+            for piece in &word {
+                push_text(
+                    &mut candidate,
+                    &piece.text,
+                    piece.font,
                 );
-                line += 1;
-                current_line = String::new();
             }
+            if line_width(&candidate, size) <= width {
+                current_line = candidate;
+                break;
+            }
+            if !current_line.is_empty() {
+                wrapped.lines.push(mem::take(&mut current_line));
+                continue;
+            }
+            let chars: Vec<(char, CardFont<'a>)> = word
+                .iter()
+                .flat_map(|piece| {
+                    piece
+                        .text
+                        .chars()
+                        .map(move |c| (c, piece.font))
+                })
+                .collect();
+            // keeps at least one character per line to make progress
+            let split = (2..=chars.len())
+                .take_while(|end| {
+                    line_width(&from_chars(&chars, 0, *end), size)
+                        <= width
+                })
+                .last()
+                .unwrap_or(1);
+            wrapped.split_words = true;
+            wrapped.lines.push(from_chars(&chars, 0, split));
+            word = from_chars(&chars, split, chars.len());
         }
-        if line >= config.pdf.title_lines {
+    }
+    if !current_line.is_empty() {
+        if wrapped.lines.len() < max_lines {
+            wrapped.lines.push(current_line);
+        } else {
+            wrapped.truncated = true;
+        }
+    }
+}
+
+/// Builds pieces from the characters between `start` and `end`
+fn from_chars<'a>(
+    chars: &[(char, CardFont<'a>)],
+    start: usize,
+    end: usize,
+) -> Pieces<'a> {
+    let mut line = Vec::new();
+    for &(c, font) in chars.iter().take(end).skip(start) {
+        push_text(&mut line, c.encode_utf8(&mut [0; 4]), font);
+    }
+    line
+}
+
+/// Shortens a line until it fits together with a trailing ellipsis
+/// printed in the font of the last piece
+fn ellipsize<'a>(
+    line: &mut Pieces<'a>,
+    font: CardFont<'a>,
+    size: f32,
+    width: f32,
+) {
+    let ellipsis = ELLIPSIS.to_string();
+    loop {
+        let font = line.last().map_or(font, |piece| piece.font);
+        if line.is_empty()
+            || line_width(line, size)
+                + font.width(size, &ellipsis)
+                <= width
+        {
             break;
         }
+        if let Some(last) = line.last_mut() {
+            let _ = last.text.pop();
+            if last.text.is_empty() {
+                let _ = line.pop();
+            }
+        }
     }
-    if line < config.pdf.title_lines {
-        let _ = pdf.draw_text(
-            Point {
-                x: config.pdf.width / 2.0,
-                y: config.pdf.height - line as f32 * fontsize,
-            },
-            Alignment::TopCenter,
-            &current_line,
-        );
+    while let Some(last) = line.last_mut() {
+        let trimmed = last.text.trim_end().len();
+        last.text.truncate(trimmed);
+        if !last.text.is_empty() {
+            break;
+        }
+        let _ = line.pop();
     }
+    let font = line.last().map_or(font, |piece| piece.font);
+    push_text(line, &ellipsis, font);
 }
 
-fn setup_qrcode(
-    ticket: &Ticket,
+/// Draws a Code 39 barcode over the whole region. Unsupported
+/// characters are reported and the barcode is skipped.
+fn draw_barcode(
+    canvas: &mut impl Canvas,
+    region: &Region,
+    data: &str,
+) {
+    let Some(modules) = barcode::code39(data) else {
+        eprintln!("WARN: {} can not be printed as Code 39", data);
+        return;
+    };
+    let module_width = region.width / modules.len() as f32;
+    let mut start = None;
+    for (index, bar) in modules.iter().chain(&[false]).enumerate()
+    {
+        match (start, bar) {
+            (None, true) => start = Some(index),
+            (Some(first), false) => {
+                fill_rect(
+                    canvas,
+                    BLACK,
+                    (first as f32)
+                        .mul_add(module_width, region.x),
+                    region.y,
+                    (index - first) as f32 * module_width,
+                    region.height,
+                );
+                start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Scales an image into the region keeping its aspect ratio. The image
+/// is aligned left and centered vertically.
+fn draw_image(
+    canvas: &mut impl Canvas,
+    image: DynamicImage,
+    region: &Region,
+) {
+    let (width, height) = image.dimensions();
+    let scale = (region.width / width as f32)
+        .min(region.height / height as f32);
+    let y = region.y
+        + (height as f32).mul_add(-scale, region.height) / 2.0;
+    canvas.draw_image(image, region.x, y, scale);
+}
+
+fn fill_rect(
+    canvas: &mut impl Canvas,
+    color: Color,
+    x: f32,
+    y: f32,
     width: f32,
     height: f32,
-) -> Option<image::ImageBuffer<Luma<u8>, Vec<u8>>> {
-    QrCode::with_version(
-        &ticket.url,
-        Version::Normal(3),
-        EcLevel::L,
-    )
-    .map(|qrcode| {
-        qrcode
-            .render::<Luma<u8>>()
-            .quiet_zone(false)
-            .max_dimensions(width as u32, height as u32)
-            .build()
-    })
-    .map_err(|e| {
-        eprintln!("WARN: {}", e);
-        e
+) {
+    canvas.fill_polygon(
+        color,
+        &[
+            (x, y),
+            (x + width, y),
+            (x + width, y + height),
+            (x, y + height),
+        ],
+    );
+}
+
+/// Fills the rectangle or corner triangle of a band region
+fn fill_band(
+    canvas: &mut impl Canvas,
+    color: Color,
+    region: &Region,
+    shape: Shape,
+) {
+    let (left, bottom) = (region.x, region.y);
+    let right = left + region.width;
+    let top = bottom + region.height;
+    let points = match shape {
+        Shape::Rectangle => {
+            return fill_rect(
+                canvas,
+                color,
+                left,
+                bottom,
+                region.width,
+                region.height,
+            )
+        }
+        Shape::TopLeft => {
+            [(left, top), (right, top), (left, bottom)]
+        }
+        Shape::TopRight => {
+            [(right, top), (right, bottom), (left, top)]
+        }
+        Shape::BottomLeft => {
+            [(left, bottom), (left, top), (right, bottom)]
+        }
+        Shape::BottomRight => {
+            [(right, bottom), (left, bottom), (right, top)]
+        }
+    };
+    canvas.fill_polygon(color, &points);
+}
+
+/// Returns the colour of the first comma separated value with an
+/// entry in `pdf.colors` or a Trello label colour name
+fn band_color(
+    values: &str,
+    colors: &BTreeMap<String, Color>,
+) -> Option<Color> {
+    values.split(',').find_map(|value| {
+        let value = value.trim().to_lowercase();
+        colors.get(&value).copied().or_else(|| {
+            // newer boards add shades like green_dark
+            let base =
+                value.split('_').next().unwrap_or_default();
+            LABEL_COLORS
+                .iter()
+                .find(|(name, _)| *name == base)
+                .map(|(_, color)| *color)
+        })
     })
-    .ok()
-}
-
-fn print_pdf(config: &Config, pdf: &Path) -> Result<()> {
-    if let Some(printer) = &config.printer {
-        for _ in 0..printer.number_of_copies {
-            let status = Command::new("/usr/bin/lp")
-                .args(&[
-                "-o",
-                "fit-to-page",
-                "-o",
-                &format!("media={}", printer.media),
-                "-o",
-                &printer.orientation,
-                "-d",
-                &printer.name,
-                pdf.to_str().unwrap_or(""),
-                ])
-                .status()
-                .with_context(|_| {
-                    "Unable to execute /usr/bin/lp. Is lp installed?"
-                        .to_string()
-                })?;
-            if !status.success() {
-                return Err(failure::err_msg(
-                    "Unable to print ticket. LP failed with non zero",
-                )
-                .into());
-            }
+}
+
+/// Draws an issue type icon into the largest square of the region,
+/// centered vertically and aligned left like images
+fn draw_icon(
+    canvas: &mut impl Canvas,
+    region: &Region,
+    icon: &Icon,
+) {
+    let size = region.width.min(region.height);
+    let x = region.x;
+    let y = region.y + (region.height - size) / 2.0;
+    fill_rect(canvas, icon.background, x, y, size, size);
+    let symbol: Vec<(f32, f32)> = icon
+        .symbol
+        .iter()
+        .map(|&(dx, dy)| {
+            (dx.mul_add(size, x), dy.mul_add(size, y))
+        })
+        .collect();
+    canvas.fill_polygon(WHITE, &symbol);
+}
+
+fn pdf_color(color: Color) -> printpdf::Color {
+    printpdf::Color::Rgb(Rgb::new(
+        f64::from(color.red) / 255.0,
+        f64::from(color.green) / 255.0,
+        f64::from(color.blue) / 255.0,
+        None,
+    ))
+}
+
+/// Card dimensions are given in millimeter
+fn mm(value: f32) -> Mm {
+    Mm(f64::from(value))
+}
+
+fn pt(value: f32) -> f64 {
+    Pt::from(mm(value)).0
+}
+
+/// Replaces placeholders like `{fields.priority}` with the values of
+/// the ticket. Returns `None` if the template contains placeholders
+/// but none of them has a value.
+pub fn fill_placeholders(
+    template: &str,
+    ticket: &Ticket,
+) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    let mut placeholders = false;
+    let mut values = false;
+    while let Some((before, after)) = rest.split_once('{') {
+        let Some((name, after)) = after.split_once('}') else {
+            break;
+        };
+        result.push_str(before);
+        let value = ticket.value(name).unwrap_or_default();
+        placeholders = true;
+        values |= !value.is_empty();
+        result.push_str(value);
+        rest = after;
+    }
+    result.push_str(rest);
+    if placeholders && !values {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+/// Returns the IPP orientation-requested value of
+/// `printer.orientation`. Options formerly passed to `lp -o`, like
+/// `orientation-requested=4`, are accepted as well.
+pub fn orientation(value: &str) -> Option<i32> {
+    let value =
+        value.trim().trim_start_matches("orientation-requested=");
+    ORIENTATIONS
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, orientation)| *orientation)
+        .or_else(|| {
+            value.parse().ok().filter(|orientation| {
+                ORIENTATIONS
+                    .iter()
+                    .any(|(_, known)| known == orientation)
+            })
+        })
+}
+
+fn unknown_orientation(printer: &Printer) -> failure::Error {
+    failure::format_err!(
+        "Unknown printer.orientation {}. Expected portrait, landscape, reverse-landscape, reverse-portrait or orientation-requested=<3-6>",
+        printer.orientation
+    )
+}
+
+fn setup_qrcode(
+    data: &str,
+) -> Option<ImageBuffer<Luma<u8>, Vec<u8>>> {
+    if data.is_empty() {
+        return None;
+    }
+    QrCode::with_error_correction_level(data, EcLevel::L)
+        .map(|qrcode| {
+            qrcode
+                .render::<Luma<u8>>()
+                .quiet_zone(false)
+                .module_dimensions(1, 1)
+                .build()
+        })
+        .inspect_err(|&e| {
+            eprintln!("WARN: {}", e);
+        })
+        .ok()
+}
+
+/// How the tickets of a print job left the printer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobEnd {
+    Printed,
+    /// Canceled or aborted, the tickets can be printed again
+    Canceled,
+    /// Still queued after the timeout and not cancelable, so the
+    /// tickets may be printed later on
+    Pending,
+}
+
+/// Submits the pdf as a single job with all copies and waits until
+/// the printer completed it. Jobs not ending within the timeout are
+/// canceled. Without printer configuration the pdf counts as printed.
+fn print_pdf(
+    config: &Config,
+    grid: &Grid,
+    pdf: &Path,
+    title: &str,
+) -> Result<JobEnd> {
+    let Some(ref printer) = config.printer else {
+        return Ok(JobEnd::Printed);
+    };
+    let mut attributes = vec![(
+        "copies",
+        Value::Integer(i32::from(printer.number_of_copies)),
+    )];
+    if let Some(duplex) = printer.duplex {
+        let sides = match duplex {
+            Duplex::LongEdge => "two-sided-long-edge",
+            Duplex::ShortEdge => "two-sided-short-edge",
+        };
+        attributes.push(("sides", Value::Keyword(sides.into())));
+    }
+    if let Some(ref media) = grid.media {
+        // sheets are printed in their actual size
+        attributes.push(("media", Value::Keyword(media.clone())));
+    } else {
+        let orientation = orientation(&printer.orientation)
+            .ok_or_else(|| unknown_orientation(printer))?;
+        attributes.extend(vec![
+            ("media", Value::Keyword(printer.media.clone())),
+            ("fit-to-page", Value::Boolean(true)),
+            ("orientation-requested", Value::Enum(orientation)),
+        ]);
+    }
+    let document = fs::read(pdf).with_context(|_| {
+        format!("Could not read pdf {}", pdf.display())
+    })?;
+    let uri = ipp::printer_uri(printer);
+    let job = ipp::print_job(&uri, title, &document, attributes)?;
+    println!("Submitted print job {}", job);
+    let timeout = printer.job_timeout.unwrap_or(JOB_TIMEOUT);
+    if timeout == 0 {
+        return Ok(JobEnd::Printed);
+    }
+    let state = match ipp::wait_for_job(
+        &uri,
+        job,
+        Duration::from_secs(timeout),
+    ) {
+        Ok(state) => state,
+        // reverting a job still queued would print it twice
+        Err(err) => {
+            return Ok(match ipp::cancel_job(&uri, job) {
+                Ok(()) => {
+                    eprintln!(
+                        "WARN: Canceled print job {}. Reverting its tickets: {:?}",
+                        job, err
+                    );
+                    JobEnd::Canceled
+                }
+                Err(cancel_err) => {
+                    eprintln!(
+                        "WARN: Print job {} may still be printed: {:?}: {:?}",
+                        job, err, cancel_err
+                    );
+                    JobEnd::Pending
+                }
+            });
         }
+    };
+    if state == JobState::Completed {
+        return Ok(JobEnd::Printed);
+    }
+    eprintln!(
+        "WARN: Print job {} ended {:?}. Reverting its tickets",
+        job, state
+    );
+    Ok(JobEnd::Canceled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        layout::TextFont,
+        services::{http::Http, TicketSource},
+    };
+    use std::cell::RefCell;
+
+    fn ticket() -> Ticket {
+        Ticket {
+            id: "1".into(),
+            label_id: "label".into(),
+            titel: "Print the title".into(),
+            subtitel: "PRJ-1".into(),
+            url: "https://example.com/PRJ-1".into(),
+            source: "Test".into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn fonts() -> Fonts {
+        Fonts::load(&PDfDimension::default(), &[], true).unwrap()
+    }
+
+    fn text_region(
+        text: &str,
+        lines: u32,
+        min_size: Option<f32>,
+    ) -> (Region, TextBox) {
+        let text_box = || TextBox {
+            text: text.into(),
+            lines,
+            size: Some(10.0),
+            min_size,
+            align: Align::Left,
+            valign: VAlign::Top,
+            font: None,
+            markdown: false,
+        };
+        let region = Region {
+            name: "title".into(),
+            x: 0.0,
+            y: 0.0,
+            width: 40.0,
+            height: 10.0 * lines as f32,
+            content: Content::Text(text_box()),
+        };
+        (region, text_box())
+    }
+
+    fn texts(lines: &[Pieces<'_>]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|piece| piece.text.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_titles_keep_their_size() {
+        let fonts = fonts();
+        let (region, text) = text_region("Short", 1, Some(4.0));
+        let wrapped =
+            wrap_region(&region, &text, &fonts, &ticket());
+        assert!((wrapped.size - 10.0).abs() < 0.001);
+        assert_eq!(texts(&wrapped.lines), ["Short"]);
+    }
+
+    #[test]
+    fn long_titles_shrink_until_they_fit() {
+        let fonts = fonts();
+        let title = "A rather long title that needs more room";
+        let (region, text) = text_region(title, 1, Some(2.0));
+        let wrapped =
+            wrap_region(&region, &text, &fonts, &ticket());
+        assert!(wrapped.size < 10.0);
+        assert!(wrapped.size >= 2.0);
+        // the smaller font leaves room for more lines
+        assert!(wrapped.lines.len() > 1);
+        assert_eq!(texts(&wrapped.lines).join(" "), title);
+        for line in &wrapped.lines {
+            assert!(
+                line_width(line, wrapped.size) <= region.width
+            );
+        }
+    }
+
+    #[test]
+    fn titles_are_ellipsized_below_the_minimum_size() {
+        let fonts = fonts();
+        let title = "A rather long title that needs more room";
+        let (region, text) = text_region(title, 1, Some(9.0));
+        let wrapped =
+            wrap_region(&region, &text, &fonts, &ticket());
+        assert!((wrapped.size - 9.0).abs() < 0.001);
+        assert_eq!(wrapped.lines.len(), 1);
+        let line = &texts(&wrapped.lines)[0];
+        assert!(line.ends_with(ELLIPSIS));
+        assert!(
+            title.starts_with(line.trim_end_matches(ELLIPSIS))
+        );
+        assert!(
+            line_width(&wrapped.lines[0], 9.0) <= region.width
+        );
+    }
+
+    #[test]
+    fn titles_without_minimum_size_are_cut_off() {
+        let fonts = fonts();
+        let title = "A rather long title that needs more room";
+        let (region, text) = text_region(title, 2, None);
+        let wrapped =
+            wrap_region(&region, &text, &fonts, &ticket());
+        assert!((wrapped.size - 10.0).abs() < 0.001);
+        assert_eq!(wrapped.lines.len(), 2);
+        assert!(texts(&wrapped.lines)[1].ends_with(ELLIPSIS));
+    }
+
+    #[test]
+    fn long_words_are_split() {
+        let fonts = fonts();
+        let font = CardFont::Builtin(TextFont::Helvetica);
+        let word = "W".repeat(20);
+        let paragraphs =
+            vec![words(&markup::plain(&word)[0], font, &fonts)];
+        let wrapped =
+            wrap_paragraphs(&paragraphs, 10.0, 40.0, 10);
+        assert!(wrapped.split_words);
+        assert!(!wrapped.fits());
+        assert_eq!(texts(&wrapped.lines).concat(), word);
+    }
+
+    #[test]
+    fn placeholders_are_filled_with_ticket_values() {
+        let mut ticket = ticket();
+        let _ = ticket
+            .fields
+            .insert("priority".into(), "High".into());
+        assert_eq!(
+            fill_placeholders(
+                "{key}: {fields.priority}",
+                &ticket
+            ),
+            Some("PRJ-1: High".into())
+        );
+        assert_eq!(
+            fill_placeholders("Due {fields.due}", &ticket),
+            None
+        );
+        assert_eq!(
+            fill_placeholders("no placeholder", &ticket),
+            Some("no placeholder".into())
+        );
+    }
+
+    /// Canvas remembering the filled polygons
+    #[derive(Default)]
+    struct Polygons(Vec<(Color, Vec<(f32, f32)>)>);
+
+    impl Canvas for Polygons {
+        fn fill_polygon(
+            &mut self,
+            color: Color,
+            points: &[(f32, f32)],
+        ) {
+            self.0.push((color, points.to_vec()));
+        }
+
+        fn draw_image(
+            &mut self,
+            _image: DynamicImage,
+            _x: f32,
+            _y: f32,
+            _scale: f32,
+        ) {
+        }
+
+        fn draw_text(
+            &mut self,
+            _piece: &Piece<'_>,
+            _size: f32,
+            _x: f32,
+            _baseline: f32,
+        ) {
+        }
+    }
+
+    fn band(value: Option<&str>, shape: Shape) -> Region {
+        Region {
+            name: "band".into(),
+            x: 1.0,
+            y: 2.0,
+            width: 10.0,
+            height: 5.0,
+            content: Content::Band {
+                color: Some(BLACK),
+                value: value.map(String::from),
+                shape,
+            },
+        }
+    }
+
+    #[test]
+    fn band_colors_come_from_config_or_trello_names() {
+        let red = Color::rgb(0xff, 0, 0);
+        let mut colors = BTreeMap::new();
+        let _ = colors.insert("high".to_string(), red);
+        assert_eq!(band_color("High", &colors), Some(red));
+        assert_eq!(
+            band_color("Backend, high", &colors),
+            Some(red)
+        );
+        assert_eq!(
+            band_color("green_dark", &colors),
+            Some(Color::rgb(0x61, 0xbd, 0x4f))
+        );
+        assert_eq!(band_color("Backend", &colors), None);
+    }
+
+    #[test]
+    fn bands_fall_back_to_their_color() {
+        let mut ticket = ticket();
+        let _ =
+            ticket.fields.insert("priority".into(), "Low".into());
+        let mut canvas = Polygons::default();
+        let region =
+            band(Some("{fields.priority}"), Shape::Rectangle);
+        draw_region(
+            &mut canvas,
+            &region,
+            None,
+            &ticket,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let region =
+            band(Some("{fields.label_color}"), Shape::Rectangle);
+        draw_region(
+            &mut canvas,
+            &region,
+            None,
+            &ticket,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_eq!(canvas.0.len(), 2);
+        assert!(canvas
+            .0
+            .iter()
+            .all(|(color, _)| *color == BLACK));
+        assert_eq!(
+            canvas.0[0].1,
+            [(1.0, 2.0), (11.0, 2.0), (11.0, 7.0), (1.0, 7.0)]
+        );
+    }
+
+    #[test]
+    fn corner_bands_are_triangles() {
+        let corners = [
+            (
+                Shape::TopLeft,
+                [(1.0, 7.0), (11.0, 7.0), (1.0, 2.0)],
+            ),
+            (
+                Shape::TopRight,
+                [(11.0, 7.0), (11.0, 2.0), (1.0, 7.0)],
+            ),
+            (
+                Shape::BottomLeft,
+                [(1.0, 2.0), (1.0, 7.0), (11.0, 2.0)],
+            ),
+            (
+                Shape::BottomRight,
+                [(11.0, 2.0), (1.0, 2.0), (11.0, 7.0)],
+            ),
+        ];
+        for (shape, points) in &corners {
+            let mut canvas = Polygons::default();
+            fill_band(
+                &mut canvas,
+                BLACK,
+                &band(None, *shape),
+                *shape,
+            );
+            assert_eq!(canvas.0.len(), 1);
+            assert_eq!(canvas.0[0].1, points, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn orientations_accept_keywords_and_lp_options() {
+        assert_eq!(orientation("portrait"), Some(3));
+        assert_eq!(orientation("landscape"), Some(4));
+        assert_eq!(orientation(" reverse-portrait "), Some(6));
+        assert_eq!(
+            orientation("orientation-requested=5"),
+            Some(5)
+        );
+        assert_eq!(orientation("4"), Some(4));
+        assert_eq!(orientation("orientation-requested=7"), None);
+        assert_eq!(orientation("sideways"), None);
+    }
+
+    /// Source remembering finished and reverted tickets
+    #[derive(Debug, Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl TicketSource for Recorder {
+        fn name(&self) -> &'static str {
+            "Test"
+        }
+
+        fn fetch_tickets(
+            &self,
+            _http: &Http,
+            _tickets: &mut Vec<Ticket>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn consume_ticket(
+            &self,
+            _http: &Http,
+            _ticket: &Ticket,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn finish_ticket(
+            &self,
+            _http: &Http,
+            ticket: &Ticket,
+            _printer: Option<&Printer>,
+        ) -> Result<()> {
+            self.0
+                .borrow_mut()
+                .push(format!("finish {}", ticket.id));
+            Ok(())
+        }
+
+        fn revert_ticket(
+            &self,
+            _http: &Http,
+            ticket: &Ticket,
+        ) -> Result<()> {
+            self.0
+                .borrow_mut()
+                .push(format!("revert {}", ticket.id));
+            Ok(())
+        }
+    }
+
+    /// Prints the tickets 1 and 2, each ending as given
+    fn print_ending(end: JobEnd) -> (usize, Vec<String>) {
+        let recorder = Recorder::default();
+        let sources = Sources::new(vec![&recorder], None);
+        let config: Config = toml::from_str("").unwrap();
+        // without url no shortener is asked
+        let mut tickets = vec![ticket(), ticket()];
+        tickets[1].id = "2".into();
+        for ticket in &mut tickets {
+            ticket.url.clear();
+        }
+        let failed =
+            print_each(&config, &sources, &mut tickets, |_| {
+                Ok(end)
+            });
+        assert!(tickets.is_empty());
+        (failed, recorder.0.into_inner())
+    }
+
+    #[test]
+    fn printed_tickets_are_finished() {
+        assert_eq!(
+            print_ending(JobEnd::Printed),
+            (0, vec!["finish 2".into(), "finish 1".into()])
+        );
+    }
+
+    #[test]
+    fn canceled_tickets_are_reverted() {
+        assert_eq!(
+            print_ending(JobEnd::Canceled),
+            (0, vec!["revert 2".into(), "revert 1".into()])
+        );
+    }
+
+    #[test]
+    fn pending_tickets_stay_consumed() {
+        assert_eq!(
+            print_ending(JobEnd::Pending),
+            (2, Vec::new())
+        );
+    }
+
+    #[test]
+    fn pending_tickets_leave_the_journal() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        Journal::new(path.clone()).add(&ticket()).unwrap();
+        let recorder = Recorder::default();
+        let sources = Sources::new(
+            vec![&recorder],
+            Some(Journal::new(path)),
+        );
+        let config: Config = toml::from_str("").unwrap();
+        let failed = finish_job(
+            &config,
+            &sources,
+            &[ticket()],
+            JobEnd::Pending,
+        );
+        assert_eq!(failed, 1);
+        // nothing is reverted on the next start
+        sources.recover().unwrap();
+        assert!(recorder.0.into_inner().is_empty());
+    }
+
+    #[test]
+    fn failing_tickets_are_reverted() {
+        let recorder = Recorder::default();
+        let sources = Sources::new(vec![&recorder], None);
+        let config: Config = toml::from_str("").unwrap();
+        let mut tickets = vec![ticket()];
+        tickets[0].url.clear();
+        let failed =
+            print_each(&config, &sources, &mut tickets, |_| {
+                Err(failure::err_msg("out of paper").into())
+            });
+        assert_eq!(failed, 1);
+        assert_eq!(recorder.0.into_inner(), ["revert 1"]);
+    }
+
+    /// Prints a pdf on a printer answering with the given responses
+    fn print_on(responses: Vec<Vec<u8>>) -> (JobEnd, Vec<u16>) {
+        let (uri, server) = ipp::tests::serve(responses);
+        let config: Config = toml::from_str(&format!(
+            "[printer]\nmedia = 'A4'\norientation = 'landscape'\nnumber_of_copies = 1\nname = 'test'\nuri = '{}'\njob_timeout = 1",
+            uri
+        ))
+        .unwrap();
+        let dir = tempdir().unwrap();
+        let pdf = dir.path().join("card.pdf");
+        fs::write(&pdf, b"%PDF-1.3").unwrap();
+        let grid = Grid::card(&config.pdf);
+        let end =
+            print_pdf(&config, &grid, &pdf, "Card").unwrap();
+        // the operation of each request is at the place of the status
+        let operations = server
+            .join()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                u16::from_be_bytes([request[2], request[3]])
+            })
+            .collect();
+        (end, operations)
+    }
+
+    fn job_state(state: i32) -> Vec<u8> {
+        ipp::tests::response(
+            0,
+            vec![("job-state", Value::Enum(state))],
+        )
+    }
+
+    fn job_created() -> Vec<u8> {
+        ipp::tests::response(
+            0,
+            vec![("job-id", Value::Integer(7))],
+        )
+    }
+
+    #[test]
+    fn completed_jobs_are_printed() {
+        assert_eq!(
+            print_on(vec![job_created(), job_state(9)]),
+            (JobEnd::Printed, vec![0x0002, 0x0009])
+        );
+    }
+
+    #[test]
+    fn aborted_jobs_are_canceled() {
+        assert_eq!(
+            print_on(vec![job_created(), job_state(8)]).0,
+            JobEnd::Canceled
+        );
+        assert_eq!(
+            print_on(vec![job_created(), job_state(7)]).0,
+            JobEnd::Canceled
+        );
+    }
+
+    #[test]
+    fn jobs_still_queued_are_canceled_after_the_timeout() {
+        // pending twice, then the job is canceled
+        assert_eq!(
+            print_on(vec![
+                job_created(),
+                job_state(3),
+                job_state(3),
+                ipp::tests::response(0, vec![]),
+            ]),
+            (
+                JobEnd::Canceled,
+                vec![0x0002, 0x0009, 0x0009, 0x0008]
+            )
+        );
+    }
+
+    #[test]
+    fn jobs_refusing_to_cancel_stay_pending() {
+        assert_eq!(
+            print_on(vec![
+                job_created(),
+                job_state(3),
+                job_state(3),
+                ipp::tests::response(0x0507, vec![]),
+            ]),
+            (
+                JobEnd::Pending,
+                vec![0x0002, 0x0009, 0x0009, 0x0008]
+            )
+        );
+    }
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    const PRINTER: &str = "[printer]\nmedia = 'A4'\nnumber_of_copies = 1\nname = 'test'\n";
+
+    #[test]
+    fn validate_accepts_the_default_card() {
+        assert!(validate(&config("")).is_ok());
+        assert!(validate(&config(&format!(
+            "{}orientation = 'orientation-requested=4'",
+            PRINTER
+        )))
+        .is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_orientations() {
+        let err = validate(&config(&format!(
+            "{}orientation = 'sideways'",
+            PRINTER
+        )))
+        .unwrap_err();
+        assert!(format!("{:?}", err).contains("sideways"));
+        // sheets and receipts ignore the orientation
+        assert!(validate(&config(&format!(
+            "{}orientation = 'sideways'\n[sheet]",
+            PRINTER
+        )))
+        .is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_conflicting_printers() {
+        let receipt = format!(
+            "{}orientation = 'landscape'\n[printer.receipt]\noutput = 'out'\n",
+            PRINTER
+        );
+        assert!(validate(&config(&receipt)).is_ok());
+        assert!(validate(&config(&format!(
+            "{}[sheet]",
+            receipt
+        )))
+        .is_err());
+        assert!(validate(&config(&format!(
+            "{}[printer.label]\nlanguage = 'zpl'\noutput = 'out'",
+            receipt
+        )))
+        .is_err());
+        assert!(validate(&config(&format!(
+            "{}orientation = 'landscape'\n[printer.label]\nlanguage = 'zpl'\noutput = 'out'\n[sheet]",
+            PRINTER
+        )))
+        .is_err());
+    }
+
+    #[test]
+    fn validate_loads_layouts_and_fonts() {
+        let mut missing_layout = config("");
+        missing_layout.pdf.layout =
+            Some("/nonexistent/layout.toml".into());
+        assert!(validate(&missing_layout).is_err());
+        // label printers only print font files
+        assert!(validate(&config(&format!(
+            "{}orientation = 'landscape'\n[printer.label]\nlanguage = 'zpl'\noutput = 'out'",
+            PRINTER
+        )))
+        .is_err());
+        // the cards do not fit onto the sheet
+        assert!(
+            validate(&config("[sheet]\nsize = '50x50'")).is_err()
+        );
     }
-    Ok(())
 }
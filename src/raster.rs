@@ -0,0 +1,335 @@
+use crate::{
+    font::CardFont,
+    layout::Color,
+    pdf::{Canvas, Piece},
+};
+use image::DynamicImage;
+use std::{cmp::Ordering, iter};
+
+const MM_PER_INCH: f32 = 25.4;
+/// Thresholds of an ordered dither. Colours and photos turn into a
+/// pattern of dots with about the same brightness.
+const DITHER: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+const BLACK: Color = Color::rgb(0, 0, 0);
+
+/// A card as 1 bit image for label printers. Rows run from the top to
+/// the bottom of the card, `true` is a printed dot.
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    dots_per_mm: f32,
+    dots: Vec<bool>,
+}
+
+impl Bitmap {
+    /// An empty bitmap of `width` x `height` millimeter
+    pub fn new(width: f32, height: f32, dpi: u32) -> Self {
+        let dots_per_mm = dpi as f32 / MM_PER_INCH;
+        let width =
+            (width * dots_per_mm).round().max(1.0) as usize;
+        let height =
+            (height * dots_per_mm).round().max(1.0) as usize;
+        Self {
+            width,
+            height,
+            dots_per_mm,
+            dots: vec![false; width * height],
+        }
+    }
+
+    /// Turns the bitmap a quarter clockwise
+    pub fn rotated(&self) -> Self {
+        let dots = (0..self.width)
+            .flat_map(|x| {
+                (0..self.height)
+                    .rev()
+                    .map(move |y| self.dot(x, y))
+            })
+            .collect();
+        Self {
+            width: self.height,
+            height: self.width,
+            dots_per_mm: self.dots_per_mm,
+            dots,
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[bool]> + '_ {
+        self.dots.chunks(self.width)
+    }
+
+    fn dot(&self, x: usize, y: usize) -> bool {
+        self.dots
+            .get(y * self.width + x)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Sets a dot to the dithered shade of `color`
+    fn paint(&mut self, x: usize, y: usize, color: Color) {
+        let luma = 0.114_f32.mul_add(
+            f32::from(color.blue),
+            0.299_f32.mul_add(
+                f32::from(color.red),
+                0.587 * f32::from(color.green),
+            ),
+        );
+        let threshold = DITHER
+            .get(y % 4)
+            .and_then(|row| row.get(x % 4))
+            .map_or(8.0, |&level| f32::from(level) + 0.5)
+            * 16.0;
+        let index = y * self.width + x;
+        if let Some(dot) = self.dots.get_mut(index) {
+            *dot = luma < threshold;
+        }
+    }
+
+    /// Converts millimeter from the lower left corner to dots from the
+    /// upper left corner
+    fn to_dots(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            x * self.dots_per_mm,
+            y.mul_add(-self.dots_per_mm, self.height as f32),
+        )
+    }
+
+    /// Fills closed contours using the nonzero winding rule, so holes
+    /// of glyphs like `o` stay empty
+    fn fill_contours(
+        &mut self,
+        color: Color,
+        contours: &[Vec<(f32, f32)>],
+    ) {
+        let edges: Vec<((f32, f32), (f32, f32))> = contours
+            .iter()
+            .flat_map(|contour| {
+                let next =
+                    contour.iter().skip(1).chain(contour.first());
+                contour.iter().zip(next)
+            })
+            .map(|(&from, &to)| {
+                (self.to_dots(from), self.to_dots(to))
+            })
+            .collect();
+        for row in 0..self.height {
+            // dots are filled if their center is inside
+            let y = row as f32 + 0.5;
+            let mut crossings: Vec<(f32, i32)> = edges
+                .iter()
+                .filter(|((_, y0), (_, y1))| {
+                    (*y0 <= y) != (*y1 <= y)
+                })
+                .map(|&((x0, y0), (x1, y1))| {
+                    let x = x0 + (y - y0) * (x1 - x0) / (y1 - y0);
+                    (x, if y1 > y0 { 1 } else { -1 })
+                })
+                .collect();
+            crossings.sort_by(|a, b| {
+                a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal)
+            });
+            let mut winding = 0;
+            let mut start = 0.0;
+            for (x, direction) in crossings {
+                let inside = winding != 0;
+                winding += direction;
+                if !inside && winding != 0 {
+                    start = x;
+                } else if inside && winding == 0 {
+                    self.fill_span(row, start, x, color);
+                }
+            }
+        }
+    }
+
+    fn fill_span(
+        &mut self,
+        row: usize,
+        start: f32,
+        end: f32,
+        color: Color,
+    ) {
+        let first = (start - 0.5).ceil().max(0.0) as usize;
+        let last = ((end - 0.5).ceil().max(0.0) as usize)
+            .min(self.width);
+        for x in first..last {
+            self.paint(x, row, color);
+        }
+    }
+}
+
+impl Canvas for Bitmap {
+    fn fill_polygon(
+        &mut self,
+        color: Color,
+        points: &[(f32, f32)],
+    ) {
+        self.fill_contours(color, &[points.to_vec()]);
+    }
+
+    fn draw_image(
+        &mut self,
+        image: DynamicImage,
+        x: f32,
+        y: f32,
+        scale: f32,
+    ) {
+        let image = image.to_luma();
+        let (width, height) = image.dimensions();
+        let (left, top) =
+            self.to_dots((x, (height as f32).mul_add(scale, y)));
+        let (right, bottom) =
+            self.to_dots(((width as f32).mul_add(scale, x), y));
+        let dots_per_pixel = scale * self.dots_per_mm;
+        let rows = (top.max(0.0) as usize)
+            ..(bottom.max(0.0) as usize).min(self.height);
+        let columns = (left.max(0.0) as usize)
+            ..(right.max(0.0) as usize).min(self.width);
+        for row in rows {
+            let source_y = ((row as f32 + 0.5 - top)
+                / dots_per_pixel)
+                .max(0.0) as u32;
+            for column in columns.clone() {
+                let source_x = ((column as f32 + 0.5 - left)
+                    / dots_per_pixel)
+                    .max(0.0)
+                    as u32;
+                if source_x < width && source_y < height {
+                    let level =
+                        image.get_pixel(source_x, source_y).0;
+                    let level =
+                        level.first().copied().unwrap_or(255);
+                    self.paint(
+                        column,
+                        row,
+                        Color::rgb(level, level, level),
+                    );
+                }
+            }
+        }
+    }
+
+    fn draw_text(
+        &mut self,
+        piece: &Piece<'_>,
+        size: f32,
+        x: f32,
+        baseline: f32,
+    ) {
+        // fonts are resolved without builtin fonts for label printers
+        if let CardFont::File(file) = piece.font {
+            let outlines =
+                file.outlines(size, &piece.text, x, baseline);
+            self.fill_contours(BLACK, &outlines);
+        }
+    }
+}
+
+/// Packs dots into bytes, the first dot is the most significant bit.
+/// The last byte is padded with empty dots.
+pub fn pack(dots: &[bool]) -> Vec<u8> {
+    dots.chunks(8)
+        .map(|bits| {
+            bits.iter()
+                .chain(iter::repeat(&false))
+                .take(8)
+                .fold(0, |byte, &bit| (byte << 1) | u8::from(bit))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 dots per millimeter
+    const DPI: u32 = 254;
+
+    fn dots(bitmap: &Bitmap) -> Vec<String> {
+        bitmap
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|&dot| if dot { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_puts_the_first_dot_first() {
+        assert_eq!(
+            pack(&[
+                true, false, false, false, false, false, false,
+                true
+            ]),
+            [0x81]
+        );
+        assert_eq!(pack(&[true; 10]), [0xff, 0xc0]);
+        assert!(pack(&[]).is_empty());
+    }
+
+    #[test]
+    fn polygons_fill_dots_from_the_top_left() {
+        let mut bitmap = Bitmap::new(0.6, 0.3, DPI);
+        assert_eq!((bitmap.width, bitmap.height), (6, 3));
+        // lower left square of 2 x 2 dots
+        bitmap.fill_polygon(
+            BLACK,
+            &[(0.0, 0.0), (0.2, 0.0), (0.2, 0.2), (0.0, 0.2)],
+        );
+        assert_eq!(dots(&bitmap), ["......", "##....", "##...."]);
+    }
+
+    #[test]
+    fn holes_stay_empty() {
+        let mut bitmap = Bitmap::new(0.5, 0.5, DPI);
+        let outer =
+            vec![(0.0, 0.0), (0.5, 0.0), (0.5, 0.5), (0.0, 0.5)];
+        let inner =
+            vec![(0.1, 0.1), (0.1, 0.4), (0.4, 0.4), (0.4, 0.1)];
+        bitmap.fill_contours(BLACK, &[outer, inner]);
+        assert_eq!(
+            dots(&bitmap),
+            ["#####", "#...#", "#...#", "#...#", "#####"]
+        );
+    }
+
+    #[test]
+    fn colors_are_dithered() {
+        let mut bitmap = Bitmap::new(0.4, 0.4, DPI);
+        bitmap.fill_polygon(
+            Color::rgb(0x80, 0x80, 0x80),
+            &[(0.0, 0.0), (0.4, 0.0), (0.4, 0.4), (0.0, 0.4)],
+        );
+        let printed =
+            bitmap.dots.iter().filter(|&&dot| dot).count();
+        assert_eq!(printed, 8);
+        let mut white = Bitmap::new(0.4, 0.4, DPI);
+        white.fill_polygon(
+            Color::rgb(0xff, 0xff, 0xff),
+            &[(0.0, 0.0), (0.4, 0.0), (0.4, 0.4), (0.0, 0.4)],
+        );
+        assert!(white.dots.iter().all(|&dot| !dot));
+    }
+
+    #[test]
+    fn rotation_turns_clockwise() {
+        let mut bitmap = Bitmap::new(0.3, 0.2, DPI);
+        // top left dot
+        bitmap.fill_polygon(
+            BLACK,
+            &[(0.0, 0.1), (0.1, 0.1), (0.1, 0.2), (0.0, 0.2)],
+        );
+        assert_eq!(dots(&bitmap), ["#..", "..."]);
+        let rotated = bitmap.rotated();
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        assert_eq!(dots(&rotated), [".#", "..", ".."]);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    config::{PDfDimension, Printer, ReceiptPrinter},
+    label, pdf,
+    services::Ticket,
+    Result,
+};
+use failure::ResultExt;
+use std::convert::TryFrom;
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+/// Code page WPC1252, covering most latin characters
+const CODE_PAGE: u8 = 16;
+/// Characters of WPC1252 outside of latin 1
+const WINDOWS_1252: &[(char, u8)] = &[
+    ('\u{20ac}', 0x80),
+    ('\u{201a}', 0x82),
+    ('\u{201e}', 0x84),
+    ('\u{2026}', 0x85),
+    ('\u{2018}', 0x91),
+    ('\u{2019}', 0x92),
+    ('\u{201c}', 0x93),
+    ('\u{201d}', 0x94),
+    ('\u{2022}', 0x95),
+    ('\u{2013}', 0x96),
+    ('\u{2014}', 0x97),
+];
+/// Width of a QR code module in dots
+const QR_MODULE: u8 = 6;
+/// Characters per line of 58 mm paper
+const COLUMNS: usize = 32;
+/// Lines fed before cutting, so the QR code clears the cutter
+const CUT_FEED: u8 = 3;
+
+/// Prints a ticket `printer.number_of_copies` times: the title in
+/// double height, the key, `pdf.field_lines` and a QR code of the url
+pub fn print(
+    printer: &Printer,
+    receipt: &ReceiptPrinter,
+    pdf: &PDfDimension,
+    ticket: &Ticket,
+) -> Result<()> {
+    let columns = receipt.columns.unwrap_or(COLUMNS).max(1);
+    let mut copy = vec![ESC, b'a', 0, ESC, b'!', 0x10];
+    for line in wrap(&ticket.titel, columns) {
+        push_line(&mut copy, &line);
+    }
+    copy.extend(&[ESC, b'!', 0, ESC, b'E', 1]);
+    push_line(&mut copy, &ticket.subtitel);
+    copy.extend(&[ESC, b'E', 0]);
+    for field_line in &pdf.field_lines {
+        if let Some(text) =
+            pdf::fill_placeholders(field_line, ticket)
+        {
+            for line in wrap(&text, columns) {
+                push_line(&mut copy, &line);
+            }
+        }
+    }
+    push_qrcode(&mut copy, &ticket.url);
+    copy.extend(&[GS, b'V', 66, CUT_FEED]);
+    let mut data = vec![ESC, b'@', ESC, b't', CODE_PAGE];
+    for _ in 0..printer.number_of_copies {
+        data.extend(&copy);
+    }
+    label::send(&receipt.output, &data).with_context(|_| {
+        format!("Could not send receipt to {}", receipt.output)
+    })?;
+    Ok(())
+}
+
+/// Centered QR code using the printers own QR code commands
+fn push_qrcode(data: &mut Vec<u8>, url: &str) {
+    let length = match u16::try_from(url.len() + 3) {
+        Ok(length) if !url.is_empty() => length.to_le_bytes(),
+        _ => return,
+    };
+    data.extend(&[ESC, b'a', 1]);
+    // model 2, module size, error correction level L
+    data.extend(&[GS, b'(', b'k', 4, 0, 49, 65, 50, 0]);
+    data.extend(&[GS, b'(', b'k', 3, 0, 49, 67, QR_MODULE]);
+    data.extend(&[GS, b'(', b'k', 3, 0, 49, 69, 48]);
+    // store the data, then print it
+    data.extend(&[GS, b'(', b'k']);
+    data.extend(&length);
+    data.extend(&[49, 80, 48]);
+    data.extend(url.as_bytes());
+    data.extend(&[GS, b'(', b'k', 3, 0, 49, 81, 48, b'\n']);
+    data.extend(&[ESC, b'a', 0]);
+}
+
+fn push_line(data: &mut Vec<u8>, line: &str) {
+    data.extend(line.chars().map(encode));
+    data.push(b'\n');
+}
+
+/// Maps a character to the code page, unknown characters become `?`
+fn encode(c: char) -> u8 {
+    if (c.is_ascii() && !c.is_ascii_control()) || c >= '\u{a0}' {
+        if let Ok(byte) = u8::try_from(u32::from(c)) {
+            return byte;
+        }
+    }
+    WINDOWS_1252
+        .iter()
+        .find(|(special, _)| *special == c)
+        .map_or(b'?', |(_, byte)| *byte)
+}
+
+/// Wraps text at whitespace into lines of at most `columns`
+/// characters. Longer words are split.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        let length = line.chars().count();
+        if length > 0 && length + 1 + word.len() <= columns {
+            line.push(' ');
+        } else if length > 0 {
+            lines.push(line.split_off(0));
+        }
+        while word.len() > columns {
+            let rest = word.split_off(columns);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::BTreeMap, io::Read, net::TcpListener, thread,
+    };
+
+    const URL: &str = "https://example.com/PRJ-1";
+
+    fn printer(copies: u16) -> Printer {
+        Printer {
+            media: "A4".into(),
+            orientation: "portrait".into(),
+            number_of_copies: copies,
+            name: "receipt".into(),
+            uri: None,
+            job_timeout: None,
+            duplex: None,
+            label: None,
+            receipt: None,
+        }
+    }
+
+    fn ticket() -> Ticket {
+        Ticket {
+            id: "1".into(),
+            label_id: "label".into(),
+            titel: "Fix the printer".into(),
+            subtitel: "PRJ-1".into(),
+            url: URL.into(),
+            source: "Test".into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Prints the ticket to a local socket and returns the bytes
+    fn print_to_socket(copies: u16) -> Vec<u8> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut data = Vec::new();
+            let _ = stream.read_to_end(&mut data).unwrap();
+            data
+        });
+        let receipt = ReceiptPrinter {
+            output: format!("tcp://127.0.0.1:{}", port),
+            columns: None,
+        };
+        print(
+            &printer(copies),
+            &receipt,
+            &PDfDimension::default(),
+            &ticket(),
+        )
+        .unwrap();
+        server.join().unwrap()
+    }
+
+    fn copy() -> Vec<u8> {
+        let mut copy = vec![ESC, b'a', 0, ESC, b'!', 0x10];
+        copy.extend(b"Fix the printer\n");
+        copy.extend(&[ESC, b'!', 0, ESC, b'E', 1]);
+        copy.extend(b"PRJ-1\n");
+        copy.extend(&[ESC, b'E', 0]);
+        copy.extend(&[ESC, b'a', 1]);
+        copy.extend(&[GS, b'(', b'k', 4, 0, 49, 65, 50, 0]);
+        copy.extend(&[GS, b'(', b'k', 3, 0, 49, 67, 6]);
+        copy.extend(&[GS, b'(', b'k', 3, 0, 49, 69, 48]);
+        copy.extend(&[GS, b'(', b'k', 28, 0, 49, 80, 48]);
+        copy.extend(URL.as_bytes());
+        copy.extend(&[GS, b'(', b'k', 3, 0, 49, 81, 48, b'\n']);
+        copy.extend(&[ESC, b'a', 0]);
+        // partial cut after feeding 3 lines
+        copy.extend(&[GS, b'V', 66, 3]);
+        copy
+    }
+
+    #[test]
+    fn receipts_are_initialized_printed_and_cut() {
+        let mut expected = vec![ESC, b'@', ESC, b't', 16];
+        expected.extend(copy());
+        assert_eq!(print_to_socket(1), expected);
+    }
+
+    #[test]
+    fn copies_share_the_initialization() {
+        let mut expected = vec![ESC, b'@', ESC, b't', 16];
+        expected.extend(copy());
+        expected.extend(copy());
+        assert_eq!(print_to_socket(2), expected);
+    }
+
+    #[test]
+    fn characters_use_the_code_page() {
+        assert_eq!(encode('a'), b'a');
+        assert_eq!(encode('\u{e4}'), 0xe4);
+        assert_eq!(encode('\u{20ac}'), 0x80);
+        assert_eq!(encode('\u{2022}'), 0x95);
+        assert_eq!(encode('\u{4e2d}'), b'?');
+        assert_eq!(encode('\t'), b'?');
+    }
+
+    #[test]
+    fn wrap_splits_at_whitespace_and_long_words() {
+        assert_eq!(
+            wrap("Fix the printer in room 4", 10),
+            ["Fix the", "printer in", "room 4"]
+        );
+        assert_eq!(
+            wrap("abcdefghijkl mn", 5),
+            ["abcde", "fghij", "kl mn"]
+        );
+        assert!(wrap("  ", 5).is_empty());
+    }
+}
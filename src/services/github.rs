@@ -0,0 +1,305 @@
+use crate::{
+    config::Github,
+    services::{http::Http, Ticket, TicketSource},
+};
+use failure::ResultExt;
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION, USER_AGENT},
+    Client, Error, RequestBuilder, Response, Url,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+const NAME: &str = "GitHub";
+const DEFAULT_API_URL: &str = "https://api.github.com";
+const PER_PAGE: usize = 100;
+
+impl TicketSource for Github {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn fetch_tickets(
+        &self,
+        http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()> {
+        if self.limit_to_repositories.is_empty() {
+            let url = build_url(self, &["issues"])?;
+            let issues = get_issues(http, self, &url, true)
+                .with_context(|_| {
+                    "Could not fetch GitHub Issues".to_string()
+                })?;
+            handle_issues(issues, self, tickets);
+        } else {
+            for repository in &self.limit_to_repositories {
+                let mut segments = vec!["repos"];
+                segments.extend(repository.split('/'));
+                segments.push("issues");
+                let url = build_url(self, &segments)?;
+                let issues = get_issues(http, self, &url, false)
+                    .with_context(|_| {
+                        format!(
+                        "Could not fetch GitHub Issues for {}",
+                        repository
+                    )
+                    })?;
+                handle_issues(issues, self, tickets);
+            }
+        }
+        Ok(())
+    }
+
+    fn consume_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        remove_label(http, self, &ticket.id).with_context(
+            |_| {
+                format!(
+                    "Could not remove Label {} from Issue {}",
+                    &self.print_label, &ticket.id
+                )
+            },
+        )?;
+        Ok(())
+    }
+
+    fn revert_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        add_label(http, self, &ticket.id).with_context(|_| {
+            format!(
+                "Could not add Label {} to Issue {}",
+                &self.print_label, &ticket.id
+            )
+        })?;
+        Ok(())
+    }
+}
+
+fn handle_issues(
+    issues: Vec<Issue>,
+    github: &Github,
+    tickets: &mut Vec<Ticket>,
+) {
+    // pull requests are issues too, but are not printed
+    for issue in issues.into_iter().filter(|issue| {
+        issue.pull_request.as_ref().is_none_or(Value::is_null)
+    }) {
+        let repository = repository_name(&issue.repository_url);
+        tickets.push(Ticket {
+            id: format!("{}#{}", repository, issue.number),
+            label_id: github.print_label.clone(),
+            titel: issue.title,
+            subtitel: format!("#{}", issue.number),
+            url: issue.html_url,
+            source: NAME.into(),
+            fields: BTreeMap::new(),
+        });
+    }
+}
+
+/// Extracts `owner/repo` from an api url like
+/// `https://api.github.com/repos/owner/repo`
+fn repository_name(repository_url: &str) -> String {
+    let mut parts = repository_url.rsplitn(3, '/');
+    let repo = parts.next().unwrap_or_default();
+    let owner = parts.next().unwrap_or_default();
+    format!("{}/{}", owner, repo)
+}
+
+/// Splits a ticket id like `owner/repo#42` into its parts
+fn split_id(id: &str) -> (Vec<&str>, &str) {
+    let mut parts = id.rsplitn(2, '#');
+    let number = parts.next().unwrap_or_default();
+    let repository = parts.next().unwrap_or_default();
+    (repository.split('/').collect(), number)
+}
+
+fn build_url(
+    github: &Github,
+    segments: &[&str],
+) -> Result<Url, failure::Error> {
+    let api_url = github
+        .api_url
+        .as_ref()
+        .map_or(DEFAULT_API_URL, String::as_str);
+    let mut url = Url::parse(api_url).with_context(|_| {
+        format!("Invalid GitHub api url {}", api_url)
+    })?;
+    let _ = url
+        .path_segments_mut()
+        .map_err(|()| {
+            failure::format_err!(
+                "Invalid GitHub api url {}",
+                api_url
+            )
+        })?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+fn fetch_resource(
+    http: &Http,
+    request: impl Fn(&Client) -> RequestBuilder,
+    github: &Github,
+) -> Result<Response, Error> {
+    http.send(|client| {
+        request(client)
+            .header(
+                AUTHORIZATION,
+                format!("token {}", github.token),
+            )
+            .header(ACCEPT, "application/vnd.github.v3+json")
+            .header(USER_AGENT, env!("CARGO_PKG_NAME"))
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    pub repository_url: String,
+    /// Set if the issue is a pull request
+    #[serde(default)]
+    pub pull_request: Option<Value>,
+}
+
+fn get_issues(
+    http: &Http,
+    github: &Github,
+    url: &Url,
+    all_repositories: bool,
+) -> Result<Vec<Issue>, failure::Error> {
+    let mut issues = Vec::new();
+    let mut page = 1;
+    loop {
+        let mut params = vec![
+            ("labels", github.print_label.clone()),
+            ("state", "open".to_string()),
+            ("per_page", PER_PAGE.to_string()),
+            ("page", page.to_string()),
+        ];
+        if all_repositories {
+            params.push(("filter", "all".to_string()));
+        }
+        let list: Vec<Issue> = fetch_resource(
+            http,
+            |client| client.get(url.clone()).query(&params),
+            github,
+        )?
+        .json()?;
+        let last_page = list.len() < PER_PAGE;
+        issues.extend(list);
+        if last_page {
+            return Ok(issues);
+        }
+        page += 1;
+    }
+}
+
+fn remove_label(
+    http: &Http,
+    github: &Github,
+    id: &str,
+) -> Result<(), failure::Error> {
+    let (repository, number) = split_id(id);
+    let mut segments = vec!["repos"];
+    segments.extend(repository);
+    segments.extend(&["issues", number, "labels"]);
+    segments.push(&github.print_label);
+    let url = build_url(github, &segments)?;
+    let _ = fetch_resource(
+        http,
+        |client| client.delete(url.clone()),
+        github,
+    )?;
+    Ok(())
+}
+
+fn add_label(
+    http: &Http,
+    github: &Github,
+    id: &str,
+) -> Result<(), failure::Error> {
+    let (repository, number) = split_id(id);
+    let mut segments = vec!["repos"];
+    segments.extend(repository);
+    segments.extend(&["issues", number, "labels"]);
+    let url = build_url(github, &segments)?;
+    let body = serde_json::json!({
+        "labels": [github.print_label]
+    });
+    let _ = fetch_resource(
+        http,
+        |client| client.post(url.clone()).json(&body),
+        github,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn github(api_url: Option<&str>) -> Github {
+        Github {
+            token: "token".into(),
+            print_label: "print".into(),
+            limit_to_repositories: Vec::new(),
+            api_url: api_url.map(String::from),
+        }
+    }
+
+    #[test]
+    fn build_url_appends_segments() {
+        let github =
+            github(Some("https://github.example.com/api/v3/"));
+        let url =
+            build_url(&github, &["repos", "o", "r"]).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://github.example.com/api/v3/repos/o/r"
+        );
+    }
+
+    #[test]
+    fn build_url_rejects_invalid_api_urls() {
+        assert!(build_url(&github(Some("no url")), &[]).is_err());
+        assert!(build_url(&github(Some("mailto:a@b.c")), &[])
+            .is_err());
+    }
+
+    #[test]
+    fn handle_issues_skips_pull_requests() {
+        let issues: Vec<Issue> = serde_json::from_str(
+            r#"[
+                {"number": 1, "title": "Issue",
+                 "html_url": "https://github.com/o/r/issues/1",
+                 "repository_url": "https://api.github.com/repos/o/r"},
+                {"number": 2, "title": "Pull request",
+                 "html_url": "https://github.com/o/r/pull/2",
+                 "repository_url": "https://api.github.com/repos/o/r",
+                 "pull_request": {"url": "https://api.github.com/repos/o/r/pulls/2"}},
+                {"number": 3, "title": "Issue", "pull_request": null,
+                 "html_url": "https://github.com/o/r/issues/3",
+                 "repository_url": "https://api.github.com/repos/o/r"}
+            ]"#,
+        )
+        .unwrap();
+        let mut tickets = Vec::new();
+        handle_issues(issues, &github(None), &mut tickets);
+        let ids: Vec<_> = tickets
+            .iter()
+            .map(|ticket| ticket.id.as_str())
+            .collect();
+        assert_eq!(ids, ["o/r#1", "o/r#3"]);
+    }
+}
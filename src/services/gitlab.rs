@@ -0,0 +1,329 @@
+use crate::{
+    config::Gitlab,
+    services::{http::Http, Ticket, TicketSource},
+};
+use failure::ResultExt;
+use reqwest::{
+    header::{HeaderMap, LINK},
+    Client, Error, RequestBuilder, Response, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::BTreeMap, vec::IntoIter};
+
+const NAME: &str = "GitLab";
+const DEFAULT_URL: &str = "https://gitlab.com";
+const ISSUES: &str = "issues";
+const MERGE_REQUESTS: &str = "merge_requests";
+
+impl TicketSource for Gitlab {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn fetch_tickets(
+        &self,
+        http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()> {
+        let mut kinds = vec![ISSUES];
+        if self.merge_requests {
+            kinds.push(MERGE_REQUESTS);
+        }
+        for kind in kinds {
+            if self.limit_to_projects.is_empty()
+                && self.limit_to_groups.is_empty()
+            {
+                let url = build_url(self, &[kind])?;
+                fetch_items(http, self, &url, kind, tickets)?;
+            }
+            for project in &self.limit_to_projects {
+                let url = build_url(
+                    self,
+                    &["projects", project.as_str(), kind],
+                )?;
+                fetch_items(http, self, &url, kind, tickets)?;
+            }
+            for group in &self.limit_to_groups {
+                let url = build_url(
+                    self,
+                    &["groups", group.as_str(), kind],
+                )?;
+                fetch_items(http, self, &url, kind, tickets)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn consume_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        update_labels(http, self, &ticket.id, "remove_labels")
+            .with_context(|_| {
+                format!(
+                    "Could not remove Label {} from {}",
+                    &self.print_label, &ticket.id
+                )
+            })?;
+        Ok(())
+    }
+
+    fn revert_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        update_labels(http, self, &ticket.id, "add_labels")
+            .with_context(|_| {
+                format!(
+                    "Could not add Label {} to {}",
+                    &self.print_label, &ticket.id
+                )
+            })?;
+        Ok(())
+    }
+}
+
+fn fetch_items(
+    http: &Http,
+    gitlab: &Gitlab,
+    url: &Url,
+    kind: &str,
+    tickets: &mut Vec<Ticket>,
+) -> crate::Result<()> {
+    let prefix = if kind == MERGE_REQUESTS { "!" } else { "#" };
+    let items =
+        ItemListIterator::<Item>::new(http, gitlab, url.clone());
+    for item in items {
+        let item = item.with_context(|_| {
+            format!(
+                "Could not fetch GitLab {} from {}",
+                kind, url
+            )
+        })?;
+        // ids are based on the api path to simplify label updates
+        push_unique(
+            tickets,
+            Ticket {
+                id: format!(
+                    "{}/{}/{}",
+                    item.project_id, kind, item.iid
+                ),
+                label_id: gitlab.print_label.clone(),
+                titel: item.title,
+                subtitel: format!("{}{}", prefix, item.iid),
+                url: item.web_url,
+                source: NAME.into(),
+                fields: BTreeMap::new(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Adds the ticket unless it is already known, e.g. because both
+/// its project and its group are configured
+fn push_unique(tickets: &mut Vec<Ticket>, ticket: Ticket) {
+    if !tickets.iter().any(|known| {
+        known.source == ticket.source && known.id == ticket.id
+    }) {
+        tickets.push(ticket);
+    }
+}
+
+fn build_url(
+    gitlab: &Gitlab,
+    segments: &[&str],
+) -> Result<Url, failure::Error> {
+    let base =
+        gitlab.url.as_ref().map_or(DEFAULT_URL, String::as_str);
+    let mut url = Url::parse(base).with_context(|_| {
+        format!("Invalid GitLab url {}", base)
+    })?;
+    let _ = url
+        .path_segments_mut()
+        .map_err(|()| {
+            failure::format_err!("Invalid GitLab url {}", base)
+        })?
+        .pop_if_empty()
+        .extend(&["api", "v4"])
+        .extend(segments);
+    Ok(url)
+}
+
+fn fetch_resource(
+    http: &Http,
+    request: impl Fn(&Client) -> RequestBuilder,
+    gitlab: &Gitlab,
+) -> Result<Response, Error> {
+    http.send(|client| {
+        request(client)
+            .header("PRIVATE-TOKEN", gitlab.token.as_str())
+    })
+}
+
+/// Returns the `rel="next"` url of a `Link` header. GitLab sets
+/// it for both offset and keyset based pagination.
+fn next_link(headers: &HeaderMap) -> Option<Url> {
+    let link = headers.get(LINK)?.to_str().ok()?;
+    link.split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| {
+            let start = part.find('<')? + 1;
+            let end = part.find('>')?;
+            part.get(start..end)
+        })
+        .and_then(|url| Url::parse(url).ok())
+}
+
+struct ItemListIterator<'a, T> {
+    current_list: Option<IntoIter<T>>,
+    next_url: Option<Url>,
+    gitlab: &'a Gitlab,
+    http: &'a Http,
+}
+
+impl<'a, T: DeserializeOwned> ItemListIterator<'a, T> {
+    pub fn new(
+        http: &'a Http,
+        gitlab: &'a Gitlab,
+        mut url: Url,
+    ) -> Self {
+        let _ = url
+            .query_pairs_mut()
+            .append_pair("labels", &gitlab.print_label)
+            .append_pair("state", "opened")
+            .append_pair("scope", "all")
+            .append_pair("per_page", "100");
+        ItemListIterator {
+            current_list: None,
+            next_url: Some(url),
+            gitlab,
+            http,
+        }
+    }
+
+    fn fetch_new_list(&mut self, url: &Url) -> Result<(), Error> {
+        let mut resp = fetch_resource(
+            self.http,
+            |client| client.get(url.clone()),
+            self.gitlab,
+        )?;
+        self.next_url = next_link(resp.headers());
+        let list: Vec<T> = resp.json()?;
+        self.current_list = Some(list.into_iter());
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for ItemListIterator<'_, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.current_list {
+                Some(ref mut list) => {
+                    if let Some(v) = list.next() {
+                        return Some(Ok(v));
+                    }
+                    let _ = self.current_list.take();
+                }
+                None => {
+                    if let Some(url) = self.next_url.take() {
+                        if let Err(err) =
+                            self.fetch_new_list(&url)
+                        {
+                            return Some(Err(err));
+                        }
+                    } else {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Item {
+    pub iid: u64,
+    pub project_id: u64,
+    pub title: String,
+    pub web_url: String,
+}
+
+fn update_labels(
+    http: &Http,
+    gitlab: &Gitlab,
+    id: &str,
+    action: &str,
+) -> Result<(), failure::Error> {
+    let mut segments = vec!["projects"];
+    segments.extend(id.split('/'));
+    let mut url = build_url(gitlab, &segments)?;
+    let _ = url
+        .query_pairs_mut()
+        .append_pair(action, &gitlab.print_label);
+    let _ = fetch_resource(
+        http,
+        |client| client.put(url.clone()),
+        gitlab,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitlab(url: Option<&str>) -> Gitlab {
+        Gitlab {
+            token: "token".into(),
+            print_label: "print".into(),
+            limit_to_projects: Vec::new(),
+            limit_to_groups: Vec::new(),
+            merge_requests: false,
+            url: url.map(String::from),
+        }
+    }
+
+    fn ticket(id: &str) -> Ticket {
+        Ticket {
+            id: id.into(),
+            label_id: "print".into(),
+            titel: String::new(),
+            subtitel: String::new(),
+            url: String::new(),
+            source: NAME.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn build_url_appends_api_path() {
+        let gitlab = gitlab(Some("https://gitlab.example.com/"));
+        let url = build_url(&gitlab, &["groups", "a/b", ISSUES]);
+        assert_eq!(
+            url.unwrap().as_str(),
+            "https://gitlab.example.com/api/v4/groups/a%2Fb/issues"
+        );
+    }
+
+    #[test]
+    fn build_url_rejects_invalid_urls() {
+        assert!(build_url(&gitlab(Some("gitlab")), &[]).is_err());
+        assert!(build_url(&gitlab(Some("data:x")), &[]).is_err());
+    }
+
+    #[test]
+    fn push_unique_skips_known_tickets() {
+        let mut tickets = vec![ticket("1/issues/2")];
+        push_unique(&mut tickets, ticket("1/issues/2"));
+        push_unique(&mut tickets, ticket("1/issues/3"));
+        let mut other = ticket("1/issues/3");
+        other.source = "Jira".into();
+        push_unique(&mut tickets, other);
+        assert_eq!(tickets.len(), 3);
+    }
+}
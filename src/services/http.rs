@@ -0,0 +1,365 @@
+use crate::config::Global;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Client, Error, Method, RequestBuilder, Response, StatusCode,
+};
+use std::{
+    cmp,
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+const DEFAULT_TIMEOUT: u64 = 30;
+const DEFAULT_RETRIES: u32 = 3;
+/// Delay before the first retry, doubled for every further one
+const BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between two attempts, even if the tracker asks for
+/// more using `Retry-After`
+const MAX_DELAY: Duration = Duration::from_mins(5);
+const RETRY_AFTER_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Client for tracker requests using `global.http_timeout` and
+/// `global.http_retries`
+#[derive(Debug, Clone)]
+pub struct Http {
+    client: Client,
+    retries: u32,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self::from_config(None)
+    }
+}
+
+impl Http {
+    pub fn from_config(global: Option<&Global>) -> Self {
+        let timeout = global
+            .and_then(|global| global.http_timeout)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        let retries = global
+            .and_then(|global| global.http_retries)
+            .unwrap_or(DEFAULT_RETRIES);
+        let client = Client::builder()
+            .timeout(Duration::from_secs(timeout))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        Self { client, retries }
+    }
+
+    /// Sends the request built by `request` and fails for error
+    /// statuses. Rate limits and connection errors are retried with
+    /// a jittered exponential backoff or as long as `Retry-After`
+    /// asks for. Timeouts and server errors are only retried for
+    /// repeatable methods, as a `POST` or `DELETE` may already have
+    /// been handled.
+    // retries are reported on stderr
+    #[allow(clippy::print_stderr)]
+    pub fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<Response, Error> {
+        let mut attempt = 0;
+        loop {
+            let request = request(&self.client).build()?;
+            let repeatable = repeatable(request.method());
+            let result = self.client.execute(request);
+            let (reason, retry_after) = match result {
+                Ok(ref response)
+                    if attempt < self.retries
+                        && retryable(
+                            response.status(),
+                            repeatable,
+                        ) =>
+                {
+                    (
+                        response.status().to_string(),
+                        retry_after(response.headers()),
+                    )
+                }
+                Err(ref err)
+                    if attempt < self.retries
+                        && retryable_error(err, repeatable) =>
+                {
+                    (err.to_string(), None)
+                }
+                _ => {
+                    return result
+                        .and_then(Response::error_for_status)
+                }
+            };
+            let delay = cmp::min(
+                retry_after.unwrap_or_else(|| {
+                    backoff(attempt, jitter())
+                }),
+                MAX_DELAY,
+            );
+            eprintln!(
+                "WARN: Request failed with {}. Retrying in {} secs",
+                reason,
+                delay.as_secs()
+            );
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
+
+/// Methods which can be repeated without changing the response. A
+/// `DELETE` is idempotent, but a repeated one fails if the first
+/// already removed e.g. the print label.
+const fn repeatable(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::POST | Method::PATCH | Method::DELETE
+    )
+}
+
+/// Rate limited requests were not handled, so they are always
+/// retried
+fn retryable(status: StatusCode, repeatable: bool) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (repeatable && status.is_server_error())
+}
+
+/// Requests failing to connect never reached the tracker, so they
+/// are always retried
+fn retryable_error(err: &Error, repeatable: bool) -> bool {
+    let connect = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<hyper::Error>())
+        .is_some_and(hyper::Error::is_connect);
+    connect || (repeatable && (err.is_timeout() || err.is_http()))
+}
+
+/// `Retry-After` given in seconds or as http date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = time::strptime(value, RETRY_AFTER_DATE).ok()?;
+    let secs =
+        (at.to_timespec() - time::get_time()).num_seconds();
+    Some(Duration::from_secs(u64::try_from(secs).unwrap_or(0)))
+}
+
+/// Doubles the delay for every attempt and picks a point in its upper
+/// half given by `jitter` between 0 and 1, so clients do not retry in
+/// lockstep
+fn backoff(attempt: u32, jitter: f64) -> Duration {
+    let delay = BACKOFF * 2_u32.saturating_pow(attempt);
+    delay.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// Random number between 0 and 1. Every `RandomState` is seeded with
+/// new random keys, so hashing nothing with it is random enough.
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    // the upper 53 bits fit into the mantissa
+    (random >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::{
+        io::{Read, Write},
+        iter,
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let _ = headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_str(retry_after).unwrap(),
+        );
+        headers
+    }
+
+    /// Answers one request per status line and json body. Returns
+    /// the url together with the received request lines like
+    /// `GET /path?query`.
+    pub fn respond(
+        responses: Vec<(&'static str, String)>,
+    ) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut data = Vec::new();
+                let mut buffer = [0; 1024];
+                while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    data.extend(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&data);
+                let line =
+                    request.lines().next().unwrap_or_default();
+                requests.push(
+                    line.trim_end_matches(" HTTP/1.1")
+                        .to_string(),
+                );
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nRetry-After: 0\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (format!("http://127.0.0.1:{}/", port), server)
+    }
+
+    /// Answers one request per status line and returns its url
+    fn serve(statuses: Vec<&'static str>) -> String {
+        respond(
+            statuses
+                .into_iter()
+                .map(|status| (status, String::new()))
+                .collect(),
+        )
+        .0
+    }
+
+    fn http() -> Http {
+        Http {
+            client: Client::new(),
+            retries: 1,
+        }
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_dates() {
+        assert_eq!(
+            retry_after(&headers(" 120 ")),
+            Some(Duration::from_mins(2))
+        );
+        assert_eq!(
+            retry_after(&headers(
+                "Thu, 01 Jan 1970 00:00:00 GMT"
+            )),
+            Some(Duration::from_secs(0))
+        );
+        let at = time::at_utc(
+            time::get_time() + time::Duration::seconds(60),
+        );
+        let date = at.strftime(RETRY_AFTER_DATE).unwrap();
+        let delay = retry_after(&headers(&date.to_string()))
+            .unwrap()
+            .as_secs();
+        assert!((58..=60).contains(&delay), "{}", delay);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter() {
+        assert_eq!(backoff(0, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff(0, 1.0), Duration::from_secs(1));
+        assert_eq!(backoff(3, 0.5), Duration::from_secs(6));
+        assert_eq!(backoff(1, 7.0), Duration::from_secs(2));
+        assert!(backoff(40, 1.0) >= MAX_DELAY);
+    }
+
+    #[test]
+    fn jitter_is_random() {
+        let values: Vec<f64> =
+            iter::repeat_with(jitter).take(8).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(values
+            .iter()
+            .any(|&v| (v - values[0]).abs() > 0.0));
+    }
+
+    #[test]
+    fn only_repeatable_requests_retry_server_errors() {
+        let status = StatusCode::SERVICE_UNAVAILABLE;
+        assert!(retryable(status, true));
+        assert!(!retryable(status, false));
+        assert!(retryable(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(!retryable(StatusCode::NOT_FOUND, true));
+        assert!(repeatable(&Method::PUT));
+        assert!(!repeatable(&Method::DELETE));
+        assert!(!repeatable(&Method::POST));
+    }
+
+    #[test]
+    fn connect_errors_are_retried_for_all_methods() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let err = Client::new()
+            .post(&format!("http://127.0.0.1:{}/", port))
+            .send()
+            .unwrap_err();
+        assert!(retryable_error(&err, false));
+    }
+
+    #[test]
+    fn get_is_retried_on_server_errors() {
+        let url =
+            serve(vec!["503 Service Unavailable", "200 OK"]);
+        let response = http().send(|client| client.get(&url));
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn post_is_not_retried_on_server_errors() {
+        let url =
+            serve(vec!["503 Service Unavailable", "200 OK"]);
+        let err =
+            http().send(|client| client.post(&url)).unwrap_err();
+        assert_eq!(
+            err.status(),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+
+    #[test]
+    fn delete_is_not_retried_on_server_errors() {
+        let url = serve(vec![
+            "503 Service Unavailable",
+            "404 Not Found",
+        ]);
+        let err = http()
+            .send(|client| client.delete(&url))
+            .unwrap_err();
+        assert_eq!(
+            err.status(),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+
+    #[test]
+    fn post_is_retried_on_rate_limits() {
+        let url = serve(vec!["429 Too Many Requests", "200 OK"]);
+        let response = http().send(|client| client.post(&url));
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn errors_are_returned_after_the_last_retry() {
+        let url = serve(vec![
+            "503 Service Unavailable",
+            "503 Service Unavailable",
+        ]);
+        let err =
+            http().send(|client| client.get(&url)).unwrap_err();
+        assert_eq!(
+            err.status(),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
-    config::Jira,
-    services::{Service, Ticket},
+    config::{Jira, JiraAuth, Printer},
+    markup,
+    services::{
+        http::Http, printed_comment, Ticket, TicketSource,
+    },
 };
 use failure::ResultExt;
 use reqwest::{
@@ -8,106 +11,310 @@ use reqwest::{
     Client, Error, RequestBuilder, Response, Url,
 };
 use serde::Deserialize;
-use std::vec::IntoIter;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    vec::IntoIter,
+};
 
-pub fn fetch_tickets(
-    jira: &Jira,
-    tickets: &mut Vec<Ticket>,
-) -> crate::Result<()> {
-    let query = build_query(jira);
-    let issues = IssueListIterator::new(jira, query);
-    for issue in issues {
-        let issue = issue?;
-        remove_label(jira, &issue.id).with_context(|_| {
+const NAME: &str = "Jira";
+const LABEL_PLACEHOLDER: &str = "{print_label}";
+/// Fields available to every card, even if not configured. The
+/// description is shown on the back, priority and issue type pick
+/// colours and icons.
+const DEFAULT_FIELDS: &[&str] =
+    &["description", "priority", "issuetype"];
+
+impl TicketSource for Jira {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    // a jql without label is only worth a warning
+    #[allow(clippy::print_stderr)]
+    fn validate(&self) -> crate::Result<()> {
+        match self.base_url {
+            Some(ref url) => {
+                let _ = Url::parse(url).with_context(|_| {
+                    format!(
+                        "jira.base_url {} is not a valid url",
+                        url
+                    )
+                })?;
+            }
+            None if self.host.trim().is_empty() => {
+                return Err(failure::format_err!(
+                    "Either jira.host or jira.base_url is required"
+                )
+                .into());
+            }
+            None => {}
+        }
+        if let Some(ref jql) = self.jql {
+            if !jql.contains(LABEL_PLACEHOLDER) {
+                eprintln!(
+                    "WARN: jira.jql does not contain {}. Issues will be printed on every run.",
+                    LABEL_PLACEHOLDER
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn fetch_tickets(
+        &self,
+        http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()> {
+        let query = build_query(self);
+        let issues = IssueListIterator::new(http, self, query);
+        for issue in issues {
+            let issue = issue?;
+            let url = format!(
+                "{}/browse/{}",
+                base_url(self),
+                &issue.key
+            );
+            let mut fields: BTreeMap<String, String> = self
+                .fields
+                .iter()
+                .map(|(name, id)| {
+                    let value =
+                        issue.fields.other.get(id).map_or_else(
+                            String::new,
+                            field_to_string,
+                        );
+                    (name.clone(), value)
+                })
+                .collect();
+            for &id in DEFAULT_FIELDS {
+                if !fields.contains_key(id) {
+                    let value =
+                        issue.fields.other.get(id).map_or_else(
+                            String::new,
+                            field_to_string,
+                        );
+                    let _ = fields.insert(id.into(), value);
+                }
+            }
+            tickets.push(Ticket {
+                id: issue.id,
+                label_id: self.print_label.clone(),
+                titel: issue.fields.summary,
+                subtitel: issue.key,
+                url,
+                source: NAME.into(),
+                fields,
+            });
+        }
+        Ok(())
+    }
+
+    fn consume_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        update_labels(
+            http,
+            self,
+            &ticket.id,
+            &[("remove", &self.print_label)],
+        )
+        .with_context(|_| {
             format!(
                 "Could not remove tag from issue {}",
-                issue.id
+                ticket.subtitel
             )
         })?;
-        let url = format!("https://{}/browse/{}", jira.host, &issue.key);
-        tickets.push(Ticket {
-            id: issue.id.clone(),
-            label_id: jira.print_label.clone(),
-            titel: issue.fields.summary,
-            subtitel: issue.key,
-            url,
-            service: Service::Jira,
-        });
+        Ok(())
+    }
+
+    fn finish_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+        printer: Option<&Printer>,
+    ) -> crate::Result<()> {
+        let Some(ref after_print) = self.after_print else {
+            return Ok(());
+        };
+        if let Some(ref label) = after_print.add_label {
+            update_labels(
+                http,
+                self,
+                &ticket.id,
+                &[("add", label)],
+            )
+            .with_context(|_| {
+                format!(
+                    "Could not add tag {} to issue {}",
+                    label, ticket.subtitel
+                )
+            })?;
+        }
+        if let Some(ref comment) = after_print.comment {
+            let comment = printed_comment(comment, printer);
+            add_comment(http, self, &ticket.id, &comment)
+                .with_context(|_| {
+                    format!(
+                        "Could not comment on issue {}",
+                        ticket.subtitel
+                    )
+                })?;
+        }
+        if let Some(ref status) = after_print.transition {
+            transition_issue(http, self, &ticket.id, status)
+                .with_context(|_| {
+                    format!(
+                        "Could not transition issue {} to {}",
+                        ticket.subtitel, status
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Re-adds the print label and removes the label added after
+    /// printing. Transitions and comments are not reverted.
+    fn revert_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        let mut operations =
+            vec![("add", self.print_label.as_str())];
+        if let Some(label) =
+            self.after_print.as_ref().and_then(|after_print| {
+                after_print.add_label.as_ref()
+            })
+        {
+            operations.push(("remove", label));
+        }
+        update_labels(http, self, &ticket.id, &operations)
+            .with_context(|_| {
+                format!(
+                    "Could not add tag to issue {}",
+                    ticket.subtitel
+                )
+            })?;
+        Ok(())
     }
-    Ok(())
 }
 
 fn build_query(jira: &Jira) -> String {
-    let mut projects = jira
-        .limit_to_projects
-        .iter()
-        .map(|entry| format!("project = {}", entry))
-        .collect::<Vec<String>>()
-        .join(" OR ");
-    if !projects.is_empty() {
-        projects = format!("{} AND", projects);
+    let label = quote(&jira.print_label);
+    if let Some(ref jql) = jira.jql {
+        return jql.replace(LABEL_PLACEHOLDER, &label);
     }
-    let types = jira.limit_to_types.join(", ");
-    if !types.is_empty() {
-        projects = format!("issuetype in ({}) AND", projects);
+    let mut clauses = Vec::new();
+    if !jira.limit_to_projects.is_empty() {
+        clauses.push(format!(
+            "project in ({})",
+            quote_all(&jira.limit_to_projects)
+        ));
+    }
+    if !jira.limit_to_types.is_empty() {
+        clauses.push(format!(
+            "issuetype in ({})",
+            quote_all(&jira.limit_to_types)
+        ));
     }
+    clauses.push(format!("labels = {}", label));
+    clauses.join(" AND ")
+}
+
+fn quote(value: &str) -> String {
     format!(
-        "{} {} labels = {}",
-        projects, types, jira.print_label
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
     )
 }
 
-pub fn revert_tickets(jira: &Jira, tickets: &[Ticket]) {
-    for ticket in tickets {
-        if let Service::Jira = ticket.service {
-            let _ = add_label(jira, &ticket.id);
-        }
-    }
+fn quote_all(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| quote(value))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Returns the jira url without a trailing slash. Either configured
+/// directly, including scheme and context path, or built from the host
+fn base_url(jira: &Jira) -> String {
+    jira.base_url.as_ref().map_or_else(
+        || format!("https://{}", jira.host),
+        |url| url.trim_end_matches('/').to_string(),
+    )
+}
+
+fn api_url(jira: &Jira, path: &str) -> String {
+    format!(
+        "{}/rest/api/{}/{}",
+        base_url(jira),
+        jira.api_version,
+        path
+    )
 }
 
 fn fetch_resource(
-    builder: RequestBuilder,
+    http: &Http,
+    request: impl Fn(&Client) -> RequestBuilder,
     jira: &Jira,
 ) -> Result<Response, Error> {
-    builder
-        .basic_auth(&jira.user, Some(&jira.token))
-        .header(CONTENT_TYPE, "application/json")
-        .header(ACCEPT, "application/json")
-        .send()
-        .and_then(reqwest::Response::error_for_status)
+    http.send(|client| {
+        let builder = match jira.auth {
+            JiraAuth::Basic => request(client)
+                .basic_auth(&jira.user, Some(&jira.token)),
+            JiraAuth::Bearer => {
+                request(client).bearer_auth(&jira.token)
+            }
+        };
+        builder
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+    })
 }
 
 struct IssueListIterator<'a> {
     current_list: Option<IntoIter<Issue>>,
-    start_at: usize,
+    /// Index of the first issue of the next page
+    start_at: u64,
     fetch_more: bool,
     jira: &'a Jira,
     query: String,
-    client: Client,
+    http: &'a Http,
 }
 
 impl<'a> IssueListIterator<'a> {
-    pub fn new(jira: &'a Jira, query: String) -> Self {
+    pub const fn new(
+        http: &'a Http,
+        jira: &'a Jira,
+        query: String,
+    ) -> Self {
         IssueListIterator {
             current_list: None,
             start_at: 0,
             fetch_more: true,
             jira,
             query,
-            client: Client::new(),
+            http,
         }
     }
 
     fn fetch_new_list(&mut self) -> Result<(), Error> {
         let list = IssueList::fetch(
-            &self.client,
+            self.http,
             self.jira,
             self.start_at,
             &self.query,
         )?;
+        let count = list.issues.len() as u64;
         self.current_list = Some(list.issues.into_iter());
-        self.start_at += 1;
-        self.fetch_more = list.max_results == list.total;
+        self.start_at += count;
+        // an empty page ends the search, so a total which is too high
+        // does not fetch forever
+        self.fetch_more = count > 0 && self.start_at < list.total;
         Ok(())
     }
 }
@@ -121,15 +328,14 @@ impl Iterator for IssueListIterator<'_> {
                 Some(ref mut list) => {
                     if let Some(v) = list.next() {
                         return Some(Ok(v));
-                    } else {
-                        let _ = self.current_list.take();
                     }
+                    let _ = self.current_list.take();
                 }
                 None => {
                     if self.fetch_more {
                         if let Err(err) = self.fetch_new_list() {
                             return Some(Err(err));
-                        };
+                        }
                     } else {
                         return None;
                     }
@@ -141,39 +347,44 @@ impl Iterator for IssueListIterator<'_> {
 
 #[derive(Deserialize, Debug)]
 struct IssueList {
-    #[serde(rename = "maxResults")]
-    pub max_results: u64,
     pub total: u64,
     pub issues: Vec<Issue>,
 }
 
 impl IssueList {
     pub fn fetch(
-        client: &Client,
+        http: &Http,
         jira: &Jira,
-        start_at: usize,
+        start_at: u64,
         query: &str,
     ) -> Result<Self, Error> {
-        let url = Url::parse_with_params(
-            &format!("https://{}/rest/api/2/search", jira.host),
-            &[
-                ("jql", query),
-                ("startAt", &start_at.to_string()),
-                ("maxResults", &50.to_string()),
-                ("oldIssueView", "true"),
-            ],
-        )
-        .expect("Unable to build url");
-        let req = client.get(url);
-        fetch_resource(req, jira)?.json()
+        let url = api_url(jira, "search");
+        let params = [
+            ("jql", query),
+            ("startAt", &start_at.to_string()),
+            ("maxResults", &50.to_string()),
+            ("fields", &search_fields(jira)),
+            ("oldIssueView", "true"),
+        ];
+        fetch_resource(
+            http,
+            |client| client.get(&url).query(&params),
+            jira,
+        )?
+        .json()
     }
 }
 
+fn search_fields(jira: &Jira) -> String {
+    let mut fields = vec!["summary"];
+    fields.extend(DEFAULT_FIELDS);
+    fields.extend(jira.fields.values().map(String::as_str));
+    fields.join(",")
+}
+
 #[derive(Deserialize, Debug)]
 struct Issue {
     pub id: String,
-    #[serde(rename = "self")]
-    pub url: String,
     pub key: String,
     pub fields: Fields,
 }
@@ -181,37 +392,381 @@ struct Issue {
 #[derive(Deserialize, Debug)]
 struct Fields {
     pub summary: String,
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// Converts a jira field into printable text. Objects like users,
+/// priorities or select options are reduced to their name.
+fn field_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(field_to_string)
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<String>>()
+            .join(", "),
+        // rich text of Jira Cloud, printed through markdown
+        Value::Object(_) if markup::is_adf(value) => {
+            markup::adf_to_markdown(value)
+        }
+        Value::Object(object) => {
+            ["displayName", "name", "value", "key"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .map_or_else(String::new, field_to_string)
+        }
+        value => value.to_string(),
+    }
 }
 
-fn remove_label(
+/// Applies label operations like `("add", "printed")` to an issue
+fn update_labels(
+    http: &Http,
     jira: &Jira,
     issue_id: &str,
+    operations: &[(&str, &str)],
 ) -> Result<(), Error> {
-    let client = Client::new();
-    let req = client
-        .put(&format!(
-            "https://{}/rest/api/3/issue/{}?oldIssueView=true",
-            jira.host, issue_id
-        ))
-        .body(format!(
-            "{{\"update\":{{\"labels\":[{{\"remove\":\"{}\"}}]}}}}",
-            jira.print_label
-        ));
-    let _ = fetch_resource(req, jira)?;
+    let labels: Vec<Value> = operations
+        .iter()
+        .map(|&(operation, label)| json!({ operation: label }))
+        .collect();
+    let url = format!(
+        "{}?oldIssueView=true",
+        api_url(jira, &format!("issue/{}", issue_id))
+    );
+    let body =
+        json!({ "update": { "labels": labels } }).to_string();
+    let _ = fetch_resource(
+        http,
+        |client| client.put(&url).body(body.clone()),
+        jira,
+    )?;
     Ok(())
 }
 
-fn add_label(jira: &Jira, issue_id: &str) -> Result<(), Error> {
-    let client = Client::new();
-    let req = client
-        .put(&format!(
-            "https://{}/rest/api/3/issue/{}?oldIssueView=true",
-            jira.host, issue_id
-        ))
-        .body(format!(
-            "{{\"update\":{{\"labels\":[{{\"add\":\"{}\"}}]}}}}",
-            jira.print_label
-        ));
-    let _ = fetch_resource(req, jira)?;
+fn add_comment(
+    http: &Http,
+    jira: &Jira,
+    issue_id: &str,
+    comment: &str,
+) -> Result<(), Error> {
+    let url =
+        api_url(jira, &format!("issue/{}/comment", issue_id));
+    let body = comment_body(jira, comment).to_string();
+    let _ = fetch_resource(
+        http,
+        |client| client.post(&url).body(body.clone()),
+        jira,
+    )?;
     Ok(())
 }
+
+fn comment_body(jira: &Jira, comment: &str) -> Value {
+    // api version 3 expects comments in the atlassian document format
+    if jira.api_version == "3" {
+        json!({ "body": {
+            "type": "doc",
+            "version": 1,
+            "content": [{
+                "type": "paragraph",
+                "content": [{ "type": "text", "text": comment }]
+            }]
+        }})
+    } else {
+        json!({ "body": comment })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Transitions {
+    pub transitions: Vec<Transition>,
+}
+
+impl Transitions {
+    /// The transition matching the status by its own or its target
+    /// status name
+    fn find(self, status: &str) -> Option<Transition> {
+        self.transitions.into_iter().find(|transition| {
+            transition.to.name.eq_ignore_ascii_case(status)
+                || transition.name.eq_ignore_ascii_case(status)
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Transition {
+    pub id: String,
+    pub name: String,
+    pub to: Status,
+}
+
+#[derive(Deserialize, Debug)]
+struct Status {
+    pub name: String,
+}
+
+/// Moves the issue into the given status. The status is matched
+/// against both the transition and the target status name.
+fn transition_issue(
+    http: &Http,
+    jira: &Jira,
+    issue_id: &str,
+    status: &str,
+) -> Result<(), failure::Error> {
+    let url =
+        api_url(jira, &format!("issue/{}/transitions", issue_id));
+    let transitions: Transitions =
+        fetch_resource(http, |client| client.get(&url), jira)?
+            .json()?;
+    let transition =
+        transitions.find(status).ok_or_else(|| {
+            failure::err_msg(format!(
+                "No transition to {} available",
+                status
+            ))
+        })?;
+    let body = json!({ "transition": { "id": transition.id } })
+        .to_string();
+    let _ = fetch_resource(
+        http,
+        |client| client.post(&url).body(body.clone()),
+        jira,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::http::tests::respond;
+
+    fn jira(host: &str, base_url: Option<&str>) -> Jira {
+        Jira {
+            host: host.into(),
+            base_url: base_url.map(String::from),
+            user: "user".into(),
+            token: "token".into(),
+            auth: JiraAuth::Basic,
+            api_version: "3".into(),
+            print_label: "print".into(),
+            limit_to_types: Vec::new(),
+            limit_to_projects: Vec::new(),
+            jql: None,
+            fields: BTreeMap::new(),
+            after_print: None,
+        }
+    }
+
+    #[test]
+    fn validate_requires_host_or_base_url() {
+        assert!(jira("", None).validate().is_err());
+        assert!(jira(" ", None).validate().is_err());
+        assert!(jira("", Some("jira.example.com"))
+            .validate()
+            .is_err());
+        assert!(jira("example.atlassian.net", None)
+            .validate()
+            .is_ok());
+        assert!(jira("", Some("https://example.com/jira/"))
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn field_to_string_reduces_objects_to_names() {
+        let field = json!({
+            "self": "https://example.com/user/1",
+            "displayName": "Jane Doe",
+            "name": "jane"
+        });
+        assert_eq!(field_to_string(&field), "Jane Doe");
+        assert_eq!(
+            field_to_string(
+                &json!({ "id": "3", "name": "High" })
+            ),
+            "High"
+        );
+        assert_eq!(
+            field_to_string(&json!({ "value": { "key": "A" } })),
+            "A"
+        );
+        assert_eq!(field_to_string(&json!({ "id": "3" })), "");
+    }
+
+    #[test]
+    fn field_to_string_joins_arrays_and_prints_scalars() {
+        let field = json!([{ "value": "red" }, null, "", "blue"]);
+        assert_eq!(field_to_string(&field), "red, blue");
+        assert_eq!(field_to_string(&json!(null)), "");
+        assert_eq!(field_to_string(&json!(3.5)), "3.5");
+        assert_eq!(field_to_string(&json!(true)), "true");
+    }
+
+    #[test]
+    fn comment_body_depends_on_api_version() {
+        let mut jira = jira("example.atlassian.net", None);
+        assert_eq!(
+            comment_body(&jira, "Printed")["body"]["content"][0]
+                ["content"][0]["text"],
+            "Printed"
+        );
+        jira.api_version = "2".into();
+        assert_eq!(
+            comment_body(&jira, "Printed"),
+            json!({ "body": "Printed" })
+        );
+    }
+
+    #[test]
+    fn transitions_match_name_or_target_status() {
+        let transitions = || -> Transitions {
+            serde_json::from_value(json!({ "transitions": [
+                { "id": "11", "name": "Start", "to": { "name": "In Progress" } },
+                { "id": "21", "name": "Finish", "to": { "name": "Done" } }
+            ]}))
+            .unwrap()
+        };
+        let id =
+            |status| transitions().find(status).map(|t| t.id);
+        assert_eq!(id("in progress").as_deref(), Some("11"));
+        assert_eq!(id("FINISH").as_deref(), Some("21"));
+        assert_eq!(id("Closed"), None);
+    }
+
+    #[test]
+    fn api_url_prefers_base_url() {
+        assert_eq!(
+            api_url(
+                &jira("example.atlassian.net", None),
+                "search"
+            ),
+            "https://example.atlassian.net/rest/api/3/search"
+        );
+        assert_eq!(
+            api_url(
+                &jira(
+                    "ignored",
+                    Some("https://example.com/jira/")
+                ),
+                "search"
+            ),
+            "https://example.com/jira/rest/api/3/search"
+        );
+    }
+
+    #[test]
+    fn field_to_string_converts_rich_text() {
+        let field = json!({
+            "type": "doc",
+            "version": 1,
+            "content": [{
+                "type": "paragraph",
+                "content": [{ "type": "text", "text": "Hello" }]
+            }]
+        });
+        assert_eq!(field_to_string(&field).trim(), "Hello");
+    }
+
+    #[test]
+    fn query_quotes_label_and_filters() {
+        let mut jira = jira("example.atlassian.net", None);
+        assert_eq!(build_query(&jira), "labels = \"print\"");
+        jira.print_label = "to \"print\" \\ now".into();
+        jira.limit_to_projects =
+            vec!["PRJ".into(), "O\"NE".into()];
+        jira.limit_to_types =
+            vec!["Bug".into(), "Sub task".into()];
+        assert_eq!(
+            build_query(&jira),
+            "project in (\"PRJ\", \"O\\\"NE\") AND issuetype in (\"Bug\", \"Sub task\") AND labels = \"to \\\"print\\\" \\\\ now\""
+        );
+    }
+
+    #[test]
+    fn query_fills_the_label_into_custom_jql() {
+        let mut jira = jira("example.atlassian.net", None);
+        jira.limit_to_projects = vec!["PRJ".into()];
+        jira.jql = Some(
+            "labels = {print_label} AND status != Done ORDER BY rank"
+                .into(),
+        );
+        jira.print_label = "a\"b".into();
+        // the filters only apply to the generated query
+        assert_eq!(
+            build_query(&jira),
+            "labels = \"a\\\"b\" AND status != Done ORDER BY rank"
+        );
+    }
+
+    fn page(keys: &[&str], total: u64) -> (&'static str, String) {
+        let issues: Vec<Value> = keys
+            .iter()
+            .map(|key| {
+                json!({
+                    "id": key,
+                    "key": key,
+                    "fields": {"summary": key}
+                })
+            })
+            .collect();
+        (
+            "200 OK",
+            json!({"maxResults": 2, "total": total, "issues": issues})
+                .to_string(),
+        )
+    }
+
+    /// Keys of all issues found and the `startAt` of each request
+    fn search(
+        pages: Vec<(&'static str, String)>,
+    ) -> (Vec<String>, Vec<String>) {
+        let (url, server) = respond(pages);
+        let jira = jira("", Some(&url));
+        let http = Http::default();
+        let keys =
+            IssueListIterator::new(&http, &jira, "q".into())
+                .map(|issue| issue.unwrap().key)
+                .collect();
+        let starts = server
+            .join()
+            .unwrap()
+            .iter()
+            .filter_map(|request| {
+                request.split('&').find_map(|param| {
+                    param
+                        .strip_prefix("startAt=")
+                        .map(String::from)
+                })
+            })
+            .collect();
+        (keys, starts)
+    }
+
+    #[test]
+    fn search_pages_through_all_issues() {
+        let (keys, starts) = search(vec![
+            page(&["A-1", "A-2"], 5),
+            page(&["A-3", "A-4"], 5),
+            page(&["A-5"], 5),
+        ]);
+        assert_eq!(keys, ["A-1", "A-2", "A-3", "A-4", "A-5"]);
+        assert_eq!(starts, ["0", "2", "4"]);
+    }
+
+    #[test]
+    fn search_stops_at_the_total_or_an_empty_page() {
+        let (keys, starts) =
+            search(vec![page(&["A-1", "A-2"], 2)]);
+        assert_eq!(keys, ["A-1", "A-2"]);
+        assert_eq!(starts, ["0"]);
+        let (keys, starts) =
+            search(vec![page(&["A-1"], 3), page(&[], 3)]);
+        assert_eq!(keys, ["A-1"]);
+        assert_eq!(starts, ["0", "1"]);
+        let (keys, _) = search(vec![page(&[], 0)]);
+        assert!(keys.is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{
+    config::Local,
+    services::{http::Http, Ticket, TicketSource},
+};
+use failure::ResultExt;
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    mem,
+    path::Path,
+};
+
+const NAME: &str = "Local";
+const STDIN: &str = "-";
+
+impl TicketSource for Local {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn fetch_tickets(
+        &self,
+        _http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()> {
+        let text = if self.path == STDIN {
+            let mut text = String::new();
+            let _ = io::stdin()
+                .read_to_string(&mut text)
+                .with_context(|_| {
+                    "Could not read stdin".to_string()
+                })?;
+            text
+        } else {
+            fs::read_to_string(&self.path).with_context(|_| {
+                format!(
+                    "Could not read ticket file {}",
+                    self.path
+                )
+            })?
+        };
+        let format = self.format.as_ref().map_or_else(
+            || {
+                Path::new(&self.path)
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or("json")
+                    .to_lowercase()
+            },
+            |format| format.to_lowercase(),
+        );
+        let records = parse_records(&format, &text)
+            .with_context(|_| {
+                format!(
+                    "Could not parse ticket file {}",
+                    self.path
+                )
+            })?;
+        for (index, record) in records.into_iter().enumerate() {
+            tickets.push(Ticket {
+                id: record
+                    .id
+                    .unwrap_or_else(|| (index + 1).to_string()),
+                label_id: String::new(),
+                titel: record.titel,
+                subtitel: record.subtitel,
+                url: record.url,
+                source: NAME.into(),
+                fields: record
+                    .fields
+                    .into_iter()
+                    .map(|(key, value)| match value {
+                        Value::String(value) => (key, value),
+                        value => (key, value.to_string()),
+                    })
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Local tickets carry no print marker, so there is nothing to
+    /// consume
+    fn consume_ticket(
+        &self,
+        _http: &Http,
+        _ticket: &Ticket,
+    ) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn revert_ticket(
+        &self,
+        _http: &Http,
+        _ticket: &Ticket,
+    ) -> crate::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Record {
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub id: Option<String>,
+    #[serde(alias = "title")]
+    pub titel: String,
+    #[serde(default, alias = "subtitle")]
+    pub subtitel: String,
+    #[serde(default)]
+    pub url: String,
+    /// All remaining columns or keys
+    #[serde(flatten)]
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Accepts numeric ids like `{"id": 42}` as well
+fn string_or_number<'de, D>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => None,
+        Some(Value::String(id)) => Some(id),
+        Some(id) => Some(id.to_string()),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct TomlRecords {
+    pub tickets: Vec<Record>,
+}
+
+fn parse_records(
+    format: &str,
+    text: &str,
+) -> Result<Vec<Record>, failure::Error> {
+    let records = match format {
+        "json" => serde_json::from_str(text)?,
+        "yaml" | "yml" => serde_yaml::from_str(text)?,
+        "toml" => toml::from_str::<TomlRecords>(text)?.tickets,
+        "csv" => serde_json::from_value(csv_to_json(text))?,
+        _ => {
+            return Err(failure::format_err!(
+                "Unsupported ticket file format {}",
+                format
+            ))
+        }
+    };
+    Ok(records)
+}
+
+/// Converts csv text into a json array of objects, using the first
+/// row as keys
+fn csv_to_json(text: &str) -> Value {
+    let mut rows = parse_csv(text).into_iter();
+    let header = rows.next().unwrap_or_default();
+    Value::Array(
+        rows.map(|row| {
+            Value::Object(
+                header
+                    .iter()
+                    .cloned()
+                    .zip(row)
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(key, value)| {
+                        (key, Value::String(value))
+                    })
+                    .collect::<Map<String, Value>>(),
+            )
+        })
+        .collect(),
+    )
+}
+
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    let _ = chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => row.push(mem::take(&mut field)),
+            '\n' if !quoted => {
+                row.push(mem::take(&mut field));
+                push_row(&mut rows, mem::take(&mut row));
+            }
+            '\r' if !quoted => {}
+            _ => field.push(c),
+        }
+    }
+    row.push(field);
+    push_row(&mut rows, row);
+    rows
+}
+
+fn push_row(rows: &mut Vec<Vec<String>>, row: Vec<String>) {
+    if row.iter().any(|field| !field.trim().is_empty()) {
+        rows.push(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_csv_keeps_quoted_commas_and_quotes() {
+        assert_eq!(
+            parse_csv("titel,url\n\"a, b\",\"say \"\"hi\"\"\"\n"),
+            [["titel", "url"], ["a, b", "say \"hi\""]]
+        );
+    }
+
+    #[test]
+    fn parse_csv_handles_crlf_and_trailing_newlines() {
+        assert_eq!(
+            parse_csv("titel,url\r\na,b\r\n\r\n\n"),
+            [["titel", "url"], ["a", "b"]]
+        );
+        assert_eq!(parse_csv("titel\na"), [["titel"], ["a"]]);
+    }
+
+    #[test]
+    fn parse_csv_keeps_line_breaks_in_quotes() {
+        assert_eq!(
+            parse_csv("titel\n\"a\r\nb\"\n"),
+            [["titel"], ["a\r\nb"]]
+        );
+    }
+
+    #[test]
+    fn csv_records_skip_empty_columns() {
+        let records =
+            parse_records("csv", "id,titel,owner\n,a,\n7,b,me\n")
+                .unwrap();
+        assert_eq!(records[0].id, None);
+        assert!(records[0].fields.is_empty());
+        assert_eq!(records[1].id.as_deref(), Some("7"));
+        assert_eq!(records[1].fields["owner"], "me");
+    }
+
+    #[test]
+    fn records_accept_numeric_ids() {
+        let records = parse_records(
+            "json",
+            r#"[{"id": 42, "titel": "a"}, {"id": "PROJ-1", "titel": "b"}, {"titel": "c"}]"#,
+        )
+        .unwrap();
+        let ids: Vec<_> = records
+            .iter()
+            .map(|record| record.id.as_deref())
+            .collect();
+        assert_eq!(ids, [Some("42"), Some("PROJ-1"), None]);
+        let records = parse_records(
+            "toml",
+            "[[tickets]]\nid = 7\ntitel = 'a'\n",
+        )
+        .unwrap();
+        assert_eq!(records[0].id.as_deref(), Some("7"));
+    }
+}
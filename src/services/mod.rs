@@ -1,27 +1,471 @@
+// Warnings and errors are reported on stderr, errors with their
+// causes through Debug
+#![allow(clippy::print_stderr, clippy::use_debug)]
+
+pub mod github;
+pub mod gitlab;
+pub mod http;
 pub mod jira;
+pub mod local;
 pub mod trello;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Service {
-    Trello,
-    Jira,
+use crate::{
+    config::{Config, Printer},
+    journal::Journal,
+};
+use http::Http;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Debug};
+
+const FIELDS_PREFIX: &str = "fields.";
+
+/// A tracker providing tickets which are marked for printing.
+///
+/// Tickets are first searched using `fetch_tickets` and then
+/// consumed one by one, which usually removes the print marker.
+/// If something goes wrong afterwards, `revert_ticket` restores
+/// the marker so the ticket is picked up again by the next run.
+pub trait TicketSource: Debug {
+    /// Name of the source. Used to map a ticket back to its source
+    fn name(&self) -> &'static str;
+
+    /// Checks the configuration once on start, before any ticket
+    /// is consumed
+    fn validate(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Searches for all tickets marked for printing
+    fn fetch_tickets(
+        &self,
+        http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()>;
+
+    /// Marks the ticket as handled, e.g. by removing the print label
+    fn consume_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()>;
+
+    /// Runs optional follow up actions once the ticket is printed
+    fn finish_ticket(
+        &self,
+        _http: &Http,
+        _ticket: &Ticket,
+        _printer: Option<&Printer>,
+    ) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Undoes `consume_ticket`, e.g. by re-adding the print label
+    fn revert_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()>;
 }
 
-impl Into<&'static str> for Service {
-    fn into(self) -> &'static str {
-        match self {
-            Self::Trello => "Trello",
-            Self::Jira => "Jira",
+/// Fills the `{printer}` and `{time}` placeholders of a comment
+/// added after printing
+// the placeholders are replaced by hand
+#[allow(clippy::literal_string_with_formatting_args)]
+pub fn printed_comment(
+    template: &str,
+    printer: Option<&Printer>,
+) -> String {
+    let printer = printer.map_or("pdf", |p| p.name.as_str());
+    let printed_at = time::now()
+        .strftime("%Y-%m-%d %H:%M")
+        .map(|printed_at| printed_at.to_string())
+        .unwrap_or_default();
+    template
+        .replace("{printer}", printer)
+        .replace("{time}", &printed_at)
+}
+
+/// All ticket sources available in the configuration
+#[derive(Debug)]
+pub struct Sources<'a> {
+    list: Vec<&'a dyn TicketSource>,
+    journal: Option<Journal>,
+    http: Http,
+}
+
+impl<'a> Sources<'a> {
+    pub fn from_config(config: &'a Config) -> Self {
+        let mut sources: Vec<&'a dyn TicketSource> = Vec::new();
+        if let Some(ref trello) = config.trello {
+            sources.push(trello);
+        }
+        if let Some(ref jira) = config.jira {
+            sources.push(jira);
+        }
+        if let Some(ref github) = config.github {
+            sources.push(github);
+        }
+        if let Some(ref gitlab) = config.gitlab {
+            sources.push(gitlab);
+        }
+        if let Some(ref local) = config.local {
+            sources.push(local);
+        }
+        Self {
+            list: sources,
+            journal: Journal::from_config(config),
+            http: Http::from_config(config.global.as_ref()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn new(
+        sources: Vec<&'a dyn TicketSource>,
+        journal: Option<Journal>,
+    ) -> Self {
+        Self {
+            list: sources,
+            journal,
+            http: Http::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn validate(&self) -> crate::Result<()> {
+        for source in &self.list {
+            source.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Marks the tickets left in the journal by an interrupted run
+    /// for printing again. Tickets failing to revert stay in the
+    /// journal for the next start. An unreadable journal is moved
+    /// aside, so printing is not blocked by it.
+    pub fn recover(&self) -> crate::Result<()> {
+        let Some(ref journal) = self.journal else {
+            return Ok(());
+        };
+        let tickets = match journal.tickets() {
+            Ok(tickets) => tickets,
+            Err(err) => {
+                let path = journal.set_aside()?;
+                eprintln!(
+                    "ERROR: {:?}. Moved it to {}, please restore the print markers of the tickets in it manually",
+                    err,
+                    path.display()
+                );
+                return Ok(());
+            }
+        };
+        for ticket in tickets {
+            let Some(source) = self.find(&ticket.source) else {
+                eprintln!(
+                    "WARN: Can not recover {} - {}. Source {} is not configured",
+                    ticket.id, ticket.subtitel, ticket.source
+                );
+                continue;
+            };
+            if let Err(err) =
+                source.revert_ticket(&self.http, &ticket)
+            {
+                eprintln!(
+                    "WARN: Can not recover {} - {}: {:?}",
+                    ticket.id, ticket.subtitel, err
+                );
+                continue;
+            }
+            journal.remove(&ticket)?;
+            println!(
+                "Recovered: {} - {}",
+                ticket.id, ticket.subtitel
+            );
+        }
+        Ok(())
+    }
+
+    /// Fetches and consumes the tickets of all sources. Tickets are
+    /// journaled before and only added to `tickets` after they have
+    /// been consumed. Failing sources and tickets are reported and
+    /// skipped, their number is returned.
+    pub fn fetch_tickets(
+        &self,
+        tickets: &mut Vec<Ticket>,
+    ) -> usize {
+        let mut failed = 0;
+        for source in &self.list {
+            let mut found = Vec::new();
+            // tickets found before the error are still printed
+            if let Err(err) =
+                source.fetch_tickets(&self.http, &mut found)
+            {
+                eprintln!(
+                    "ERROR: Could not fetch tickets from {}: {:?}",
+                    source.name(),
+                    err
+                );
+                failed += 1;
+            }
+            for ticket in found {
+                if let Err(err) = self.consume(*source, &ticket) {
+                    eprintln!(
+                        "ERROR: Could not consume {} - {}: {:?}",
+                        ticket.id, ticket.subtitel, err
+                    );
+                    failed += 1;
+                } else {
+                    tickets.push(ticket);
+                }
+            }
+        }
+        failed
+    }
+
+    fn consume(
+        &self,
+        source: &dyn TicketSource,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        if let Some(ref journal) = self.journal {
+            journal.add(ticket)?;
+        }
+        if let Err(err) =
+            source.consume_ticket(&self.http, ticket)
+        {
+            self.forget(ticket);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Runs the follow up actions of a printed ticket. The ticket is
+    /// already printed, so failures are only reported.
+    pub fn finish_ticket(
+        &self,
+        ticket: &Ticket,
+        printer: Option<&Printer>,
+    ) {
+        if let Some(source) = self.find(&ticket.source) {
+            if let Err(err) =
+                source.finish_ticket(&self.http, ticket, printer)
+            {
+                eprintln!("WARN: {:?}", err);
+            }
+        }
+        self.forget(ticket);
+    }
+
+    /// Leaves the tickets of a job which may still be printed
+    /// consumed. They are removed from the journal, as reverting them
+    /// on the next start could print them twice.
+    pub fn leave_consumed(&self, tickets: &[Ticket]) {
+        for ticket in tickets {
+            eprintln!(
+                "WARN: {} - {} stays consumed, as it may still be printed. Please restore its print marker manually if it is not",
+                ticket.id, ticket.subtitel
+            );
+            self.forget(ticket);
         }
     }
+
+    /// Reverts the tickets. Reverted tickets are removed from the
+    /// journal, the others are recovered on the next start.
+    pub fn revert_tickets(&self, tickets: &[Ticket]) {
+        for ticket in tickets {
+            let result = self
+                .find(&ticket.source)
+                .ok_or_else(|| {
+                    failure::format_err!(
+                        "Source {} is not configured",
+                        ticket.source
+                    )
+                    .into()
+                })
+                .and_then(|source| {
+                    source.revert_ticket(&self.http, ticket)
+                });
+            match result {
+                Ok(()) => self.forget(ticket),
+                Err(err) => eprintln!(
+                    "ERROR: Could not mark {} - {} for printing again. {}: {:?}",
+                    ticket.id,
+                    ticket.subtitel,
+                    if self.journal.is_some() {
+                        "It is retried on the next start"
+                    } else {
+                        "Please restore its print marker manually"
+                    },
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Removes a ticket from the journal. Failing to do so only
+    /// prints it again after the next start, so it is reported.
+    fn forget(&self, ticket: &Ticket) {
+        if let Some(ref journal) = self.journal {
+            if let Err(err) = journal.remove(ticket) {
+                eprintln!("WARN: {:?}", err);
+            }
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&'a dyn TicketSource> {
+        self.list
+            .iter()
+            .find(|source| source.name() == name)
+            .copied()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Ticket {
     pub id: String,
     pub label_id: String,
     pub titel: String,
     pub subtitel: String,
     pub url: String,
-    pub service: Service,
+    pub source: String,
+    /// Additional, source specific values like priority or assignee
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Ticket {
+    /// Returns the value for a placeholder like `titel` or
+    /// `fields.priority`
+    pub fn value(&self, name: &str) -> Option<&str> {
+        match name {
+            "id" => Some(&self.id),
+            "titel" | "title" => Some(&self.titel),
+            "subtitel" | "subtitle" | "key" => {
+                Some(&self.subtitel)
+            }
+            "url" => Some(&self.url),
+            "source" => Some(&self.source),
+            _ => name
+                .strip_prefix(FIELDS_PREFIX)
+                .and_then(|field| self.fields.get(field))
+                .map(String::as_str),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, fs};
+    use tempfile::tempdir;
+
+    /// Source remembering reverted tickets, failing for the id `fail`
+    #[derive(Debug, Default)]
+    struct Reverter(RefCell<Vec<String>>);
+
+    impl TicketSource for Reverter {
+        fn name(&self) -> &'static str {
+            "Test"
+        }
+
+        fn fetch_tickets(
+            &self,
+            _http: &Http,
+            _tickets: &mut Vec<Ticket>,
+        ) -> crate::Result<()> {
+            Ok(())
+        }
+
+        fn consume_ticket(
+            &self,
+            _http: &Http,
+            _ticket: &Ticket,
+        ) -> crate::Result<()> {
+            Ok(())
+        }
+
+        fn revert_ticket(
+            &self,
+            _http: &Http,
+            ticket: &Ticket,
+        ) -> crate::Result<()> {
+            self.0.borrow_mut().push(ticket.id.clone());
+            if ticket.id == "fail" {
+                return Err(failure::err_msg("offline").into());
+            }
+            Ok(())
+        }
+    }
+
+    fn ticket(source: &str, id: &str) -> Ticket {
+        Ticket {
+            id: id.into(),
+            label_id: "print".into(),
+            titel: "Titel".into(),
+            subtitel: id.into(),
+            url: String::new(),
+            source: source.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn recover_reverts_journaled_tickets() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let journal = Journal::new(path.clone());
+        journal.add(&ticket("Test", "1")).unwrap();
+        journal.add(&ticket("Test", "fail")).unwrap();
+        journal.add(&ticket("Other", "2")).unwrap();
+        let reverter = Reverter::default();
+        let sources =
+            Sources::new(vec![&reverter], Some(journal));
+        sources.recover().unwrap();
+        assert_eq!(reverter.0.into_inner(), ["1", "fail"]);
+        // failed and unknown tickets are kept for the next start
+        let left: Vec<String> = Journal::new(path)
+            .tickets()
+            .unwrap()
+            .into_iter()
+            .map(|ticket| ticket.id)
+            .collect();
+        assert_eq!(left, ["fail", "2"]);
+    }
+
+    #[test]
+    fn recover_sets_corrupt_journal_aside() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        fs::write(&path, "not json").unwrap();
+        let reverter = Reverter::default();
+        let sources = Sources::new(
+            vec![&reverter],
+            Some(Journal::new(path.clone())),
+        );
+        sources.recover().unwrap();
+        assert!(reverter.0.borrow().is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(
+                dir.path().join("journal.corrupt")
+            )
+            .unwrap(),
+            "not json"
+        );
+        // new tickets are journaled again
+        sources.consume(&reverter, &ticket("Test", "1")).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn printed_comment_fills_placeholders() {
+        let comment =
+            printed_comment("Printed on {printer}", None);
+        assert_eq!(comment, "Printed on pdf");
+        let comment = printed_comment("{time}", None);
+        // like 2020-01-31 12:00
+        assert_eq!(comment.len(), 16);
+        assert_eq!(comment.get(4..5), Some("-"));
+    }
 }
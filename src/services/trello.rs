@@ -1,105 +1,412 @@
 use crate::{
-    config::Trello,
-    services::{Service, Ticket},
+    config::{Printer, Trello, TrelloSubtitle},
+    services::{
+        http::Http, printed_comment, Ticket, TicketSource,
+    },
 };
 use failure::ResultExt;
-use reqwest::{Client, Error, Response, Url};
+use reqwest::{Error, Response};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
-pub fn fetch_tickets(
-    trello: &Trello,
-    tickets: &mut Vec<Ticket>,
-) -> crate::Result<()> {
-    let mut iter_a;
-    let mut iter_b;
-    let boards = get_boards(&trello.app_key, &trello.token)
-        .with_context(|_| {
-            "Could not fetch Trello Board".to_string()
-        })?;
-    let boards_filter: &mut dyn Iterator<Item = &Board> =
-        if trello.limit_to_boards.is_empty() {
-            iter_b = boards.iter().filter(|_| true);
-            &mut iter_b
-        } else {
-            iter_a = boards.iter().filter(move |board| {
-                trello.limit_to_boards.contains(&board.name)
-            });
-            &mut iter_a
-        };
-    for board in boards_filter {
-        let mut lists =
-            get_lists(board, &trello.token, &trello.app_key)
+const NAME: &str = "Trello";
+const SEARCH_LIMIT: usize = 1000;
+const DEFAULT_API_URL: &str = "https://api.trello.com/1";
+/// Field keeping the list of a card, which it is moved back to on
+/// revert
+const LIST_ID: &str = "list_id";
+
+impl TicketSource for Trello {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn validate(&self) -> crate::Result<()> {
+        // the label is quoted in the search query
+        if self.print_label.contains('"') {
+            return Err(failure::format_err!(
+                "trello.print_label {} must not contain quotes",
+                self.print_label
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn fetch_tickets(
+        &self,
+        http: &Http,
+        tickets: &mut Vec<Ticket>,
+    ) -> crate::Result<()> {
+        let mut iter_a;
+        let mut iter_b;
+        let boards =
+            get_boards(http, self).with_context(|_| {
+                "Could not fetch Trello Board".to_string()
+            })?;
+        let boards_filter: &mut dyn Iterator<Item = &Board> =
+            if self.limit_to_boards.is_empty() {
+                iter_b = boards.iter().filter(|_| true);
+                &mut iter_b
+            } else {
+                iter_a = boards.iter().filter(move |board| {
+                    self.limit_to_boards.contains(&board.name)
+                });
+                &mut iter_a
+            };
+        let mut board_ids = Vec::new();
+        let mut print_label_ids = Vec::new();
+        for board in boards_filter {
+            let labels = get_labels(http, &board.id, self)
                 .with_context(|_| {
                     format!(
-                "Could not fetch Trello Board Lists for {}",
-                board.name
+                    "Could not fetch Trello Board Labels for {}",
+                    board.name
+                )
+                })?;
+            // boards without the print label cannot contain any cards
+            // marked for printing
+            if let Some(label) = labels
+                .into_iter()
+                .find(|label| label.name == self.print_label)
+            {
+                board_ids.push(board.id.as_str());
+                print_label_ids.push(label.id);
+            }
+        }
+        if board_ids.is_empty() {
+            return Ok(());
+        }
+        let cards = search_cards(http, &board_ids, self)
+            .with_context(|_| {
+                format!(
+                    "Could not search Trello Cards with Label {}",
+                    self.print_label
+                )
+            })?;
+        for mut card in cards {
+            // the search index may lag behind, so only cards still
+            // carrying the print label are printed
+            if !card
+                .label_ids
+                .iter()
+                .any(|id| print_label_ids.contains(id))
+            {
+                continue;
+            }
+            let has_checklist = card
+                .badges
+                .as_ref()
+                .is_some_and(|badges| badges.check_items > 0);
+            if has_checklist {
+                card.checklists =
+                    get_checklists(http, &card.id, self)
+                        .with_context(|_| {
+                            format!(
+                        "Could not fetch Checklists of Card {}",
+                        card.name
+                    )
+                        })?;
+            }
+            handle_card(card, self, tickets);
+        }
+        Ok(())
+    }
+
+    fn consume_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        remove_label(http, &ticket.id, &ticket.label_id, self)
+            .with_context(|_| {
+                format!(
+                    "Could not remove Label {} from Card {}",
+                    &self.print_label, &ticket.titel
+                )
+            })?;
+        Ok(())
+    }
+
+    fn finish_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+        printer: Option<&Printer>,
+    ) -> crate::Result<()> {
+        let Some(ref after_print) = self.after_print else {
+            return Ok(());
+        };
+        if let Some(ref comment) = after_print.comment {
+            add_comment(
+                http,
+                &ticket.id,
+                &printed_comment(comment, printer),
+                self,
             )
+            .with_context(|_| {
+                format!(
+                    "Could not comment on Card {}",
+                    &ticket.titel
+                )
+            })?;
+        }
+        if after_print.add_label.is_none()
+            && after_print.move_to_list.is_none()
+        {
+            return Ok(());
+        }
+        let board_id = get_board_id(http, &ticket.id, self)
+            .with_context(|_| {
+                format!(
+                    "Could not fetch Board of Card {}",
+                    &ticket.titel
+                )
+            })?;
+        if let Some(ref label) = after_print.add_label {
+            let label_id =
+                find_label(http, &board_id, label, self)?;
+            add_label(http, &ticket.id, &label_id, self)
+                .with_context(|_| {
+                    format!(
+                        "Could not add Label {} to Card {}",
+                        label, &ticket.titel
+                    )
+                })?;
+        }
+        if let Some(ref list) = after_print.move_to_list {
+            let lists = get_lists(http, &board_id, self)
+                .with_context(|_| {
+                    format!(
+                    "Could not fetch Trello Board Lists for {}",
+                    list
+                )
+                })?;
+            let list_id = lists
+                .into_iter()
+                .find(|l| &l.name == list)
+                .map(|l| l.id)
+                .ok_or_else(|| {
+                    failure::err_msg(format!(
+                        "Unknown Trello List {}",
+                        list
+                    ))
+                })?;
+            move_card(http, &ticket.id, &list_id, self)
+                .with_context(|_| {
+                    format!(
+                        "Could not move Card {} to List {}",
+                        &ticket.titel, list
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Re-adds the print label, removes the label added after
+    /// printing and moves the card back to its list. Comments are not
+    /// reverted.
+    fn revert_ticket(
+        &self,
+        http: &Http,
+        ticket: &Ticket,
+    ) -> crate::Result<()> {
+        let card = get_card_board(http, &ticket.id, self)
+            .with_context(|_| {
+                format!(
+                    "Could not fetch Labels of Card {}",
+                    &ticket.titel
+                )
+            })?;
+        // the card keeps its print label if the run was interrupted
+        // before consuming it, and Trello rejects adding it twice
+        if !card.label_ids.contains(&ticket.label_id) {
+            add_label(http, &ticket.id, &ticket.label_id, self)
+                .with_context(|_| {
+                format!(
+                    "Could not add Label {} to Card {}",
+                    &self.print_label, &ticket.titel
+                )
+            })?;
+        }
+        let Some(ref after_print) = self.after_print else {
+            return Ok(());
+        };
+        if let Some(ref label) = after_print.add_label {
+            let label_id =
+                find_label(http, &card.board_id, label, self)?;
+            // the label may never have been added or was removed by hand
+            if card.label_ids.contains(&label_id) {
+                remove_label(http, &ticket.id, &label_id, self)
+                    .with_context(|_| {
+                    format!(
+                        "Could not remove Label {} from Card {}",
+                        label, &ticket.titel
+                    )
                 })?;
-        for list in &mut lists {
-            if let Some(cards) = list.cards.take() {
-                for card in cards {
-                    handle_card(card, trello, tickets)?;
-                }
             }
         }
+        if let (Some(_), Some(list_id)) = (
+            after_print.move_to_list.as_ref(),
+            ticket.fields.get(LIST_ID),
+        ) {
+            move_card(http, &ticket.id, list_id, self)
+                .with_context(|_| {
+                    format!(
+                        "Could not move Card {} back",
+                        &ticket.titel
+                    )
+                })?;
+        }
+        Ok(())
     }
-    Ok(())
 }
 
+#[allow(clippy::too_many_lines)]
 fn handle_card(
     card: Card,
     trello: &Trello,
     tickets: &mut Vec<Ticket>,
-) -> crate::Result<()> {
+) {
+    let other_labels: Vec<&Label> = card
+        .labels
+        .iter()
+        .filter(|label| label.name != trello.print_label)
+        .collect();
+    let label_names = other_labels
+        .iter()
+        .map(|label| label.name.as_str())
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    let label_color =
+        other_labels.iter().find_map(|label| label.color.clone());
     for label in card.labels {
         if label.name == trello.print_label {
-            let name = &card.name;
-            remove_label(
-                &card.id,
-                &label,
-                &trello.token,
-                &trello.app_key,
-            )
-            .with_context(|_| {
-                format!(
-                    "Could not remove Label {} from Card {}",
-                    &label.name, name
-                )
-            })?;
+            let (list_id, list_name) = card
+                .list
+                .map(|list| (list.id, list.name))
+                .unwrap_or_default();
+            let mut fields = BTreeMap::new();
+            let _ = fields
+                .insert("list".to_string(), list_name.clone());
+            let _ = fields.insert(LIST_ID.to_string(), list_id);
+            let _ = fields
+                .insert("description".to_string(), card.desc);
+            if !label_names.is_empty() {
+                let _ = fields
+                    .insert("labels".to_string(), label_names);
+            }
+            if let Some(ref color) = label_color {
+                let _ = fields.insert(
+                    "label_color".to_string(),
+                    color.clone(),
+                );
+            }
+            if let Some(date) =
+                card.due.as_deref().and_then(local_date)
+            {
+                let _ = fields.insert("due".to_string(), date);
+            }
+            let card_members = card
+                .members
+                .iter()
+                .map(|member| format!("@{}", member.username))
+                .collect::<Vec<String>>()
+                .join(" ");
+            if !card_members.is_empty() {
+                let _ = fields
+                    .insert("members".to_string(), card_members);
+            }
+            if let Some(ref badges) = card.badges {
+                if badges.check_items > 0 {
+                    let _ = fields.insert(
+                        "checklist".to_string(),
+                        format!(
+                            "{}/{}",
+                            badges.check_items_checked,
+                            badges.check_items
+                        ),
+                    );
+                }
+            }
+            let check_items = card
+                .checklists
+                .iter()
+                .flat_map(|checklist| &checklist.check_items)
+                .map(|item| {
+                    let mark = if item.state == "complete" {
+                        "[x]"
+                    } else {
+                        "[ ]"
+                    };
+                    format!("{} {}", mark, item.name)
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+            if !check_items.is_empty() {
+                let _ = fields.insert(
+                    "check_items".to_string(),
+                    check_items,
+                );
+            }
+            let details = [
+                fields
+                    .get("due")
+                    .map(|due| format!("Due {}", due)),
+                fields.get("members").cloned(),
+                fields.get("checklist").map(|checklist| {
+                    format!("{} \u{2713}", checklist)
+                }),
+            ]
+            .iter()
+            .filter_map(Option::as_ref)
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .join(" \u{b7} ");
+            let _ = fields.insert("details".to_string(), details);
+            let subtitel = match trello.subtitle {
+                TrelloSubtitle::Id => card.id.clone(),
+                TrelloSubtitle::List => list_name,
+            };
             tickets.push(Ticket {
-                id: card.id.clone(),
+                id: card.id,
                 label_id: label.id,
                 titel: card.name,
-                subtitel: card.id,
+                subtitel,
                 url: card.url,
-                service: Service::Trello,
+                source: NAME.into(),
+                fields,
             });
             break;
         }
     }
-    Ok(())
 }
 
-pub fn revert_tickets(trello: &Trello, tickets: &[Ticket]) {
-    for ticket in tickets {
-        if let Service::Trello = ticket.service {
-            let _ = add_label(
-                &ticket.id,
-                &ticket.label_id,
-                &trello.token,
-                &trello.app_key,
-            );
-        }
-    }
+/// Local date of a due timestamp like `2019-11-03T12:00:00.000Z`,
+/// which Trello gives in UTC
+fn local_date(due: &str) -> Option<String> {
+    let utc = time::strptime(due.get(..19)?, "%Y-%m-%dT%H:%M:%S")
+        .ok()?;
+    let local = time::at(utc.to_timespec());
+    Some(local.strftime("%Y-%m-%d").ok()?.to_string())
+}
+
+/// Url of an api resource like `cards/<id>`
+fn api_url(trello: &Trello, path: &str) -> String {
+    let base = trello
+        .api_url
+        .as_ref()
+        .map_or(DEFAULT_API_URL, String::as_str);
+    format!("{}/{}", base.trim_end_matches('/'), path)
 }
 
 fn get_resource(
+    http: &Http,
     url: &str,
     params: &[(&str, &str)],
 ) -> Result<Response, Error> {
-    let url = Url::parse_with_params(url, params)
-        .expect("Unable to build url");
-    reqwest::get(url)?.error_for_status()
+    http.send(|client| client.get(url).query(params))
 }
 
 #[derive(Deserialize, Debug)]
@@ -109,36 +416,94 @@ struct Board {
 }
 
 fn get_boards(
-    key: &str,
-    token: &str,
+    http: &Http,
+    trello: &Trello,
 ) -> Result<Vec<Board>, Error> {
     let mut resp = get_resource(
-        "https://api.trello.com/1/members/me/boards",
-        &[("key", key), ("token", token), ("filter", "open")],
+        http,
+        &api_url(trello, "members/me/boards"),
+        &[
+            ("key", trello.app_key.as_str()),
+            ("token", trello.token.as_str()),
+            ("filter", "open"),
+        ],
     )?;
     resp.json()
 }
 
 #[derive(Deserialize, Debug)]
 struct List {
-    pub cards: Option<Vec<Card>>,
+    pub id: String,
+    pub name: String,
 }
 
 fn get_lists(
-    board: &Board,
-    token: &str,
-    key: &str,
+    http: &Http,
+    board_id: &str,
+    trello: &Trello,
 ) -> Result<Vec<List>, Error> {
     let mut resp = get_resource(
-        &format!(
-            "https://api.trello.com/1/boards/{}/lists",
-            &board.id
-        ),
-        &[("key", key), ("token", token), ("cards", "open")],
+        http,
+        &api_url(trello, &format!("boards/{}/lists", board_id)),
+        &[
+            ("key", trello.app_key.as_str()),
+            ("token", trello.token.as_str()),
+            ("fields", "name"),
+        ],
     )?;
     resp.json()
 }
 
+#[derive(Deserialize, Debug)]
+struct SearchResult {
+    pub cards: Vec<Card>,
+}
+
+/// Searches for open cards carrying the print label. Only the given
+/// boards are searched, which should be known to have the label.
+fn search_cards(
+    http: &Http,
+    board_ids: &[&str],
+    trello: &Trello,
+) -> Result<Vec<Card>, Error> {
+    let query =
+        format!("label:\"{}\" is:open", trello.print_label);
+    let board_ids = board_ids.join(",");
+    let limit = SEARCH_LIMIT.to_string();
+    let mut cards = Vec::new();
+    let mut page = 0;
+    loop {
+        let page_str = page.to_string();
+        let mut resp = get_resource(
+            http,
+            &api_url(trello, "search"),
+            &[
+                ("key", trello.app_key.as_str()),
+                ("token", trello.token.as_str()),
+                ("query", query.as_str()),
+                ("idBoards", board_ids.as_str()),
+                ("modelTypes", "cards"),
+                ("partial", "false"),
+                ("cards_limit", limit.as_str()),
+                ("cards_page", page_str.as_str()),
+                (
+                    "card_fields",
+                    "name,desc,url,labels,idLabels,due,badges,idList",
+                ),
+                ("card_list", "true"),
+                ("card_members", "true"),
+            ],
+        )?;
+        let result: SearchResult = resp.json()?;
+        let last_page = result.cards.len() < SEARCH_LIMIT;
+        cards.extend(result.cards);
+        if last_page {
+            return Ok(cards);
+        }
+        page += 1;
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct Card {
     pub id: String,
@@ -146,50 +511,520 @@ struct Card {
     pub name: String,
     pub url: String,
     pub labels: Vec<Label>,
+    #[serde(rename = "idLabels", default)]
+    pub label_ids: Vec<String>,
+    #[serde(default)]
+    pub due: Option<String>,
+    #[serde(default)]
+    pub badges: Option<Badges>,
+    #[serde(default)]
+    pub list: Option<List>,
+    #[serde(default)]
+    pub members: Vec<Member>,
+    /// Only fetched for cards with check items
+    #[serde(default)]
+    pub checklists: Vec<Checklist>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Badges {
+    #[serde(rename = "checkItems")]
+    pub check_items: u64,
+    #[serde(rename = "checkItemsChecked")]
+    pub check_items_checked: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Checklist {
+    #[serde(rename = "checkItems")]
+    pub check_items: Vec<CheckItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CheckItem {
+    pub name: String,
+    /// `complete` or `incomplete`
+    pub state: String,
+}
+
+fn get_checklists(
+    http: &Http,
+    card_id: &str,
+    trello: &Trello,
+) -> Result<Vec<Checklist>, Error> {
+    let mut resp = get_resource(
+        http,
+        &api_url(
+            trello,
+            &format!("cards/{}/checklists", card_id),
+        ),
+        &[
+            ("key", trello.app_key.as_str()),
+            ("token", trello.token.as_str()),
+            ("fields", "name"),
+            ("checkItem_fields", "name,state"),
+        ],
+    )?;
+    resp.json()
+}
+
+#[derive(Deserialize, Debug)]
+struct Member {
+    pub username: String,
 }
 
 #[derive(Deserialize, Debug)]
 struct Label {
     pub name: String,
     pub id: String,
+    /// Colour name like `green`, `None` for labels without colour
+    #[serde(default)]
+    pub color: Option<String>,
 }
 
 fn remove_label(
+    http: &Http,
     card_id: &str,
-    label: &Label,
-    token: &str,
-    key: &str,
+    label_id: &str,
+    trello: &Trello,
 ) -> Result<(), Error> {
-    let url = Url::parse_with_params(
-        &format!(
-            "https://api.trello.com/1/cards/{}/idLabels/{}",
-            card_id, label.id
-        ),
-        &[("token", token), ("key", key)],
-    )
-    .expect("Unable to build url");
-    let client = Client::new();
-    let resp = client.delete(url).send()?;
-    let _ = resp.error_for_status()?;
+    let url = api_url(
+        trello,
+        &format!("cards/{}/idLabels/{}", card_id, label_id),
+    );
+    let _ = http.send(|client| {
+        client.delete(&url).query(&[
+            ("token", trello.token.as_str()),
+            ("key", trello.app_key.as_str()),
+        ])
+    })?;
     Ok(())
 }
 
 fn add_label(
+    http: &Http,
     card_id: &str,
     label_id: &str,
-    token: &str,
-    key: &str,
+    trello: &Trello,
 ) -> Result<(), Error> {
-    let url = Url::parse_with_params(
-        &format!(
-            "https://api.trello.com/1/cards/{}/idLabels",
-            card_id
-        ),
-        &[("token", token), ("key", key), ("value", label_id)],
-    )
-    .expect("Unable to build url");
-    let client = Client::new();
-    let resp = client.post(url).send()?;
-    let _ = resp.error_for_status()?;
+    let url =
+        api_url(trello, &format!("cards/{}/idLabels", card_id));
+    let _ = http.send(|client| {
+        client.post(&url).query(&[
+            ("token", trello.token.as_str()),
+            ("key", trello.app_key.as_str()),
+            ("value", label_id),
+        ])
+    })?;
     Ok(())
 }
+
+#[derive(Deserialize, Debug)]
+struct CardBoard {
+    #[serde(rename = "idBoard")]
+    pub board_id: String,
+    #[serde(rename = "idLabels", default)]
+    pub label_ids: Vec<String>,
+}
+
+fn get_card_board(
+    http: &Http,
+    card_id: &str,
+    trello: &Trello,
+) -> Result<CardBoard, Error> {
+    let mut resp = get_resource(
+        http,
+        &api_url(trello, &format!("cards/{}", card_id)),
+        &[
+            ("key", trello.app_key.as_str()),
+            ("token", trello.token.as_str()),
+            ("fields", "idBoard,idLabels"),
+        ],
+    )?;
+    resp.json()
+}
+
+fn get_board_id(
+    http: &Http,
+    card_id: &str,
+    trello: &Trello,
+) -> Result<String, Error> {
+    Ok(get_card_board(http, card_id, trello)?.board_id)
+}
+
+fn get_labels(
+    http: &Http,
+    board_id: &str,
+    trello: &Trello,
+) -> Result<Vec<Label>, Error> {
+    let mut resp = get_resource(
+        http,
+        &api_url(trello, &format!("boards/{}/labels", board_id)),
+        &[
+            ("key", trello.app_key.as_str()),
+            ("token", trello.token.as_str()),
+            ("fields", "name"),
+        ],
+    )?;
+    resp.json()
+}
+
+fn find_label(
+    http: &Http,
+    board_id: &str,
+    name: &str,
+    trello: &Trello,
+) -> Result<String, failure::Error> {
+    get_labels(http, board_id, trello)?
+        .into_iter()
+        .find(|label| label.name == name)
+        .map(|label| label.id)
+        .ok_or_else(|| {
+            failure::err_msg(format!(
+                "Unknown Trello Label {}",
+                name
+            ))
+        })
+}
+
+fn move_card(
+    http: &Http,
+    card_id: &str,
+    list_id: &str,
+    trello: &Trello,
+) -> Result<(), Error> {
+    let url = api_url(trello, &format!("cards/{}", card_id));
+    let _ = http.send(|client| {
+        client.put(&url).query(&[
+            ("token", trello.token.as_str()),
+            ("key", trello.app_key.as_str()),
+            ("idList", list_id),
+        ])
+    })?;
+    Ok(())
+}
+
+fn add_comment(
+    http: &Http,
+    card_id: &str,
+    text: &str,
+    trello: &Trello,
+) -> Result<(), Error> {
+    let url = api_url(
+        trello,
+        &format!("cards/{}/actions/comments", card_id),
+    );
+    let _ = http.send(|client| {
+        client.post(&url).query(&[
+            ("token", trello.token.as_str()),
+            ("key", trello.app_key.as_str()),
+            ("text", text),
+        ])
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::TrelloAfterPrint, services::http::tests::respond,
+    };
+    use serde_json::json;
+
+    fn trello(print_label: &str) -> Trello {
+        Trello {
+            app_key: "key".into(),
+            token: "token".into(),
+            print_label: print_label.into(),
+            limit_to_boards: Vec::new(),
+            subtitle: TrelloSubtitle::Id,
+            after_print: None,
+            api_url: None,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_quoted_labels() {
+        assert!(trello("print").validate().is_ok());
+        assert!(trello("to print").validate().is_ok());
+        assert!(trello("\"print\"").validate().is_err());
+        assert!(trello("pr\"int").validate().is_err());
+    }
+
+    /// Trello using the fake server at `url` with all after print
+    /// actions
+    fn trello_at(url: &str) -> Trello {
+        Trello {
+            after_print: Some(TrelloAfterPrint {
+                move_to_list: Some("Done".into()),
+                comment: Some("Printed on {printer}".into()),
+                add_label: Some("printed".into()),
+            }),
+            api_url: Some(format!("{}1", url)),
+            ..trello("print")
+        }
+    }
+
+    fn ticket() -> Ticket {
+        let mut fields = BTreeMap::new();
+        let _ = fields.insert(LIST_ID.to_string(), "l1".into());
+        Ticket {
+            id: "c1".into(),
+            label_id: "p1".into(),
+            titel: "Card".into(),
+            subtitel: "c1".into(),
+            url: String::new(),
+            source: NAME.into(),
+            fields,
+        }
+    }
+
+    fn ok(body: &serde_json::Value) -> (&'static str, String) {
+        ("200 OK", body.to_string())
+    }
+
+    #[test]
+    fn finish_ticket_comments_labels_and_moves() {
+        let (url, server) = respond(vec![
+            ok(&json!({})),
+            ok(&json!({"idBoard": "b1", "idLabels": ["p1"]})),
+            ok(&json!([{"id": "l2", "name": "printed"}])),
+            ok(&json!([])),
+            ok(&json!([{"id": "l3", "name": "Done"}])),
+            ok(&json!({})),
+        ]);
+        trello_at(&url)
+            .finish_ticket(&Http::default(), &ticket(), None)
+            .unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "POST /1/cards/c1/actions/comments?token=token&key=key&text=Printed+on+pdf",
+                "GET /1/cards/c1?key=key&token=token&fields=idBoard%2CidLabels",
+                "GET /1/boards/b1/labels?key=key&token=token&fields=name",
+                "POST /1/cards/c1/idLabels?token=token&key=key&value=l2",
+                "GET /1/boards/b1/lists?key=key&token=token&fields=name",
+                "PUT /1/cards/c1?token=token&key=key&idList=l3",
+            ]
+        );
+    }
+
+    #[test]
+    fn finish_ticket_fails_for_unknown_lists() {
+        let (url, _server) = respond(vec![
+            ok(&json!({})),
+            ok(&json!({"idBoard": "b1", "idLabels": []})),
+            ok(&json!([{"id": "l2", "name": "printed"}])),
+            ok(&json!([])),
+            ok(&json!([{"id": "l3", "name": "Todo"}])),
+        ]);
+        assert!(trello_at(&url)
+            .finish_ticket(&Http::default(), &ticket(), None)
+            .is_err());
+    }
+
+    #[test]
+    fn revert_ticket_restores_label_and_list() {
+        let (url, server) = respond(vec![
+            ok(&json!({"idBoard": "b1", "idLabels": ["l2"]})),
+            ok(&json!([])),
+            ok(&json!([{"id": "l2", "name": "printed"}])),
+            ok(&json!({})),
+            ok(&json!({})),
+        ]);
+        trello_at(&url)
+            .revert_ticket(&Http::default(), &ticket())
+            .unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "GET /1/cards/c1?key=key&token=token&fields=idBoard%2CidLabels",
+                "POST /1/cards/c1/idLabels?token=token&key=key&value=p1",
+                "GET /1/boards/b1/labels?key=key&token=token&fields=name",
+                "DELETE /1/cards/c1/idLabels/l2?token=token&key=key",
+                "PUT /1/cards/c1?token=token&key=key&idList=l1",
+            ]
+        );
+    }
+
+    #[test]
+    fn revert_ticket_keeps_present_print_label() {
+        let (url, server) = respond(vec![ok(
+            &json!({"idBoard": "b1", "idLabels": ["p1"]}),
+        )]);
+        let trello = Trello {
+            after_print: None,
+            ..trello_at(&url)
+        };
+        trello
+            .revert_ticket(&Http::default(), &ticket())
+            .unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            ["GET /1/cards/c1?key=key&token=token&fields=idBoard%2CidLabels"]
+        );
+    }
+
+    #[test]
+    fn fetch_tickets_skips_cards_which_lost_the_label() {
+        let card = |id: &str, label_ids: &[&str]| {
+            json!({
+                "id": id,
+                "name": id,
+                "desc": "",
+                "url": "",
+                "labels": [{"id": "p1", "name": "print"}],
+                "idLabels": label_ids,
+            })
+        };
+        let (url, server) = respond(vec![
+            ok(&json!([
+                {"id": "b1", "name": "One"},
+                {"id": "b2", "name": "Two"}
+            ])),
+            ok(&json!([{"id": "p1", "name": "print"}])),
+            ok(&json!([{"id": "l1", "name": "bug"}])),
+            ok(&json!({"cards": [
+                card("c1", &["p1"]),
+                card("c2", &[])
+            ]})),
+        ]);
+        let mut tickets = Vec::new();
+        trello_at(&url)
+            .fetch_tickets(&Http::default(), &mut tickets)
+            .unwrap();
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].id, "c1");
+        let requests = server.join().unwrap();
+        let paths: Vec<&str> = requests
+            .iter()
+            .filter_map(|request| request.split('?').next())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "GET /1/members/me/boards",
+                "GET /1/boards/b1/labels",
+                "GET /1/boards/b2/labels",
+                "GET /1/search",
+            ]
+        );
+        assert!(requests[3].contains("idBoards=b1&"));
+        assert!(requests[3].contains("idLabels"));
+    }
+
+    /// Date of a unix timestamp in local time
+    fn local(secs: i64) -> String {
+        time::at(time::Timespec::new(secs, 0))
+            .strftime("%Y-%m-%d")
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn local_date_converts_from_utc() {
+        assert_eq!(
+            local_date("2019-11-03T12:00:00.000Z"),
+            Some(local(1_572_782_400))
+        );
+        assert_eq!(
+            local_date("2019-11-03T23:30:00.000Z"),
+            Some(local(1_572_823_800))
+        );
+        assert_eq!(local_date("soon"), None);
+    }
+
+    fn card() -> Card {
+        serde_json::from_value(json!({
+            "id": "c1",
+            "name": "Card",
+            "desc": "Text",
+            "url": "https://trello.com/c/c1",
+            "labels": [
+                {"id": "p1", "name": "print", "color": null},
+                {"id": "l2", "name": "bug", "color": "red"},
+                {"id": "l3", "name": "", "color": "blue"}
+            ],
+            "due": "2019-11-03T12:00:00.000Z",
+            "badges": {"checkItems": 5, "checkItemsChecked": 3},
+            "list": {"id": "l1", "name": "Todo"},
+            "members": [{"username": "alice"}, {"username": "bob"}],
+            "checklists": [{"checkItems": [
+                {"name": "first", "state": "complete"},
+                {"name": "second", "state": "incomplete"}
+            ]}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn handle_card_maps_the_fields() {
+        let mut tickets = Vec::new();
+        handle_card(card(), &trello("print"), &mut tickets);
+        assert_eq!(tickets.len(), 1);
+        let ticket = &tickets[0];
+        assert_eq!(
+            (ticket.id.as_str(), ticket.label_id.as_str()),
+            ("c1", "p1")
+        );
+        assert_eq!(ticket.subtitel, "c1");
+        let due = local(1_572_782_400);
+        let field = |name: &str| ticket.fields[name].as_str();
+        assert_eq!(field("list"), "Todo");
+        assert_eq!(field(LIST_ID), "l1");
+        assert_eq!(field("description"), "Text");
+        assert_eq!(field("labels"), "bug");
+        assert_eq!(field("label_color"), "red");
+        assert_eq!(field("due"), due);
+        assert_eq!(field("members"), "@alice @bob");
+        assert_eq!(field("checklist"), "3/5");
+        assert_eq!(field("check_items"), "[x] first\n[ ] second");
+        assert_eq!(
+            field("details"),
+            format!(
+                "Due {} \u{b7} @alice @bob \u{b7} 3/5 \u{2713}",
+                due
+            )
+        );
+    }
+
+    #[test]
+    fn handle_card_uses_the_list_as_subtitle() {
+        let trello = Trello {
+            subtitle: TrelloSubtitle::List,
+            ..trello("print")
+        };
+        let mut tickets = Vec::new();
+        handle_card(card(), &trello, &mut tickets);
+        assert_eq!(tickets[0].subtitel, "Todo");
+    }
+
+    #[test]
+    fn handle_card_skips_cards_without_print_label() {
+        let mut tickets = Vec::new();
+        handle_card(card(), &trello("other"), &mut tickets);
+        assert!(tickets.is_empty());
+    }
+
+    #[test]
+    fn handle_card_leaves_out_missing_details() {
+        let card: Card = serde_json::from_value(json!({
+            "id": "c1",
+            "name": "Card",
+            "desc": "",
+            "url": "",
+            "labels": [{"id": "p1", "name": "print"}]
+        }))
+        .unwrap();
+        let mut tickets = Vec::new();
+        handle_card(card, &trello("print"), &mut tickets);
+        let fields = &tickets[0].fields;
+        for name in
+            &["due", "members", "checklist", "label_color"]
+        {
+            assert!(!fields.contains_key(*name), "{}", name);
+        }
+        assert_eq!(fields["details"], "");
+    }
+}
@@ -0,0 +1,406 @@
+use crate::config::{Duplex, PDfDimension, Sheet};
+
+/// Known sheet sizes in millimeter together with their cups media name
+const SIZES: &[(&str, f32, f32, &str)] = &[
+    ("a3", 297.0, 420.0, "A3"),
+    ("a4", 210.0, 297.0, "A4"),
+    ("letter", 215.9, 279.4, "Letter"),
+    ("legal", 215.9, 355.6, "Legal"),
+];
+/// Space between the cards and their cut marks
+const MARK_OFFSET: f32 = 1.0;
+/// Length of a cut mark, shorter if the margin is small
+const MARK_LENGTH: f32 = 5.0;
+
+/// A line from the first to the second point
+pub type Segment = [(f32, f32); 2];
+
+/// Positions of the cards on a page. Cards are placed in rows from
+/// the top left to the bottom right, centered on the page.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    pub width: f32,
+    pub height: f32,
+    /// Cups media of the sheet, `None` if each card is its own page
+    pub media: Option<String>,
+    pub cut_marks: bool,
+    pub fold_line: bool,
+    duplex: Duplex,
+    card_width: f32,
+    card_height: f32,
+    columns: u32,
+    rows: u32,
+    gutter: f32,
+    left: f32,
+    bottom: f32,
+}
+
+impl Grid {
+    /// One card per page in the size of the card
+    pub fn card(pdf: &PDfDimension) -> Self {
+        Self {
+            width: pdf.width,
+            height: pdf.height,
+            media: None,
+            cut_marks: false,
+            fold_line: false,
+            duplex: Duplex::default(),
+            card_width: pdf.width,
+            card_height: pdf.height,
+            columns: 1,
+            rows: 1,
+            gutter: 0.0,
+            left: 0.0,
+            bottom: 0.0,
+        }
+    }
+
+    /// As many cards per page as configured or as fit on the sheet
+    pub fn sheet(
+        sheet: &Sheet,
+        pdf: &PDfDimension,
+        duplex: Duplex,
+    ) -> Result<Self, failure::Error> {
+        let (width, height, media) = parse_size(&sheet.size)
+            .ok_or_else(|| {
+                failure::format_err!(
+                    "Unknown sheet size {}. Use a4, a3, letter, legal or <width>x<height>",
+                    sheet.size
+                )
+            })?;
+        let fit = |space: f32, card: f32| {
+            ((2.0_f32.mul_add(-sheet.margin, space)
+                + sheet.gutter)
+                / (card + sheet.gutter)
+                + 0.001)
+                .floor()
+                .max(0.0) as u32
+        };
+        let columns = sheet
+            .columns
+            .unwrap_or_else(|| fit(width, pdf.width));
+        let rows =
+            sheet.rows.unwrap_or_else(|| fit(height, pdf.height));
+        let grid_width = (columns as f32)
+            .mul_add(pdf.width + sheet.gutter, -sheet.gutter);
+        let grid_height = (rows as f32)
+            .mul_add(pdf.height + sheet.gutter, -sheet.gutter);
+        if columns == 0
+            || rows == 0
+            || grid_width
+                > 2.0_f32.mul_add(-sheet.margin, width) + 0.001
+            || grid_height
+                > 2.0_f32.mul_add(-sheet.margin, height) + 0.001
+        {
+            return Err(failure::format_err!(
+                "{} columns and {} rows of {}x{} mm cards do not fit on a {} sheet",
+                columns,
+                rows,
+                pdf.width,
+                pdf.height,
+                sheet.size
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            media: Some(media),
+            cut_marks: sheet.cut_marks,
+            fold_line: sheet.fold_line,
+            duplex,
+            card_width: pdf.width,
+            card_height: pdf.height,
+            columns,
+            rows,
+            gutter: sheet.gutter,
+            left: (width - grid_width) / 2.0,
+            bottom: (height - grid_height) / 2.0,
+        })
+    }
+
+    pub const fn cards_per_page(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// Lower left corners of the cards on a page in printing order
+    pub fn positions(
+        &self,
+    ) -> impl Iterator<Item = (f32, f32)> + '_ {
+        (0..self.rows).rev().flat_map(move |row| {
+            (0..self.columns).map(move |column| {
+                (
+                    (column as f32).mul_add(
+                        self.card_width + self.gutter,
+                        self.left,
+                    ),
+                    (row as f32).mul_add(
+                        self.card_height + self.gutter,
+                        self.bottom,
+                    ),
+                )
+            })
+        })
+    }
+
+    /// Lower left corners of the backs, mirrored so every back ends
+    /// up behind its front once the page is flipped
+    pub fn back_positions(
+        &self,
+    ) -> impl Iterator<Item = (f32, f32)> + '_ {
+        let portrait = self.height >= self.width;
+        // flipping around a vertical edge mirrors left and right
+        let mirror_x =
+            portrait == (self.duplex == Duplex::LongEdge);
+        self.positions().map(move |(x, y)| {
+            if mirror_x {
+                (self.width - x - self.card_width, y)
+            } else {
+                (x, self.height - y - self.card_height)
+            }
+        })
+    }
+
+    /// Short lines in the margin continuing every card edge
+    pub fn cut_marks(&self) -> Vec<Segment> {
+        let length = MARK_LENGTH
+            .min(self.left.min(self.bottom) - MARK_OFFSET);
+        if length <= 0.0 {
+            return Vec::new();
+        }
+        let right = self.width - self.left;
+        let top = self.height - self.bottom;
+        let mut marks = Vec::new();
+        for x in edges(
+            self.left,
+            self.columns,
+            self.card_width,
+            self.gutter,
+        ) {
+            let below = self.bottom - MARK_OFFSET;
+            let above = top + MARK_OFFSET;
+            marks.push([(x, below), (x, below - length)]);
+            marks.push([(x, above), (x, above + length)]);
+        }
+        for y in edges(
+            self.bottom,
+            self.rows,
+            self.card_height,
+            self.gutter,
+        ) {
+            let before = self.left - MARK_OFFSET;
+            let after = right + MARK_OFFSET;
+            marks.push([(before, y), (before - length, y)]);
+            marks.push([(after, y), (after + length, y)]);
+        }
+        marks
+    }
+
+    /// Line across the middle of the page. With an even number of
+    /// rows it runs between the two middle rows.
+    pub fn fold_line(&self) -> Segment {
+        let middle = self.height / 2.0;
+        [(0.0, middle), (self.width, middle)]
+    }
+}
+
+/// Start and end of every card along one axis. Touching cards share
+/// their edge.
+fn edges(
+    start: f32,
+    count: u32,
+    size: f32,
+    gutter: f32,
+) -> Vec<f32> {
+    let mut edges: Vec<f32> = (0..count)
+        .flat_map(|index| {
+            let begin =
+                (index as f32).mul_add(size + gutter, start);
+            vec![begin, begin + size]
+        })
+        .collect();
+    edges.dedup_by(|a, b| (*a - *b).abs() < 0.001);
+    edges
+}
+
+/// Returns width, height and cups media of a sheet size
+fn parse_size(size: &str) -> Option<(f32, f32, String)> {
+    let size = size.to_lowercase();
+    if let Some(&(_, width, height, media)) =
+        SIZES.iter().find(|(name, ..)| *name == size)
+    {
+        return Some((width, height, media.into()));
+    }
+    let mut parts = size.splitn(2, 'x').map(str::trim);
+    let width = parts.next()?.parse::<f32>().ok()?;
+    let height = parts.next()?.parse::<f32>().ok()?;
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    Some((
+        width,
+        height,
+        format!("Custom.{}x{}mm", width, height),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(size: &str, margin: f32, gutter: f32) -> Sheet {
+        Sheet {
+            size: size.into(),
+            columns: None,
+            rows: None,
+            margin,
+            gutter,
+            cut_marks: true,
+            fold_line: false,
+        }
+    }
+
+    fn a4_grid(duplex: Duplex) -> Grid {
+        Grid::sheet(
+            &sheet("a4", 5.0, 0.0),
+            &PDfDimension::default(),
+            duplex,
+        )
+        .unwrap()
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 0.001
+                && (actual.1 - expected.1).abs() < 0.001,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sizes_are_known_or_parsed() {
+        assert_eq!(
+            parse_size("A4"),
+            Some((210.0, 297.0, "A4".into()))
+        );
+        assert_eq!(
+            parse_size("100x150"),
+            Some((100.0, 150.0, "Custom.100x150mm".into()))
+        );
+        assert_eq!(parse_size("0x150"), None);
+        assert_eq!(parse_size("b5"), None);
+    }
+
+    #[test]
+    fn cards_fill_the_sheet_centered() {
+        let grid = a4_grid(Duplex::LongEdge);
+        assert_eq!(grid.media.as_ref().unwrap(), "A4");
+        // 2 columns of 100 mm and 4 rows of 62 mm
+        assert_eq!(grid.cards_per_page(), 8);
+        let positions: Vec<(f32, f32)> =
+            grid.positions().collect();
+        assert_eq!(positions.len(), 8);
+        // printed from the top left to the bottom right
+        assert_close(positions[0], (5.0, 210.5));
+        assert_close(positions[1], (105.0, 210.5));
+        assert_close(positions[7], (105.0, 24.5));
+    }
+
+    #[test]
+    fn gutters_separate_the_cards() {
+        let grid = Grid::sheet(
+            &sheet("a4", 5.0, 4.0),
+            &PDfDimension::default(),
+            Duplex::LongEdge,
+        )
+        .unwrap();
+        let positions: Vec<(f32, f32)> =
+            grid.positions().collect();
+        // the gutter leaves room for one column only
+        assert_eq!(grid.cards_per_page(), 4);
+        assert_close(positions[0], (55.0, 216.5));
+        assert!(
+            (positions[0].1 - positions[1].1 - 66.0).abs()
+                < 0.001
+        );
+    }
+
+    #[test]
+    fn too_many_cards_are_rejected() {
+        let mut sheet = sheet("a4", 5.0, 0.0);
+        sheet.columns = Some(3);
+        assert!(Grid::sheet(
+            &sheet,
+            &PDfDimension::default(),
+            Duplex::LongEdge
+        )
+        .is_err());
+        assert!(Grid::sheet(
+            &self::sheet("50x50", 5.0, 0.0),
+            &PDfDimension::default(),
+            Duplex::LongEdge
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn long_edge_backs_mirror_columns_on_portrait_sheets() {
+        let grid = a4_grid(Duplex::LongEdge);
+        let backs: Vec<(f32, f32)> =
+            grid.back_positions().collect();
+        assert_close(backs[0], (105.0, 210.5));
+        assert_close(backs[1], (5.0, 210.5));
+        assert_close(backs[7], (5.0, 24.5));
+    }
+
+    #[test]
+    fn short_edge_backs_mirror_rows_on_portrait_sheets() {
+        let grid = a4_grid(Duplex::ShortEdge);
+        let backs: Vec<(f32, f32)> =
+            grid.back_positions().collect();
+        assert_close(backs[0], (5.0, 24.5));
+        assert_close(backs[1], (105.0, 24.5));
+        assert_close(backs[7], (105.0, 210.5));
+    }
+
+    #[test]
+    fn long_edge_backs_mirror_rows_on_landscape_sheets() {
+        let grid = Grid::sheet(
+            &sheet("297x210", 5.0, 0.0),
+            &PDfDimension::default(),
+            Duplex::LongEdge,
+        )
+        .unwrap();
+        let fronts: Vec<(f32, f32)> = grid.positions().collect();
+        let backs: Vec<(f32, f32)> =
+            grid.back_positions().collect();
+        for (front, back) in fronts.iter().zip(&backs) {
+            assert_close(
+                *back,
+                (front.0, grid.height - front.1 - 62.0),
+            );
+        }
+    }
+
+    #[test]
+    fn cut_marks_continue_every_card_edge() {
+        let grid = a4_grid(Duplex::LongEdge);
+        let marks = grid.cut_marks();
+        // 3 vertical and 5 horizontal edges, marked on both sides
+        assert_eq!(marks.len(), 2 * 3 + 2 * 5);
+        // the 5 mm margin leaves 4 mm next to the 1 mm offset
+        assert_eq!(marks[0], [(5.0, 23.5), (5.0, 19.5)]);
+        assert_eq!(marks[1], [(5.0, 273.5), (5.0, 277.5)]);
+        assert_eq!(marks[6], [(4.0, 24.5), (0.0, 24.5)]);
+    }
+
+    #[test]
+    fn edges_are_shared_without_gutter() {
+        assert_eq!(edges(0.0, 2, 10.0, 0.0), [0.0, 10.0, 20.0]);
+        assert_eq!(
+            edges(0.0, 2, 10.0, 2.0),
+            [0.0, 10.0, 12.0, 22.0]
+        );
+    }
+}